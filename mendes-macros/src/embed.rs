@@ -0,0 +1,166 @@
+#[cfg(any(feature = "brotli", feature = "gzip"))]
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::{LitByteStr, LitStr, Token};
+
+pub struct EmbedArgs {
+    dir: LitStr,
+}
+
+impl Parse for EmbedArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let dir = input.parse()?;
+        if !input.is_empty() {
+            input.parse::<Token![,]>()?;
+        }
+
+        Ok(Self { dir })
+    }
+}
+
+pub fn embed(args: &EmbedArgs) -> TokenStream {
+    let manifest_dir = env::var_os("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let root = PathBuf::from(manifest_dir).join(args.dir.value());
+    match files(&root) {
+        Ok(files) => quote!(::mendes::utils::Embedded::new(&[#(#files),*])),
+        Err(error) => {
+            let msg = format!("unable to embed {}: {error}", root.display());
+            syn::Error::new(args.dir.span(), msg).to_compile_error()
+        }
+    }
+}
+
+/// Generate `EmbeddedFile` expressions for the files in `root`, sorted by path
+fn files(root: &Path) -> io::Result<Vec<TokenStream>> {
+    let mut paths = Vec::new();
+    collect(root, "", &mut paths)?;
+    paths.sort();
+
+    let mut files = Vec::with_capacity(paths.len());
+    for (relative, path) in paths {
+        let data = fs::read(&path)?;
+        let content_type = content_type(&relative);
+        let hash = hash(&data);
+        let etag = format!("\"{hash:016x}\"");
+        let brotli = variant(brotli(&data)?, data.len());
+        let gzip = variant(gzip(&data)?, data.len());
+
+        // Including the file (rather than a literal) makes cargo rebuild when it changes
+        let path = path.to_str().ok_or_else(|| non_utf8(&path))?;
+        files.push(quote!(::mendes::utils::EmbeddedFile {
+            path: #relative,
+            content_type: #content_type,
+            etag: #etag,
+            data: include_bytes!(#path),
+            brotli: #brotli,
+            gzip: #gzip,
+        }));
+    }
+
+    Ok(files)
+}
+
+/// Recursively collect the (non-hidden) files in `dir`, with their path relative to the root
+fn collect(dir: &Path, prefix: &str, paths: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_str().ok_or_else(|| non_utf8(&path))?;
+        if name.starts_with('.') {
+            continue;
+        }
+
+        let relative = match prefix {
+            "" => name.to_owned(),
+            prefix => format!("{prefix}/{name}"),
+        };
+
+        if fs::metadata(&path)?.is_dir() {
+            collect(&path, &relative, paths)?;
+        } else {
+            paths.push((relative, path));
+        }
+    }
+
+    Ok(())
+}
+
+/// The `Content-Type` for the file at `path`, matching what `mendes::utils::file()` sends
+fn content_type(path: &str) -> String {
+    let mime = match mime_guess::from_path(path).first() {
+        Some(mime) => mime,
+        None => return "application/octet-stream".to_owned(),
+    };
+
+    let text = match (mime.type_(), mime.subtype()) {
+        (mime_guess::mime::TEXT, _) => true,
+        (mime_guess::mime::APPLICATION, subtype) => {
+            matches!(subtype.as_str(), "javascript" | "json" | "xml")
+        }
+        (mime_guess::mime::IMAGE, subtype) => subtype == "svg",
+        _ => false,
+    };
+
+    match text && mime.get_param(mime_guess::mime::CHARSET).is_none() {
+        true => format!("{mime}; charset=utf-8"),
+        false => mime.to_string(),
+    }
+}
+
+/// Only keep compressed variants that are actually smaller
+fn variant(compressed: Option<Vec<u8>>, len: usize) -> TokenStream {
+    match compressed {
+        Some(compressed) if compressed.len() < len => {
+            let lit = LitByteStr::new(&compressed, Span::call_site());
+            quote!(Some(#lit))
+        }
+        _ => quote!(None),
+    }
+}
+
+#[cfg(feature = "brotli")]
+fn brotli(data: &[u8]) -> io::Result<Option<Vec<u8>>> {
+    let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 11, 22);
+    encoder.write_all(data)?;
+    Ok(Some(encoder.into_inner()))
+}
+
+#[cfg(not(feature = "brotli"))]
+fn brotli(_: &[u8]) -> io::Result<Option<Vec<u8>>> {
+    Ok(None)
+}
+
+#[cfg(feature = "gzip")]
+fn gzip(data: &[u8]) -> io::Result<Option<Vec<u8>>> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(data)?;
+    encoder.finish().map(Some)
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gzip(_: &[u8]) -> io::Result<Option<Vec<u8>>> {
+    Ok(None)
+}
+
+/// The 64-bit FNV-1a hash of `data`
+///
+/// Unlike `DefaultHasher`, this is the same for every build, so entity tags stay valid.
+fn hash(data: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for &byte in data {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+fn non_utf8(path: &Path) -> io::Error {
+    let msg = format!("non-UTF-8 path {}", path.display());
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
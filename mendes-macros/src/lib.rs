@@ -5,6 +5,8 @@ use quote::{quote, ToTokens};
 use syn::parse_macro_input;
 
 mod cookies;
+#[cfg(feature = "embed")]
+mod embed;
 mod forms;
 mod route;
 
@@ -100,6 +102,26 @@ pub fn route(item: TokenStream) -> TokenStream {
     quote!(#ast).into()
 }
 
+/// Embed the files in a directory into the binary
+///
+/// The path is relative to the crate's manifest directory. Hidden files (with a name
+/// starting with `.`) are skipped. Evaluates to a `mendes::utils::Embedded`, which has
+/// the MIME type and a strong `ETag` for each file. With the `embed-brotli` and
+/// `embed-gzip` features of mendes, compressed variants are generated as well.
+///
+/// ```ignore
+/// static ASSETS: Embedded = embed!("assets");
+/// ```
+///
+/// Changes to the embedded files cause the crate to be rebuilt, but added files are
+/// only picked up when it's rebuilt for another reason.
+#[cfg(feature = "embed")]
+#[proc_macro]
+pub fn embed(item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(item as embed::EmbedArgs);
+    embed::embed(&args).into()
+}
+
 #[proc_macro_derive(ToField, attributes(option))]
 pub fn derive_to_field(item: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(item as syn::DeriveInput);
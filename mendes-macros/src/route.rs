@@ -235,14 +235,33 @@ pub fn route(ast: &mut syn::ExprMatch) {
         match &mut *arm.body {
             syn::Expr::Path(path) => {
                 let rewind = rewind.then(|| quote!(#cx.rewind();));
+                let name = path
+                    .path
+                    .segments
+                    .iter()
+                    .map(|segment| segment.ident.to_string())
+                    .collect::<Vec<_>>()
+                    .join("::");
                 *arm.body = parse_quote!({
                     #rewind
+                    #cx.req.extensions.insert(::mendes::application::Route(#name));
                     let rsp = #path::handler(#cx.as_mut()).await;
-                    ::mendes::application::IntoResponse::into_response(rsp, &*#cx.app, &cx.req)
+                    let mut rsp = ::mendes::application::IntoResponse::into_response(rsp, &*#cx.app, &cx.req);
+                    if rsp.extensions().get::<::mendes::application::Route>().is_none() {
+                        rsp.extensions_mut().insert(::mendes::application::Route(#name));
+                    }
+                    rsp
                 });
             }
             syn::Expr::Match(inner) => route(inner),
-            _ => panic!("only identifiers, paths and match expressions allowed"),
+            target => {
+                let rewind = rewind.then(|| quote!(#cx.rewind();));
+                *arm.body = parse_quote!({
+                    #rewind
+                    let target = #target;
+                    ::mendes::application::Mount::mount(target, #cx.as_mut()).await
+                });
+            }
         }
     }
 
@@ -1,12 +1,25 @@
+use std::any::Any;
 use std::borrow::Cow;
 #[cfg(feature = "body-util")]
 use std::error::Error as StdError;
+#[cfg(any(feature = "hyper", feature = "tower"))]
+use std::future::poll_fn;
+#[cfg(any(feature = "hyper", feature = "tower"))]
+use std::panic::{self, AssertUnwindSafe};
 use std::str::FromStr;
 use std::sync::Arc;
+#[cfg(any(feature = "hyper", feature = "tower"))]
+use std::sync::Mutex;
+#[cfg(any(feature = "hyper", feature = "tower"))]
+use std::task::Poll;
 
 use async_trait::async_trait;
 #[cfg(feature = "body-util")]
 use bytes::Bytes;
+#[cfg(feature = "body-util")]
+use http::header::CONTENT_ENCODING;
+#[cfg(any(feature = "hyper", feature = "tower", feature = "testing"))]
+use http::header::EXPECT;
 use http::header::LOCATION;
 use http::request::Parts;
 use http::Request;
@@ -15,6 +28,17 @@ use http_body::Body as HttpBody;
 use percent_encoding::percent_decode_str;
 use thiserror::Error;
 
+#[cfg(all(
+    feature = "body-util",
+    any(
+        feature = "brotli",
+        feature = "gzip",
+        feature = "zlib",
+        feature = "zstd"
+    )
+))]
+use crate::body::Encoding;
+
 pub use mendes_macros::{handler, route, scope};
 
 /// Main interface for an application or service
@@ -60,39 +84,88 @@ pub trait Application: Send + Sized {
         <Self::RequestBody as HttpBody>::Data: Send,
         <Self::RequestBody as HttpBody>::Error: Into<Box<dyn StdError + Sync + Send>>,
     {
-        // Check if the Content-Length header suggests the body is larger than our max len
-        // to avoid allocation if we drop the request in any case.
-        let expected_len = match body.size_hint().upper() {
-            Some(length) => length,
-            None => body.size_hint().lower(),
-        };
+        // Reject unsupported content types and encodings before reading (and, for requests
+        // that carry `Expect: 100-continue`, before the client is asked to send) the body.
+        BodyType::from_request(req)?;
+        content_encoding(req)?;
 
-        if expected_len > max_len as u64 {
-            return Err(Error::BodyTooLarge);
-        }
+        check_size_hint(&body, max_len)?;
 
         from_body::<Self::RequestBody, T>(req, body, max_len).await
     }
 
+    /// Receive the request body as is, without decoding its `Content-Encoding`
+    ///
+    /// Fails with `Error::BodyTooLarge` if the body is larger than `max_len` bytes. This
+    /// can't see the request's `Content-Encoding`, so it returns compressed bodies as is;
+    /// use `body_bytes_decoded()` instead.
     #[cfg(feature = "body-util")]
     #[cfg_attr(docsrs, doc(cfg(feature = "body-util")))]
+    #[deprecated(note = "use `body_bytes_decoded()`, which handles `Content-Encoding`")]
     async fn body_bytes<B: HttpBody + Send>(body: B, max_len: usize) -> Result<Bytes, Error>
     where
         B::Data: Send,
         B::Error: Into<Box<dyn StdError + Sync + Send + 'static>>,
     {
-        // Check if the Content-Length header suggests the body is larger than our max len
-        // to avoid allocation if we drop the request in any case.
-        let expected_len = match body.size_hint().upper() {
-            Some(length) => length,
-            None => body.size_hint().lower(),
-        };
+        check_size_hint(&body, max_len)?;
 
-        if expected_len > max_len as u64 {
-            return Err(Error::BodyTooLarge);
-        }
+        Ok(collect(body, max_len).await?)
+    }
+
+    /// Receive the request body, decompressing it according to its `Content-Encoding`
+    ///
+    /// Fails with `Error::BodyTooLarge` if the body is larger than `max_len` bytes, before
+    /// or after decompression. To protect against decompression bombs, bodies that expand
+    /// to more than 64 KiB are also rejected if they decompress to more than 100 times their
+    /// compressed size. Bodies with an unsupported encoding are rejected before receiving
+    /// them with `Error::BodyUnknownEncoding`.
+    #[cfg(feature = "body-util")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "body-util")))]
+    async fn body_bytes_decoded<B: HttpBody + Send>(
+        req: &Parts,
+        body: B,
+        max_len: usize,
+    ) -> Result<Bytes, Error>
+    where
+        B::Data: Send,
+        B::Error: Into<Box<dyn StdError + Sync + Send + 'static>>,
+    {
+        content_encoding(req)?;
 
-        Ok(to_bytes(body, max_len).await?)
+        check_size_hint(&body, max_len)?;
+
+        Ok(to_bytes(req, body, max_len).await?)
+    }
+
+    /// Decide whether to accept a request before the client sends its body
+    ///
+    /// For requests with an `Expect: 100-continue` header, the server calls this before
+    /// routing the request. If it returns an error, the error's response is sent as the
+    /// final response, and the client won't send the body. Use this to reject uploads
+    /// based on the request head, like missing credentials or a `Content-Length` that
+    /// exceeds a global limit.
+    ///
+    /// The server only asks the client to send the body once a handler starts reading it,
+    /// so rejecting the request in a handler (or extractor) before reading the body works
+    /// too; `from_body()` and `body_bytes_decoded()` check the content type
+    /// and length first.
+    #[allow(unused_variables)]
+    fn expect_continue(&self, req: &Parts) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Build the response for a request whose handler panicked
+    ///
+    /// Gets the panic payload and the request's head, including the `Route` extension if
+    /// `route!` had dispatched to a handler. If this returns `None` (the default), the server
+    /// responds with a plain text `500 Internal Server Error`.
+    #[allow(unused_variables)]
+    fn panic_response(
+        &self,
+        panic: &(dyn Any + Send),
+        req: &Parts,
+    ) -> Option<Response<Self::ResponseBody>> {
+        None
     }
 
     fn redirect(status: StatusCode, path: impl AsRef<str>) -> Response<Self::ResponseBody>
@@ -158,6 +231,9 @@ where
     pub body: Option<A::RequestBody>,
     #[doc(hidden)]
     pub path: PathState,
+    /// Receives the request head if the `Context` is dropped while unwinding from a panic
+    #[cfg(any(feature = "hyper", feature = "tower"))]
+    unwind: Option<Arc<Mutex<Option<Parts>>>>,
 }
 
 impl<A> Context<A>
@@ -167,13 +243,18 @@ where
     // This should only be used by procedural routing macros.
     #[doc(hidden)]
     pub fn new(app: Arc<A>, req: Request<A::RequestBody>) -> Context<A> {
-        let path = PathState::new(req.uri().path());
         let (req, body) = req.into_parts();
+        Self::from_parts(app, req, body)
+    }
+
+    fn from_parts(app: Arc<A>, req: Parts, body: A::RequestBody) -> Context<A> {
         Context {
             app,
+            path: PathState::new(req.uri.path()),
             req,
             body: Some(body),
-            path,
+            #[cfg(any(feature = "hyper", feature = "tower"))]
+            unwind: None,
         }
     }
 
@@ -220,12 +301,151 @@ where
     }
 }
 
+#[cfg(any(feature = "hyper", feature = "tower"))]
+impl<A: Application> Drop for Context<A> {
+    fn drop(&mut self) {
+        // Hand the request head to `handle_unwind()` if the handler panicked
+        let Some(unwind) = self.unwind.take() else {
+            return;
+        };
+
+        if std::thread::panicking() {
+            if let Ok(mut head) = unwind.lock() {
+                *head = Some(std::mem::replace(
+                    &mut self.req,
+                    Request::new(()).into_parts().0,
+                ));
+            }
+        }
+    }
+}
+
 impl<A: Application> AsMut<Context<A>> for Context<A> {
     fn as_mut(&mut self) -> &mut Context<A> {
         self
     }
 }
 
+/// A request handler for a subtree of paths, for use as a `route!` target
+///
+/// If the target of a `route!` arm is an expression other than a handler path or a nested
+/// `match`, it should evaluate to a `Mount`. The `Mount` gets the `Context`, in which the
+/// path components matched so far have been consumed. With the `tower` feature, this is
+/// implemented for tower services, which see a request for the remaining path:
+///
+/// ```ignore
+/// route!(match cx.path() {
+///     Some("grpc") => cx.app.grpc.clone(),
+///     _ => index,
+/// })
+/// ```
+#[async_trait]
+pub trait Mount<A: Application>: Send + Sized {
+    async fn mount(self, cx: &mut Context<A>) -> Response<A::ResponseBody>;
+}
+
+/// Handle `req`, turning a panic in the request handler into a response
+///
+/// See `Application::panic_response()` for how the response is generated.
+#[cfg(any(feature = "hyper", feature = "tower"))]
+pub(crate) async fn handle_unwind<A: Application>(
+    app: Arc<A>,
+    req: Request<A::RequestBody>,
+) -> Response<A::ResponseBody>
+where
+    A::ResponseBody: From<&'static str>,
+{
+    let (parts, body) = req.into_parts();
+    if let Some(rsp) = expect_continue(&*app, &parts) {
+        return rsp;
+    }
+
+    // The `Context` gives the request head back if it is dropped by a panic
+    let unwind = Arc::new(Mutex::new(None));
+    let mut cx = Context::from_parts(app.clone(), parts, body);
+    cx.unwind = Some(unwind.clone());
+
+    let mut handle = A::handle(cx);
+    let result =
+        poll_fn(
+            |cx| match panic::catch_unwind(AssertUnwindSafe(|| handle.as_mut().poll(cx))) {
+                Ok(Poll::Ready(rsp)) => Poll::Ready(Ok(rsp)),
+                Ok(Poll::Pending) => Poll::Pending,
+                Err(panic) => Poll::Ready(Err(panic)),
+            },
+        )
+        .await;
+
+    let panic = match result {
+        Ok(rsp) => return rsp,
+        Err(panic) => panic,
+    };
+
+    // Drop the handler future in case it still holds the `Context`
+    drop(handle);
+    let req = unwind.lock().ok().and_then(|mut head| head.take());
+    panic_response(&*app, panic, req.as_ref())
+}
+
+/// Check requests with `Expect: 100-continue` through `Application::expect_continue()`
+///
+/// Returns the response to send if the `Application` rejects the request.
+#[cfg(any(feature = "hyper", feature = "tower", feature = "testing"))]
+pub(crate) fn expect_continue<A: Application>(
+    app: &A,
+    req: &Parts,
+) -> Option<Response<A::ResponseBody>> {
+    let expect = req.headers.get(EXPECT)?;
+    if !expect.as_bytes().eq_ignore_ascii_case(b"100-continue") {
+        return None;
+    }
+
+    match app.expect_continue(req) {
+        Ok(()) => None,
+        Err(error) => Some(error.into_response(app, req)),
+    }
+}
+
+#[cfg(any(feature = "hyper", feature = "tower"))]
+fn panic_response<A: Application>(
+    app: &A,
+    panic: Box<dyn Any + Send + 'static>,
+    req: Option<&Parts>,
+) -> Response<A::ResponseBody>
+where
+    A::ResponseBody: From<&'static str>,
+{
+    #[cfg(feature = "tracing")]
+    {
+        let panic_str = if let Some(s) = panic.downcast_ref::<String>() {
+            Some(s.as_str())
+        } else if let Some(s) = panic.downcast_ref::<&'static str>() {
+            Some(*s)
+        } else {
+            Some("no error")
+        };
+
+        tracing::error!(
+            method = req.map(|req| tracing::field::display(&req.method)),
+            path = req.map(|req| req.uri.path()),
+            route = req
+                .and_then(|req| req.extensions.get::<Route>())
+                .map(|route| route.0),
+            "caught panic from request handler: {:?}",
+            panic_str
+        );
+    }
+
+    if let Some(rsp) = req.and_then(|req| app.panic_response(&*panic, req)) {
+        return rsp;
+    }
+
+    Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body("Caught panic".into())
+        .unwrap()
+}
+
 pub trait FromContext<'a, A>: Sized
 where
     A: Application,
@@ -412,30 +632,55 @@ from_context_from_str!(usize);
 
 macro_rules! deserialize_body {
     ($req:ident, $bytes:ident) => {{
-        let content_type = $req.headers.get("content-type").ok_or(Error::BodyNoType)?;
+        match BodyType::from_request($req)? {
+            BodyType::Form => {
+                serde_urlencoded::from_bytes::<T>(&$bytes).map_err(Error::BodyDecodeForm)
+            }
+            #[cfg(feature = "json")]
+            BodyType::Json => serde_json::from_slice::<T>(&$bytes).map_err(Error::BodyDecodeJson),
+            #[cfg(feature = "uploads")]
+            BodyType::Multipart => crate::forms::from_form_data::<T>(&$req.headers, &$bytes)
+                .map_err(Error::BodyDecodeMultipart),
+        }
+    }};
+}
+
+/// The request body formats that can be deserialized
+enum BodyType {
+    Form,
+    #[cfg(feature = "json")]
+    Json,
+    #[cfg(feature = "uploads")]
+    Multipart,
+}
+
+impl BodyType {
+    fn from_request(req: &Parts) -> Result<Self, Error> {
+        let content_type = req.headers.get("content-type").ok_or(Error::BodyNoType)?;
         let ct_str = content_type.to_str().map_err(|_| {
             Error::BodyUnknownType(String::from_utf8_lossy(content_type.as_bytes()).into_owned())
         })?;
 
         let mut parts = ct_str.splitn(2, ';');
         match parts.next().map(|s| s.trim()) {
-            Some("application/x-www-form-urlencoded") => {
-                serde_urlencoded::from_bytes::<T>(&$bytes).map_err(Error::BodyDecodeForm)
-            }
+            Some("application/x-www-form-urlencoded") => Ok(Self::Form),
             #[cfg(feature = "json")]
-            Some("application/json") => {
-                serde_json::from_slice::<T>(&$bytes).map_err(Error::BodyDecodeJson)
-            }
+            Some("application/json") => Ok(Self::Json),
             #[cfg(feature = "uploads")]
-            Some("multipart/form-data") => {
-                crate::forms::from_form_data::<T>(&$req.headers, &$bytes)
-                    .map_err(Error::BodyDecodeMultipart)
-            }
+            Some("multipart/form-data") => Ok(Self::Multipart),
             Some(_) | None => Err(Error::BodyUnknownType(ct_str.to_owned())),
         }
-    }};
+    }
 }
 
+/// The handler that produced a response
+///
+/// `route!` adds this to the extensions of responses from the handlers it dispatches to,
+/// unless a nested `route!` (for example, in a scope) already did. It also adds it to the
+/// extensions of the request head before calling the handler.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Route(pub &'static str);
+
 #[doc(hidden)]
 pub struct Rest<T>(pub T);
 
@@ -493,7 +738,7 @@ where
     B: HttpBody,
     B::Error: Into<Box<dyn StdError + Send + Sync + 'static>>,
 {
-    let bytes = to_bytes(body, max_len).await?;
+    let bytes = to_bytes(req, body, max_len).await?;
     deserialize_body!(req, bytes)
 }
 
@@ -506,21 +751,113 @@ fn from_bytes<'de, T: serde::de::Deserialize<'de>>(
 
 #[cfg(feature = "body-util")]
 #[cfg_attr(docsrs, doc(cfg(feature = "body-util")))]
-#[cfg_attr(feature = "tracing", tracing::instrument(skip(body)))]
-async fn to_bytes<B: HttpBody>(body: B, max_len: usize) -> Result<Bytes, Error>
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(req, body)))]
+async fn to_bytes<B: HttpBody>(req: &Parts, body: B, max_len: usize) -> Result<Bytes, Error>
+where
+    B::Error: Into<Box<dyn StdError + Send + Sync + 'static>>,
+{
+    match content_encoding(req)? {
+        #[cfg(any(
+            feature = "brotli",
+            feature = "gzip",
+            feature = "zlib",
+            feature = "zstd"
+        ))]
+        Some(encoding) => return crate::body::decompress(encoding, body, max_len).await,
+        #[cfg(not(any(
+            feature = "brotli",
+            feature = "gzip",
+            feature = "zlib",
+            feature = "zstd"
+        )))]
+        Some(encoding) => match encoding {},
+        None => {}
+    }
+
+    collect(body, max_len).await
+}
+
+/// Check if the Content-Length header suggests the body is larger than our max len
+///
+/// This avoids allocation if we drop the request in any case.
+#[cfg(feature = "body-util")]
+fn check_size_hint<B: HttpBody>(body: &B, max_len: usize) -> Result<(), Error> {
+    let expected_len = match body.size_hint().upper() {
+        Some(length) => length,
+        None => body.size_hint().lower(),
+    };
+
+    match expected_len > max_len as u64 {
+        true => Err(Error::BodyTooLarge),
+        false => Ok(()),
+    }
+}
+
+#[cfg(feature = "body-util")]
+async fn collect<B: HttpBody>(body: B, max_len: usize) -> Result<Bytes, Error>
 where
     B::Error: Into<Box<dyn StdError + Send + Sync + 'static>>,
 {
-    #[cfg(feature = "body-util")]
     use http_body_util::BodyExt;
 
     let limited = http_body_util::Limited::new(body, max_len);
     match limited.collect().await {
         Ok(collected) => Ok(collected.to_bytes()),
+        Err(err) if err.is::<http_body_util::LengthLimitError>() => Err(Error::BodyTooLarge),
         Err(err) => Err(Error::BodyReceive(err)),
     }
 }
 
+/// The content coding applied to the request body, if it's not `identity`
+///
+/// Fails if the request uses a coding that isn't supported, or more than one coding.
+#[cfg(feature = "body-util")]
+fn content_encoding(req: &Parts) -> Result<Option<Encoding>, Error> {
+    let mut codings = Vec::new();
+    for value in req.headers.get_all(CONTENT_ENCODING) {
+        let value = value.to_str().map_err(|_| {
+            Error::BodyUnknownEncoding(String::from_utf8_lossy(value.as_bytes()).into_owned())
+        })?;
+
+        codings.extend(
+            value
+                .split(',')
+                .map(|coding| coding.trim())
+                .filter(|coding| !coding.is_empty() && !coding.eq_ignore_ascii_case("identity")),
+        );
+    }
+
+    let coding = match codings.as_slice() {
+        [] => return Ok(None),
+        [coding] => *coding,
+        _ => return Err(Error::BodyUnknownEncoding(codings.join(", "))),
+    };
+
+    #[cfg(any(
+        feature = "brotli",
+        feature = "gzip",
+        feature = "zlib",
+        feature = "zstd"
+    ))]
+    if let Ok(encoding) = Encoding::from_str(coding) {
+        return Ok(Some(encoding));
+    }
+
+    Err(Error::BodyUnknownEncoding(coding.to_owned()))
+}
+
+/// Without compression support, request bodies can't have a content coding
+#[cfg(all(
+    feature = "body-util",
+    not(any(
+        feature = "brotli",
+        feature = "gzip",
+        feature = "zlib",
+        feature = "zstd"
+    ))
+))]
+enum Encoding {}
+
 // This should only be used by procedural routing macros.
 #[doc(hidden)]
 pub struct PathState {
@@ -607,6 +944,31 @@ pub enum Error {
     #[cfg(feature = "body-util")]
     #[error("request body too large")]
     BodyTooLarge,
+    #[cfg(feature = "body-util")]
+    #[error("unsupported content encoding on request body: {0}")]
+    BodyUnknownEncoding(String),
+    #[cfg(all(
+        feature = "body-util",
+        any(
+            feature = "brotli",
+            feature = "gzip",
+            feature = "zlib",
+            feature = "zstd"
+        )
+    ))]
+    #[error("unable to decompress request body: {0}")]
+    BodyDecompress(std::io::Error),
+    #[cfg(all(
+        feature = "body-util",
+        any(
+            feature = "brotli",
+            feature = "gzip",
+            feature = "zlib",
+            feature = "zstd"
+        )
+    ))]
+    #[error("request body compression ratio too high")]
+    BodyCompressionRatio,
     #[cfg(feature = "json")]
     #[error("unable to decode body as JSON: {0}")]
     BodyDecodeJson(#[from] serde_json::Error),
@@ -622,6 +984,24 @@ pub enum Error {
     #[cfg(feature = "static")]
     #[error("file not found")]
     FileNotFound,
+    #[cfg(feature = "static")]
+    #[error("access to file forbidden")]
+    FileForbidden,
+    #[cfg(feature = "static")]
+    #[error("unable to read file: {0}")]
+    FileRead(std::io::Error),
+    #[cfg(feature = "static")]
+    #[error("invalid path for static file")]
+    FileInvalidPath,
+    #[cfg(feature = "tower")]
+    #[error("request body already taken")]
+    BodyTaken,
+    #[cfg(feature = "tower")]
+    #[error("mounted service failed: {0}")]
+    MountedService(Box<dyn std::error::Error + Send + Sync + 'static>),
+    #[cfg(feature = "hyper")]
+    #[error("request extension missing: {0}")]
+    ExtensionMissing(&'static str),
 }
 
 impl From<&Error> for StatusCode {
@@ -635,7 +1015,29 @@ impl From<&Error> for StatusCode {
             #[cfg(feature = "body-util")]
             BodyReceive(_) => StatusCode::INTERNAL_SERVER_ERROR,
             #[cfg(feature = "body-util")]
-            BodyTooLarge => StatusCode::BAD_REQUEST,
+            BodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            #[cfg(feature = "body-util")]
+            BodyUnknownEncoding(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            #[cfg(all(
+                feature = "body-util",
+                any(
+                    feature = "brotli",
+                    feature = "gzip",
+                    feature = "zlib",
+                    feature = "zstd"
+                )
+            ))]
+            BodyDecompress(_) => StatusCode::BAD_REQUEST,
+            #[cfg(all(
+                feature = "body-util",
+                any(
+                    feature = "brotli",
+                    feature = "gzip",
+                    feature = "zlib",
+                    feature = "zstd"
+                )
+            ))]
+            BodyCompressionRatio => StatusCode::PAYLOAD_TOO_LARGE,
             BodyDecodeForm(_) => StatusCode::UNPROCESSABLE_ENTITY,
             #[cfg(feature = "json")]
             BodyDecodeJson(_) => StatusCode::UNPROCESSABLE_ENTITY,
@@ -643,6 +1045,18 @@ impl From<&Error> for StatusCode {
             BodyDecodeMultipart(_) => StatusCode::UNPROCESSABLE_ENTITY,
             #[cfg(feature = "static")]
             FileNotFound => StatusCode::NOT_FOUND,
+            #[cfg(feature = "static")]
+            FileForbidden => StatusCode::FORBIDDEN,
+            #[cfg(feature = "static")]
+            FileRead(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            #[cfg(feature = "static")]
+            FileInvalidPath => StatusCode::NOT_FOUND,
+            #[cfg(feature = "tower")]
+            BodyTaken => StatusCode::INTERNAL_SERVER_ERROR,
+            #[cfg(feature = "tower")]
+            MountedService(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            #[cfg(feature = "hyper")]
+            ExtensionMissing(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
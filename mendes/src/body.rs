@@ -1,73 +1,146 @@
+use std::error::Error as StdError;
 use std::future::Future;
 use std::pin::Pin;
-use std::str::FromStr;
 use std::sync::Arc;
 use std::task::ready;
 use std::task::Poll;
 use std::{io, mem, str};
 
-#[cfg(feature = "brotli")]
-use async_compression::tokio::bufread::BrotliEncoder;
-#[cfg(feature = "gzip")]
-use async_compression::tokio::bufread::GzipEncoder;
-#[cfg(feature = "zlib")]
-use async_compression::tokio::bufread::ZlibEncoder;
-use bytes::{Buf, Bytes, BytesMut};
-#[cfg(any(feature = "brotli", feature = "gzip", feature = "zlib"))]
-use http::header::{ACCEPT_ENCODING, CONTENT_ENCODING};
+use bytes::{Buf, Bytes};
 use http::request::Parts;
-#[cfg(any(feature = "brotli", feature = "gzip", feature = "zlib"))]
-use http::HeaderMap;
-#[cfg(any(feature = "brotli", feature = "gzip", feature = "zlib"))]
-use http::{request, HeaderValue, Response};
-use http_body::{Frame, SizeHint};
+use http_body::{Body as _, Frame, SizeHint};
 use pin_project::pin_project;
-#[cfg(any(feature = "brotli", feature = "gzip", feature = "zlib"))]
-use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
-#[cfg(any(feature = "brotli", feature = "gzip", feature = "zlib"))]
-use tokio_util::io::poll_read_buf;
 
 use crate::application::{Application, FromContext, PathState};
 
-#[pin_project]
+#[cfg(any(
+    feature = "brotli",
+    feature = "gzip",
+    feature = "zlib",
+    feature = "zstd"
+))]
+mod compression;
+#[cfg(all(
+    feature = "body-util",
+    any(
+        feature = "brotli",
+        feature = "gzip",
+        feature = "zlib",
+        feature = "zstd"
+    )
+))]
+pub(crate) use compression::decompress;
+#[cfg(any(
+    feature = "brotli",
+    feature = "gzip",
+    feature = "zlib",
+    feature = "zstd"
+))]
+pub use compression::{CompressionPolicy, EncodeResponse, Encoding, Level, NoCompression};
+mod conditional;
+pub use conditional::ConditionalResponse;
+#[cfg(feature = "static")]
+mod file;
+mod range;
+pub use range::RangeResponse;
+#[cfg(feature = "sse")]
+mod sse;
+#[cfg(feature = "sse")]
+pub use sse::{Event, LastEventId, Sse};
+
 pub struct Body {
-    #[pin]
     inner: InnerBody,
-    full_size: u64,
+    #[cfg(any(
+        feature = "brotli",
+        feature = "gzip",
+        feature = "zlib",
+        feature = "zstd"
+    ))]
+    encoder: Option<Box<compression::Encoder>>,
     done: bool,
 }
 
 impl Body {
     pub fn empty() -> Self {
-        Self {
-            inner: InnerBody::Bytes(Bytes::new()),
-            full_size: 0,
-            done: true,
-        }
+        Self::new(InnerBody::Bytes(Bytes::new()), true)
     }
 
     pub fn lazy(future: impl Future<Output = io::Result<Bytes>> + Send + 'static) -> Self {
-        Self {
-            inner: InnerBody::Lazy {
-                future: Box::pin(future),
-                encoding: Encoding::Identity,
-            },
-            full_size: 0,
-            done: false,
-        }
+        Self::new(InnerBody::Lazy(Box::pin(future)), false)
     }
 
     pub fn stream(
         stream: impl http_body::Body<Data = Bytes, Error = io::Error> + Send + 'static,
     ) -> Self {
+        Self::new(InnerBody::Streaming(Box::pin(stream)), false)
+    }
+
+    /// Wrap another HTTP body, like a response body from a tower service
+    pub fn wrap<B>(body: B) -> Self
+    where
+        B: http_body::Body + Send + 'static,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    {
+        Self::stream(Wrapped { inner: body })
+    }
+
+    /// Stream the contents of `file`, which is `len` bytes long
+    #[cfg(feature = "static")]
+    pub(crate) fn file(file: tokio::fs::File, len: u64) -> Self {
+        Self::new(InnerBody::File(file::FileBody::new(file, len)), false)
+    }
+
+    fn new(inner: InnerBody, done: bool) -> Self {
         Self {
-            inner: InnerBody::Streaming(Box::pin(stream)),
-            full_size: 0,
-            done: false,
+            inner,
+            #[cfg(any(
+                feature = "brotli",
+                feature = "gzip",
+                feature = "zlib",
+                feature = "zstd"
+            ))]
+            encoder: None,
+            done,
         }
     }
 }
 
+#[pin_project]
+struct Wrapped<B> {
+    #[pin]
+    inner: B,
+}
+
+impl<B> http_body::Body for Wrapped<B>
+where
+    B: http_body::Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Poll::Ready(match ready!(self.project().inner.poll_frame(cx)) {
+            Some(Ok(frame)) => Some(Ok(
+                frame.map_data(|mut data| data.copy_to_bytes(data.remaining()))
+            )),
+            Some(Err(error)) => Some(Err(io::Error::other(error))),
+            None => None,
+        })
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
 impl<'a, A: Application<RequestBody = Body>> FromContext<'a, A> for Body {
     fn from_context(
         _: &'a Arc<A>,
@@ -86,132 +159,89 @@ impl http_body::Body for Body {
     type Data = Bytes;
     type Error = io::Error;
 
-    #[allow(unused_variables)] // Depends on features
     fn poll_frame(
         self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
-        let this = self.project();
-        if *this.done {
-            return Poll::Ready(None);
-        }
-
-        #[allow(unused_mut)] // Depends on features
-        let mut buf = BytesMut::new();
-        let result = match this.inner.project() {
-            #[cfg(feature = "brotli")]
-            PinnedBody::Brotli(encoder) => poll_read_buf(encoder, cx, &mut buf),
-            #[cfg(feature = "gzip")]
-            PinnedBody::Gzip(encoder) => poll_read_buf(encoder, cx, &mut buf),
-            #[cfg(feature = "zlib")]
-            PinnedBody::Zlib(encoder) => poll_read_buf(encoder, cx, &mut buf),
-            PinnedBody::Bytes(bytes) => {
-                *this.done = true;
-                let bytes = mem::take(bytes.get_mut());
-                return Poll::Ready(match bytes.has_remaining() {
-                    true => Some(Ok(Frame::data(bytes))),
-                    false => None,
-                });
+        let this = self.get_mut();
+        #[allow(clippy::never_loop)] // Depends on features
+        loop {
+            #[cfg(any(
+                feature = "brotli",
+                feature = "gzip",
+                feature = "zlib",
+                feature = "zstd"
+            ))]
+            if let Some(trailers) = this.encoder.as_mut().and_then(|enc| enc.trailers.take()) {
+                return Poll::Ready(Some(Ok(Frame::trailers(trailers))));
             }
-            #[cfg(feature = "hyper")]
-            PinnedBody::Hyper(mut inner) => {
-                return Poll::Ready(match ready!(inner.as_mut().poll_frame(cx)) {
-                    Some(Ok(frame)) => Some(Ok(frame)),
-                    Some(Err(error)) => Some(Err(io::Error::other(error))),
-                    None => {
-                        *this.done = true;
-                        None
-                    }
-                })
-            }
-            PinnedBody::Streaming(inner) => match ready!(inner.as_mut().poll_frame(cx)) {
-                Some(item) => return Poll::Ready(Some(item)),
-                None => {
-                    *this.done = true;
-                    return Poll::Ready(None);
-                }
-            },
-            PinnedBody::Lazy { future, encoding } => {
-                let bytes = match ready!(future.as_mut().poll(cx)) {
-                    Ok(bytes) => bytes,
-                    Err(error) => return Poll::Ready(Some(Err(error))),
-                };
 
-                let len = bytes.len();
-                let mut inner = InnerBody::wrap(bytes, *encoding);
-                *this.full_size = len as u64;
-                // The duplication here is pretty ugly, but I couldn't come up with anything better.
-                match &mut inner {
-                    #[cfg(feature = "brotli")]
-                    InnerBody::Brotli(encoder) => poll_read_buf(Pin::new(encoder), cx, &mut buf),
-                    #[cfg(feature = "gzip")]
-                    InnerBody::Gzip(encoder) => poll_read_buf(Pin::new(encoder), cx, &mut buf),
-                    #[cfg(feature = "zlib")]
-                    InnerBody::Zlib(encoder) => poll_read_buf(Pin::new(encoder), cx, &mut buf),
-                    InnerBody::Bytes(bytes) => {
-                        *this.done = true;
-                        let bytes = mem::take(bytes);
-                        return Poll::Ready(match bytes.has_remaining() {
-                            true => Some(Ok(Frame::data(bytes))),
-                            false => None,
-                        });
-                    }
-                    #[cfg(feature = "hyper")]
-                    InnerBody::Hyper(_) => unreachable!(),
-                    InnerBody::Lazy { .. } | InnerBody::Streaming(_) => {
-                        unreachable!()
-                    }
-                }
+            if this.done {
+                return Poll::Ready(None);
             }
-        };
 
-        #[cfg(any(feature = "brotli", feature = "gzip", feature = "zlib"))]
-        match ready!(result) {
-            Ok(0) => {
-                *this.done = true;
-                Poll::Ready(None)
-            }
-            Ok(n) => {
-                *this.full_size = this.full_size.saturating_sub(n as u64);
-                Poll::Ready(Some(Ok(Frame::data(buf.freeze()))))
-            }
-            Err(error) => Poll::Ready(Some(Err(error))),
+            let frame = match ready!(this.inner.poll_frame(cx)) {
+                Some(Ok(frame)) => Some(frame),
+                Some(Err(error)) => return Poll::Ready(Some(Err(error))),
+                None => None,
+            };
+
+            this.done = match &frame {
+                Some(frame) => frame.is_trailers() || this.inner.is_end_stream(),
+                None => true,
+            };
+
+            #[cfg(any(
+                feature = "brotli",
+                feature = "gzip",
+                feature = "zlib",
+                feature = "zstd"
+            ))]
+            let frame = match &mut this.encoder {
+                Some(encoder) => match encoder.encode(frame, this.done, cx) {
+                    Ok(Some(frame)) => Some(frame),
+                    // Nothing to send yet, or the encoder had nothing left to flush
+                    Ok(None) => continue,
+                    Err(error) => return Poll::Ready(Some(Err(error))),
+                },
+                None => frame,
+            };
+
+            return Poll::Ready(frame.map(Ok));
         }
     }
 
     fn is_end_stream(&self) -> bool {
+        #[cfg(any(
+            feature = "brotli",
+            feature = "gzip",
+            feature = "zlib",
+            feature = "zstd"
+        ))]
+        if let Some(encoder) = &self.encoder {
+            return self.done && encoder.trailers.is_none();
+        }
+
         self.done
     }
 
     fn size_hint(&self) -> http_body::SizeHint {
-        match (self.done, &self.inner) {
-            (true, _) => SizeHint::with_exact(0),
-            (false, InnerBody::Bytes(body)) => SizeHint::with_exact(body.len() as u64),
-            #[cfg(feature = "hyper")]
-            (false, InnerBody::Hyper(inner)) => inner.size_hint(),
-            (false, InnerBody::Lazy { .. } | InnerBody::Streaming(_)) => SizeHint::default(),
-            // The duplication here is pretty ugly, but I couldn't come up with anything better.
-            #[cfg(feature = "brotli")]
-            (false, InnerBody::Brotli(_)) => {
-                let mut hint = SizeHint::default();
-                hint.set_lower(1);
-                hint.set_upper(self.full_size + 256);
-                hint
-            }
-            #[cfg(feature = "gzip")]
-            (false, InnerBody::Gzip(_)) => {
-                let mut hint = SizeHint::default();
-                hint.set_lower(1);
-                hint.set_upper(self.full_size + 256);
-                hint
-            }
-            #[cfg(feature = "zlib")]
-            (false, InnerBody::Zlib(_)) => {
-                let mut hint = SizeHint::default();
-                hint.set_lower(1);
-                hint.set_upper(self.full_size + 256);
-                hint
-            }
+        #[cfg(any(
+            feature = "brotli",
+            feature = "gzip",
+            feature = "zlib",
+            feature = "zstd"
+        ))]
+        if self.encoder.is_some() {
+            return match self.is_end_stream() {
+                true => SizeHint::with_exact(0),
+                false => SizeHint::default(),
+            };
+        }
+
+        match self.done {
+            true => SizeHint::with_exact(0),
+            false => self.inner.size_hint(),
         }
     }
 }
@@ -219,11 +249,7 @@ impl http_body::Body for Body {
 #[cfg(feature = "hyper")]
 impl From<hyper::body::Incoming> for Body {
     fn from(inner: hyper::body::Incoming) -> Self {
-        Self {
-            inner: InnerBody::Hyper(inner),
-            full_size: 0,
-            done: false,
-        }
+        Self::new(InnerBody::Hyper(inner), false)
     }
 }
 
@@ -247,11 +273,8 @@ impl From<&'static str> for Body {
 
 impl From<Bytes> for Body {
     fn from(data: Bytes) -> Self {
-        Self {
-            done: !data.has_remaining(),
-            full_size: data.len() as u64,
-            inner: InnerBody::Bytes(data),
-        }
+        let done = !data.has_remaining();
+        Self::new(InnerBody::Bytes(data), done)
     }
 }
 
@@ -261,195 +284,71 @@ impl Default for Body {
     }
 }
 
-#[cfg(any(feature = "brotli", feature = "gzip", feature = "zlib"))]
-impl EncodeResponse for Response<Body> {
-    fn encoded(mut self, req: &request::Parts) -> Response<Body> {
-        let buf = match self.body_mut() {
-            Body { done: true, .. } => return self,
-            Body {
-                inner: InnerBody::Bytes(buf),
-                ..
-            } => mem::take(buf),
-            Body {
-                inner:
-                    InnerBody::Lazy {
-                        encoding: enc @ Encoding::Identity,
-                        ..
-                    },
-                ..
-            } => {
-                let new = Encoding::from_accept(&req.headers).unwrap_or(Encoding::Identity);
-                *enc = new;
-                return self;
-            }
-            Body { .. } => return self,
-        };
-
-        let len = buf.len();
-        let encoding = Encoding::from_accept(&req.headers).unwrap_or(Encoding::Identity);
-        let inner = InnerBody::wrap(buf, encoding);
-        if let Some(encoding) = encoding.as_str() {
-            self.headers_mut()
-                .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding));
-        }
-
-        let body = self.body_mut();
-        body.full_size = len as u64;
-        body.inner = inner;
-        self
-    }
-}
-
-#[cfg(any(feature = "brotli", feature = "gzip", feature = "zlib"))]
-pub trait EncodeResponse {
-    fn encoded(self, req: &request::Parts) -> Self;
-}
-
-#[pin_project(project = PinnedBody)]
 enum InnerBody {
-    #[cfg(feature = "brotli")]
-    Brotli(#[pin] Box<BrotliEncoder<BufReader>>),
-    #[cfg(feature = "gzip")]
-    Gzip(#[pin] GzipEncoder<BufReader>),
-    #[cfg(feature = "zlib")]
-    Zlib(#[pin] ZlibEncoder<BufReader>),
-    Bytes(#[pin] Bytes),
+    Bytes(Bytes),
     #[cfg(feature = "hyper")]
-    Hyper(#[pin] hyper::body::Incoming),
-    Lazy {
-        future: Pin<Box<dyn Future<Output = io::Result<Bytes>> + Send>>,
-        encoding: Encoding,
-    },
+    Hyper(hyper::body::Incoming),
+    #[cfg(feature = "static")]
+    File(file::FileBody),
+    Lazy(Pin<Box<dyn Future<Output = io::Result<Bytes>> + Send>>),
     Streaming(Pin<Box<dyn http_body::Body<Data = Bytes, Error = io::Error> + Send>>),
 }
 
 impl InnerBody {
-    fn wrap(buf: Bytes, encoding: Encoding) -> Self {
-        match encoding {
-            #[cfg(feature = "brotli")]
-            Encoding::Brotli => Self::Brotli(Box::new(BrotliEncoder::new(BufReader { buf }))),
-            #[cfg(feature = "gzip")]
-            Encoding::Gzip => Self::Gzip(GzipEncoder::new(BufReader { buf })),
-            #[cfg(feature = "zlib")]
-            Encoding::Zlib => Self::Zlib(ZlibEncoder::new(BufReader { buf })),
-            Encoding::Identity => Self::Bytes(buf),
-        }
-    }
-}
-
-#[cfg(any(feature = "brotli", feature = "gzip", feature = "zlib"))]
-struct BufReader {
-    pub(crate) buf: Bytes,
-}
-
-#[cfg(any(feature = "brotli", feature = "gzip", feature = "zlib"))]
-impl AsyncBufRead for BufReader {
-    fn poll_fill_buf(
-        self: Pin<&mut Self>,
-        _: &mut std::task::Context<'_>,
-    ) -> Poll<io::Result<&[u8]>> {
-        Poll::Ready(Ok(self.get_mut().buf.chunk()))
-    }
-
-    fn consume(self: Pin<&mut Self>, amt: usize) {
-        self.get_mut().buf.advance(amt);
-    }
-}
-
-#[cfg(any(feature = "brotli", feature = "gzip", feature = "zlib"))]
-impl AsyncRead for BufReader {
-    fn poll_read(
-        self: Pin<&mut Self>,
-        _: &mut std::task::Context<'_>,
-        buf: &mut ReadBuf<'_>,
-    ) -> Poll<io::Result<()>> {
-        let len = Ord::min(self.buf.remaining(), buf.remaining());
-        self.get_mut()
-            .buf
-            .copy_to_slice(buf.initialize_unfilled_to(len));
-        Poll::Ready(Ok(()))
-    }
-}
-
-#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
-enum Encoding {
-    #[cfg(feature = "brotli")]
-    Brotli,
-    #[cfg(feature = "gzip")]
-    Gzip,
-    #[cfg(feature = "zlib")]
-    Zlib,
-    Identity,
-}
-
-impl Encoding {
-    #[cfg(any(feature = "brotli", feature = "gzip", feature = "zlib"))]
-    fn from_accept(headers: &HeaderMap) -> Option<Self> {
-        let accept = match headers.get(ACCEPT_ENCODING).map(|hv| hv.to_str()) {
-            Some(Ok(accept)) => accept,
-            _ => return None,
-        };
-
-        let mut encodings = accept
-            .split(',')
-            .filter_map(|s| {
-                let mut parts = s.splitn(2, ';');
-                let alg = match Encoding::from_str(parts.next()?.trim()) {
-                    Ok(encoding) => encoding,
-                    Err(()) => return None,
+    fn poll_frame(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<io::Result<Frame<Bytes>>>> {
+        match self {
+            Self::Bytes(bytes) => {
+                let bytes = mem::take(bytes);
+                Poll::Ready(match bytes.has_remaining() {
+                    true => Some(Ok(Frame::data(bytes))),
+                    false => None,
+                })
+            }
+            #[cfg(feature = "hyper")]
+            Self::Hyper(inner) => Poll::Ready(match ready!(Pin::new(inner).poll_frame(cx)) {
+                Some(Ok(frame)) => Some(Ok(frame)),
+                Some(Err(error)) => Some(Err(io::Error::other(error))),
+                None => None,
+            }),
+            #[cfg(feature = "static")]
+            Self::File(inner) => Pin::new(inner).poll_frame(cx),
+            Self::Lazy(future) => {
+                let bytes = match ready!(future.as_mut().poll(cx)) {
+                    Ok(bytes) => bytes,
+                    Err(error) => return Poll::Ready(Some(Err(error))),
                 };
 
-                let qual = parts
-                    .next()
-                    .and_then(|s| {
-                        let mut parts = s.splitn(2, '=');
-                        if parts.next()?.trim() != "q" {
-                            return None;
-                        }
-
-                        let value = parts.next()?;
-                        f64::from_str(value).ok()
-                    })
-                    .unwrap_or(1.0);
-
-                Some((alg, (qual * 100.0) as u64))
-            })
-            .collect::<Vec<_>>();
-        encodings.sort_by_key(|(algo, qual)| (-(*qual as i64), *algo));
-
-        encodings.into_iter().next().map(|(algo, _)| algo)
+                *self = Self::Bytes(bytes);
+                self.poll_frame(cx)
+            }
+            Self::Streaming(inner) => inner.as_mut().poll_frame(cx),
+        }
     }
-}
 
-impl Encoding {
-    #[cfg(any(feature = "brotli", feature = "gzip", feature = "zlib"))]
-    pub fn as_str(self) -> Option<&'static str> {
+    fn is_end_stream(&self) -> bool {
         match self {
-            #[cfg(feature = "brotli")]
-            Self::Brotli => Some("br"),
-            #[cfg(feature = "gzip")]
-            Self::Gzip => Some("gzip"),
-            Self::Identity => None,
-            // The `deflate` encoding is actually zlib, but the HTTP standard calls it `deflate`.
-            #[cfg(feature = "zlib")]
-            Self::Zlib => Some("deflate"),
+            Self::Bytes(bytes) => !bytes.has_remaining(),
+            #[cfg(feature = "hyper")]
+            Self::Hyper(inner) => inner.is_end_stream(),
+            #[cfg(feature = "static")]
+            Self::File(inner) => inner.is_end_stream(),
+            Self::Lazy(_) => false,
+            Self::Streaming(inner) => inner.is_end_stream(),
         }
     }
-}
 
-impl FromStr for Encoding {
-    type Err = ();
-
-    fn from_str(s: &str) -> Result<Encoding, ()> {
-        Ok(match s {
-            #[cfg(feature = "brotli")]
-            "br" => Encoding::Brotli,
-            #[cfg(feature = "gzip")]
-            "gzip" => Encoding::Gzip,
-            "identity" => Encoding::Identity,
-            #[cfg(feature = "zlib")]
-            "deflate" => Encoding::Zlib,
-            _ => return Err(()),
-        })
+    fn size_hint(&self) -> SizeHint {
+        match self {
+            Self::Bytes(bytes) => SizeHint::with_exact(bytes.len() as u64),
+            #[cfg(feature = "hyper")]
+            Self::Hyper(inner) => inner.size_hint(),
+            #[cfg(feature = "static")]
+            Self::File(inner) => inner.size_hint(),
+            Self::Lazy(_) => SizeHint::default(),
+            Self::Streaming(inner) => inner.size_hint(),
+        }
     }
 }
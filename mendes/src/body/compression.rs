@@ -0,0 +1,861 @@
+use std::borrow::Cow;
+#[cfg(feature = "body-util")]
+use std::error::Error as StdError;
+#[cfg(feature = "body-util")]
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::str::FromStr;
+#[cfg(feature = "body-util")]
+use std::task::ready;
+use std::task::Poll;
+use std::{io, mem};
+
+#[cfg(all(feature = "brotli", feature = "body-util"))]
+use async_compression::tokio::bufread::BrotliDecoder;
+#[cfg(all(feature = "gzip", feature = "body-util"))]
+use async_compression::tokio::bufread::GzipDecoder;
+#[cfg(all(feature = "zlib", feature = "body-util"))]
+use async_compression::tokio::bufread::ZlibDecoder;
+#[cfg(all(feature = "zstd", feature = "body-util"))]
+use async_compression::tokio::bufread::ZstdDecoder;
+#[cfg(feature = "brotli")]
+use async_compression::tokio::write::BrotliEncoder;
+#[cfg(feature = "gzip")]
+use async_compression::tokio::write::GzipEncoder;
+#[cfg(feature = "zlib")]
+use async_compression::tokio::write::ZlibEncoder;
+#[cfg(feature = "zstd")]
+use async_compression::tokio::write::ZstdEncoder;
+pub use async_compression::Level;
+use bytes::{Buf, Bytes};
+use http::header::{
+    ACCEPT_ENCODING, ACCEPT_RANGES, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE,
+    ETAG, VARY,
+};
+use http::{request, HeaderMap, HeaderValue, Response, StatusCode};
+use http_body::{Body as _, Frame};
+use tokio::io::AsyncWrite;
+#[cfg(feature = "body-util")]
+use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+
+use super::Body;
+#[cfg(feature = "body-util")]
+use crate::application::Error;
+
+impl EncodeResponse for Response<Body> {
+    fn encoded(self, req: &request::Parts) -> Response<Body> {
+        self.encoded_with(req, &DEFAULT_POLICY)
+    }
+
+    fn encoded_with(mut self, req: &request::Parts, policy: &CompressionPolicy) -> Response<Body> {
+        // Leave empty bodies and bodies that were already encoded (for example by a proxied
+        // upstream server) alone
+        if self.body().done || self.headers().contains_key(CONTENT_ENCODING) {
+            return self;
+        }
+
+        // Negotiate first, so that a request ruling out `identity` gets the same response
+        // whether or not the policy would compress this body
+        let encoding = policy.negotiate(&req.headers);
+        if encoding.is_none() && self.status().is_success() {
+            return Response::builder()
+                .status(StatusCode::NOT_ACCEPTABLE)
+                .header(VARY, "accept-encoding")
+                .body(Body::empty())
+                .unwrap();
+        }
+
+        if !policy.applies(&self) {
+            return self;
+        }
+
+        let headers = self.headers_mut();
+        let vary = headers.get_all(VARY).iter().any(|value| {
+            value.to_str().is_ok_and(|value| {
+                value.split(',').any(|field| {
+                    let field = field.trim();
+                    field == "*" || field.eq_ignore_ascii_case("accept-encoding")
+                })
+            })
+        });
+        if !vary {
+            headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+        }
+
+        let encoding = match encoding {
+            Some(encoding) => encoding,
+            // Don't hide errors and redirects behind a 406, send them unencoded instead
+            None => return self,
+        };
+
+        let level = policy.level_for(encoding);
+        let (Some(name), Some(encoder)) = (encoding.as_str(), Encoder::new(encoding, level)) else {
+            return self;
+        };
+
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static(name));
+        // The length of the encoded body isn't known until it has been compressed
+        headers.remove(CONTENT_LENGTH);
+        // Byte ranges can't be served from a body that's encoded on the fly
+        headers.remove(ACCEPT_RANGES);
+        // The encoded body is no longer byte-for-byte identical to the unencoded one
+        if let Some(Ok(etag)) = headers.get(ETAG).map(|etag| etag.to_str()) {
+            if !etag.starts_with("W/") {
+                let weak = HeaderValue::try_from(format!("W/{etag}")).unwrap();
+                headers.insert(ETAG, weak);
+            }
+        }
+
+        self.body_mut().encoder = Some(encoder);
+        self
+    }
+}
+
+pub trait EncodeResponse {
+    /// Compress the response body using the best encoding accepted by the request
+    ///
+    /// Sets the `Content-Encoding` and `Vary` headers and removes any `Content-Length`.
+    /// Streaming bodies are compressed frame by frame, flushing the encoder after each frame
+    /// so that clients can decode the data as it arrives.
+    ///
+    /// Uses the default `CompressionPolicy`; see `encoded_with()` for details. Responses
+    /// carrying the `NoCompression` extension or a `Cache-Control: no-transform` directive
+    /// are left alone.
+    fn encoded(self, req: &request::Parts) -> Self;
+
+    /// Compress the response body according to the given `policy`
+    ///
+    /// If the request's `Accept-Encoding` header rules out every encoding including
+    /// `identity`, successful responses are replaced with a `406 Not Acceptable` response,
+    /// even if the policy wouldn't compress them; other responses are sent unencoded.
+    ///
+    /// To set a policy once for all of an application's responses, keep it in the
+    /// `Application` (or in a `static`, since `CompressionPolicy::new()` is `const`) and
+    /// encode the response `route!` produces in `Application::handle()`:
+    ///
+    /// ```ignore
+    /// async fn handle(mut cx: Context<Self>) -> Response<Body> {
+    ///     let response = route!(match cx.path() {
+    ///         Some("api") => api,
+    ///         _ => index,
+    ///     });
+    ///
+    ///     response.encoded_with(&cx.req, &cx.app.compression)
+    /// }
+    /// ```
+    fn encoded_with(self, req: &request::Parts, policy: &CompressionPolicy) -> Self;
+}
+
+/// Response extension that prevents `EncodeResponse` from compressing the response
+///
+/// ```ignore
+/// response.extensions_mut().insert(NoCompression);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoCompression;
+
+/// Configures how response bodies are compressed
+///
+/// By default, all supported encodings are used at their default levels (except brotli,
+/// which uses level 4 to keep on-the-fly compression fast), bodies smaller than 32 bytes
+/// are sent as is, and common media types that are already compressed (like PNG and JPEG
+/// images, audio, video and archives) are excluded.
+#[derive(Clone, Debug)]
+pub struct CompressionPolicy {
+    preference: Cow<'static, [Encoding]>,
+    levels: Vec<(Encoding, Level)>,
+    min_size: u64,
+    allow: Cow<'static, [Cow<'static, str>]>,
+    deny: Cow<'static, [Cow<'static, str>]>,
+}
+
+impl CompressionPolicy {
+    /// The default policy
+    pub const fn new() -> Self {
+        Self {
+            preference: Cow::Borrowed(DEFAULT_PREFERENCE),
+            levels: Vec::new(),
+            min_size: DEFAULT_MIN_SIZE,
+            allow: Cow::Borrowed(&[]),
+            deny: Cow::Borrowed(DEFAULT_DENY),
+        }
+    }
+
+    /// Set the compression level for `encoding`
+    pub fn level(mut self, encoding: Encoding, level: Level) -> Self {
+        self.levels.retain(|(e, _)| *e != encoding);
+        self.levels.push((encoding, level));
+        self
+    }
+
+    /// Don't compress bodies smaller than `size` bytes
+    ///
+    /// Only applies to bodies with a known size; streaming bodies are always compressed.
+    pub fn min_size(mut self, size: u64) -> Self {
+        self.min_size = size;
+        self
+    }
+
+    /// Only compress responses with one of the given media types
+    ///
+    /// Types are matched against the response's `Content-Type` without parameters, and can
+    /// use a wildcard subtype like `text/*`. Responses without a `Content-Type` are not
+    /// compressed if this is set. By default, all types that aren't denied are compressed.
+    pub fn allow_types(mut self, types: &[&str]) -> Self {
+        self.allow = Cow::Owned(media_types(types));
+        self
+    }
+
+    /// Never compress responses with one of the given media types
+    ///
+    /// Types are matched like in `allow_types()`. This replaces the default list of types
+    /// that are already compressed.
+    pub fn deny_types(mut self, types: &[&str]) -> Self {
+        self.deny = Cow::Owned(media_types(types));
+        self
+    }
+
+    /// Set the server's order of preference for content encodings
+    ///
+    /// If the client accepts several encodings with the same quality value, the first of
+    /// them in `encodings` is used. Encodings left out are never used. Defaults to all
+    /// supported encodings, in the order zstd, brotli, gzip and deflate.
+    pub fn prefer(mut self, encodings: &[Encoding]) -> Self {
+        let mut preference = Vec::with_capacity(encodings.len());
+        for &encoding in encodings {
+            if encoding != Encoding::Identity && !preference.contains(&encoding) {
+                preference.push(encoding);
+            }
+        }
+
+        self.preference = Cow::Owned(preference);
+        self
+    }
+
+    /// Select the encoding for a response to a request with the given `headers`
+    ///
+    /// Implements the `Accept-Encoding` semantics from RFC 9110, section 12.5.3: codings
+    /// with `q=0` are excluded, `*` matches any coding not listed explicitly, and the
+    /// coding with the highest quality value wins, with ties broken by the server's
+    /// preference. The `identity` coding is used if the request has no `Accept-Encoding`
+    /// header or if no other coding is acceptable. Returns `None` if `identity` has been
+    /// ruled out too, in which case the server should respond with `406 Not Acceptable`.
+    pub fn negotiate(&self, headers: &HeaderMap) -> Option<Encoding> {
+        let mut values = headers.get_all(ACCEPT_ENCODING).iter().peekable();
+        if values.peek().is_none() {
+            return Some(Encoding::Identity);
+        }
+
+        let mut wildcard = None;
+        let mut explicit = Vec::new();
+        for value in values {
+            // Ignore header values that aren't valid ASCII, like a server would an unknown coding
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+
+            for member in value.split(',') {
+                let mut params = member.split(';');
+                let coding = params.next().unwrap_or_default().trim();
+                if coding.is_empty() {
+                    continue;
+                }
+
+                let mut quality = Some(1000);
+                for param in params {
+                    let Some((name, value)) = param.split_once('=') else {
+                        continue;
+                    };
+
+                    if name.trim().eq_ignore_ascii_case("q") {
+                        quality = qvalue(value.trim());
+                    }
+                }
+
+                // Skip members with a malformed quality value
+                let Some(quality) = quality else {
+                    continue;
+                };
+
+                if coding == "*" {
+                    wildcard.get_or_insert(quality);
+                } else if let Ok(encoding) = Encoding::from_str(coding) {
+                    if !explicit.iter().any(|(e, _)| *e == encoding) {
+                        explicit.push((encoding, quality));
+                    }
+                }
+            }
+        }
+
+        let quality = |encoding| {
+            explicit
+                .iter()
+                .find_map(|&(e, q)| (e == encoding).then_some(q))
+                .or(wildcard)
+        };
+
+        let mut best = None;
+        for &encoding in self.preference.iter() {
+            let quality = quality(encoding).unwrap_or(0);
+            if quality > 0 && best.is_none_or(|(_, best)| quality > best) {
+                best = Some((encoding, quality));
+            }
+        }
+
+        // `identity` is always acceptable unless it's excluded explicitly (or through `*`)
+        match (best, quality(Encoding::Identity)) {
+            (Some((_, quality)), Some(identity)) if identity > quality => Some(Encoding::Identity),
+            (Some((encoding, _)), _) => Some(encoding),
+            (None, Some(0)) => None,
+            (None, _) => Some(Encoding::Identity),
+        }
+    }
+}
+
+impl CompressionPolicy {
+    /// Whether the policy allows compressing the given response at all
+    fn applies(&self, rsp: &Response<Body>) -> bool {
+        // Partial content refers to ranges of the unencoded representation
+        if rsp.status() == StatusCode::PARTIAL_CONTENT {
+            return false;
+        }
+
+        if rsp.extensions().get::<NoCompression>().is_some() {
+            return false;
+        }
+
+        let headers = rsp.headers();
+        let no_transform = headers.get_all(CACHE_CONTROL).iter().any(|value| {
+            value.to_str().is_ok_and(|value| {
+                value
+                    .split(',')
+                    .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"))
+            })
+        });
+        if no_transform {
+            return false;
+        }
+
+        if let Some(size) = rsp.body().size_hint().exact() {
+            if size < self.min_size {
+                return false;
+            }
+        }
+
+        let essence = match headers.get(CONTENT_TYPE).map(|value| value.to_str()) {
+            Some(Ok(value)) => value.split(';').next().unwrap_or_default().trim(),
+            Some(Err(_)) => return false,
+            None => return self.allow.is_empty(),
+        };
+
+        let matches = |pattern: &Cow<'static, str>| match pattern.strip_suffix("/*") {
+            Some(ty) => essence
+                .split_once('/')
+                .is_some_and(|(t, _)| t.eq_ignore_ascii_case(ty)),
+            None => essence.eq_ignore_ascii_case(pattern),
+        };
+
+        (self.allow.is_empty() || self.allow.iter().any(matches)) && !self.deny.iter().any(matches)
+    }
+
+    fn level_for(&self, encoding: Encoding) -> Level {
+        match self.levels.iter().find(|(e, _)| *e == encoding) {
+            Some(&(_, level)) => level,
+            #[cfg(feature = "brotli")]
+            None if encoding == Encoding::Brotli => Level::Precise(4),
+            None => Level::Default,
+        }
+    }
+}
+
+impl Default for CompressionPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parse a quality value (RFC 9110, section 12.4.2) in thousandths
+fn qvalue(s: &str) -> Option<u16> {
+    let (int, fraction) = s.split_once('.').unwrap_or((s, ""));
+    if fraction.len() > 3 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let fraction = fraction
+        .bytes()
+        .chain([b'0'; 3])
+        .take(3)
+        .fold(0, |acc, b| acc * 10 + u16::from(b - b'0'));
+    match int {
+        "0" => Some(fraction),
+        "1" if fraction == 0 => Some(1000),
+        _ => None,
+    }
+}
+
+fn media_types(types: &[&str]) -> Vec<Cow<'static, str>> {
+    types
+        .iter()
+        .map(|ty| Cow::Owned(ty.trim().to_ascii_lowercase()))
+        .collect()
+}
+
+static DEFAULT_POLICY: CompressionPolicy = CompressionPolicy::new();
+
+const DEFAULT_MIN_SIZE: u64 = 32;
+
+/// Media types that are already compressed, so compressing them again is a waste of time
+const DEFAULT_DENY: &[Cow<'static, str>] = &[
+    Cow::Borrowed("application/gzip"),
+    Cow::Borrowed("application/vnd.rar"),
+    Cow::Borrowed("application/x-7z-compressed"),
+    Cow::Borrowed("application/x-bzip2"),
+    Cow::Borrowed("application/x-gzip"),
+    Cow::Borrowed("application/x-xz"),
+    Cow::Borrowed("application/zip"),
+    Cow::Borrowed("application/zstd"),
+    Cow::Borrowed("audio/*"),
+    Cow::Borrowed("font/woff"),
+    Cow::Borrowed("font/woff2"),
+    Cow::Borrowed("image/avif"),
+    Cow::Borrowed("image/gif"),
+    Cow::Borrowed("image/jpeg"),
+    Cow::Borrowed("image/png"),
+    Cow::Borrowed("image/webp"),
+    Cow::Borrowed("video/*"),
+];
+
+const DEFAULT_PREFERENCE: &[Encoding] = &[
+    #[cfg(feature = "zstd")]
+    Encoding::Zstd,
+    #[cfg(feature = "brotli")]
+    Encoding::Brotli,
+    #[cfg(feature = "gzip")]
+    Encoding::Gzip,
+    #[cfg(feature = "zlib")]
+    Encoding::Zlib,
+];
+
+/// Decompress a request body with the given `encoding`
+///
+/// Both the compressed and the decompressed body are limited to `max_len` bytes.
+#[cfg(feature = "body-util")]
+pub(crate) async fn decompress<B>(
+    encoding: Encoding,
+    body: B,
+    max_len: usize,
+) -> Result<Bytes, Error>
+where
+    B: http_body::Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync + 'static>>,
+{
+    let reader = BodyReader {
+        body: Box::pin(body),
+        chunk: Bytes::new(),
+        received: 0,
+        max_len,
+        error: None,
+    };
+
+    match encoding {
+        #[cfg(feature = "brotli")]
+        Encoding::Brotli => {
+            read_decoded(BrotliDecoder::new(reader), max_len, |d| d.get_mut()).await
+        }
+        #[cfg(feature = "gzip")]
+        Encoding::Gzip => read_decoded(GzipDecoder::new(reader), max_len, |d| d.get_mut()).await,
+        #[cfg(feature = "zlib")]
+        Encoding::Zlib => read_decoded(ZlibDecoder::new(reader), max_len, |d| d.get_mut()).await,
+        #[cfg(feature = "zstd")]
+        Encoding::Zstd => read_decoded(ZstdDecoder::new(reader), max_len, |d| d.get_mut()).await,
+        Encoding::Identity => unreachable!("identity is not a content coding to decompress"),
+    }
+}
+
+#[cfg(feature = "body-util")]
+async fn read_decoded<D, B>(
+    mut decoder: D,
+    max_len: usize,
+    reader: impl Fn(&mut D) -> &mut BodyReader<B>,
+) -> Result<Bytes, Error>
+where
+    D: AsyncRead + Unpin,
+    B: http_body::Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync + 'static>>,
+{
+    let mut output = Vec::new();
+    let mut chunk = [0; 8192];
+    loop {
+        let mut buf = ReadBuf::new(&mut chunk);
+        let result = poll_fn(|cx| Pin::new(&mut decoder).poll_read(cx, &mut buf)).await;
+
+        let reader = reader(&mut decoder);
+        if let Some(error) = reader.error.take() {
+            return Err(error);
+        }
+
+        if let Err(error) = result {
+            return Err(Error::BodyDecompress(error));
+        }
+
+        let read = buf.filled();
+        if read.is_empty() {
+            return Ok(Bytes::from(output));
+        }
+
+        output.extend_from_slice(read);
+        let len = output.len() as u64;
+        if len > max_len as u64 {
+            return Err(Error::BodyTooLarge);
+        } else if len > MAX_RATIO_EXEMPT && len > reader.received.saturating_mul(MAX_RATIO) {
+            return Err(Error::BodyCompressionRatio);
+        }
+    }
+}
+
+/// Adapts a compressed request body to `AsyncBufRead` for the decoders
+///
+/// Fails once more than `max_len` bytes have been received.
+#[cfg(feature = "body-util")]
+struct BodyReader<B> {
+    body: Pin<Box<B>>,
+    chunk: Bytes,
+    received: u64,
+    max_len: usize,
+    /// The error that made reading fail, reported instead of the decoder's error
+    error: Option<Error>,
+}
+
+#[cfg(feature = "body-util")]
+impl<B> AsyncBufRead for BodyReader<B>
+where
+    B: http_body::Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync + 'static>>,
+{
+    fn poll_fill_buf(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        while !this.chunk.has_remaining() {
+            match ready!(this.body.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => {
+                    let Ok(mut data) = frame.into_data() else {
+                        continue;
+                    };
+
+                    this.chunk = data.copy_to_bytes(data.remaining());
+                    this.received += this.chunk.len() as u64;
+                    if this.received > this.max_len as u64 {
+                        this.error = Some(Error::BodyTooLarge);
+                        return Poll::Ready(Err(io::ErrorKind::FileTooLarge.into()));
+                    }
+                }
+                Some(Err(error)) => {
+                    this.error = Some(Error::BodyReceive(error.into()));
+                    return Poll::Ready(Err(io::ErrorKind::Other.into()));
+                }
+                None => break,
+            }
+        }
+
+        Poll::Ready(Ok(this.chunk.chunk()))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.get_mut().chunk.advance(amt);
+    }
+}
+
+#[cfg(feature = "body-util")]
+impl<B> AsyncRead for BodyReader<B>
+where
+    B: http_body::Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync + 'static>>,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let available = ready!(self.as_mut().poll_fill_buf(cx))?;
+        let len = Ord::min(available.len(), buf.remaining());
+        buf.put_slice(&available[..len]);
+        self.consume(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Bodies that decompress to at most this many bytes are exempt from the ratio limit
+#[cfg(feature = "body-util")]
+const MAX_RATIO_EXEMPT: u64 = 64 * 1024;
+/// The maximum ratio of decompressed to compressed size for request bodies
+#[cfg(feature = "body-util")]
+const MAX_RATIO: u64 = 100;
+
+/// Incrementally compresses the frames of a body
+pub(super) struct Encoder {
+    writer: Writer,
+    /// Trailers held back until the remaining compressed data has been sent
+    pub(super) trailers: Option<HeaderMap>,
+}
+
+impl Encoder {
+    fn new(encoding: Encoding, level: Level) -> Option<Box<Self>> {
+        let writer = match encoding {
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => {
+                Writer::Brotli(Box::new(BrotliEncoder::with_quality(Vec::new(), level)))
+            }
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => Writer::Gzip(GzipEncoder::with_quality(Vec::new(), level)),
+            #[cfg(feature = "zlib")]
+            Encoding::Zlib => Writer::Zlib(ZlibEncoder::with_quality(Vec::new(), level)),
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => Writer::Zstd(ZstdEncoder::with_quality(Vec::new(), level)),
+            Encoding::Identity => return None,
+        };
+
+        Some(Box::new(Self {
+            writer,
+            trailers: None,
+        }))
+    }
+
+    /// Compress the next `frame` of the body, if any
+    ///
+    /// If `end` is set, this finishes the compressed stream; otherwise the encoder is flushed
+    /// so that everything written so far can be decoded by the client. Returns `None` if
+    /// there is no output to send.
+    pub(super) fn encode(
+        &mut self,
+        frame: Option<Frame<Bytes>>,
+        end: bool,
+        cx: &mut std::task::Context<'_>,
+    ) -> io::Result<Option<Frame<Bytes>>> {
+        let mut data = Bytes::new();
+        if let Some(frame) = frame {
+            match frame.into_data() {
+                Ok(frame) => data = frame,
+                Err(frame) => self.trailers = frame.into_trailers().ok(),
+            }
+        }
+
+        if data.is_empty() && !end {
+            return Ok(None);
+        }
+
+        let mut writer = self.writer.as_mut();
+        while data.has_remaining() {
+            match writer.as_mut().poll_write(cx, &data) {
+                Poll::Ready(Ok(0)) => return Err(io::ErrorKind::WriteZero.into()),
+                Poll::Ready(Ok(n)) => data.advance(n),
+                Poll::Ready(Err(error)) => return Err(error),
+                Poll::Pending => return Err(io::ErrorKind::WouldBlock.into()),
+            }
+        }
+
+        let result = match end {
+            true => writer.poll_shutdown(cx),
+            false => writer.poll_flush(cx),
+        };
+
+        match result {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(error)) => return Err(error),
+            Poll::Pending => return Err(io::ErrorKind::WouldBlock.into()),
+        }
+
+        let output = mem::take(self.writer.output());
+        Ok(match output.is_empty() {
+            true => self.trailers.take().map(Frame::trailers),
+            false => Some(Frame::data(Bytes::from(output))),
+        })
+    }
+}
+
+/// Compression encoders writing to an in-memory buffer, which never blocks
+enum Writer {
+    #[cfg(feature = "brotli")]
+    Brotli(Box<BrotliEncoder<Vec<u8>>>),
+    #[cfg(feature = "gzip")]
+    Gzip(GzipEncoder<Vec<u8>>),
+    #[cfg(feature = "zlib")]
+    Zlib(ZlibEncoder<Vec<u8>>),
+    #[cfg(feature = "zstd")]
+    Zstd(ZstdEncoder<Vec<u8>>),
+}
+
+impl Writer {
+    fn as_mut(&mut self) -> Pin<&mut (dyn AsyncWrite + Send + Unpin)> {
+        Pin::new(match self {
+            #[cfg(feature = "brotli")]
+            Self::Brotli(encoder) => encoder,
+            #[cfg(feature = "gzip")]
+            Self::Gzip(encoder) => encoder,
+            #[cfg(feature = "zlib")]
+            Self::Zlib(encoder) => encoder,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder,
+        })
+    }
+
+    fn output(&mut self) -> &mut Vec<u8> {
+        match self {
+            #[cfg(feature = "brotli")]
+            Self::Brotli(encoder) => encoder.get_mut(),
+            #[cfg(feature = "gzip")]
+            Self::Gzip(encoder) => encoder.get_mut(),
+            #[cfg(feature = "zlib")]
+            Self::Zlib(encoder) => encoder.get_mut(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.get_mut(),
+        }
+    }
+}
+
+/// A content coding for HTTP message bodies
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum Encoding {
+    #[cfg(feature = "zstd")]
+    Zstd,
+    #[cfg(feature = "brotli")]
+    Brotli,
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "zlib")]
+    Zlib,
+    Identity,
+}
+
+impl Encoding {
+    /// The coding's name in `Content-Encoding` headers, `None` for `Identity`
+    pub fn as_str(self) -> Option<&'static str> {
+        match self {
+            #[cfg(feature = "brotli")]
+            Self::Brotli => Some("br"),
+            #[cfg(feature = "gzip")]
+            Self::Gzip => Some("gzip"),
+            Self::Identity => None,
+            // The `deflate` encoding is actually zlib, but the HTTP standard calls it `deflate`.
+            #[cfg(feature = "zlib")]
+            Self::Zlib => Some("deflate"),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Some("zstd"),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = ();
+
+    /// Parse a content coding name, ignoring case
+    fn from_str(s: &str) -> Result<Encoding, ()> {
+        let s = s.to_ascii_lowercase();
+        Ok(match s.as_str() {
+            #[cfg(feature = "brotli")]
+            "br" => Encoding::Brotli,
+            // Recipients should treat `x-gzip` as `gzip` (RFC 9110, section 8.4.1.3)
+            #[cfg(feature = "gzip")]
+            "gzip" | "x-gzip" => Encoding::Gzip,
+            "identity" => Encoding::Identity,
+            #[cfg(feature = "zlib")]
+            "deflate" => Encoding::Zlib,
+            #[cfg(feature = "zstd")]
+            "zstd" => Encoding::Zstd,
+            _ => return Err(()),
+        })
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "brotli",
+    feature = "gzip",
+    feature = "zlib",
+    feature = "zstd"
+))]
+mod test {
+    use http::header::ACCEPT_ENCODING;
+    use http::{HeaderMap, HeaderValue};
+
+    use super::{CompressionPolicy, Encoding};
+
+    fn negotiate(policy: &CompressionPolicy, values: &[&'static str]) -> Option<Encoding> {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(ACCEPT_ENCODING, HeaderValue::from_static(value));
+        }
+        policy.negotiate(&headers)
+    }
+
+    #[test]
+    fn accept_encoding() {
+        use Encoding::*;
+
+        let default = CompressionPolicy::new();
+        let cases: &[(&[&str], Option<Encoding>)] = &[
+            (&[], Some(Identity)),
+            (&[""], Some(Identity)),
+            (&["gzip"], Some(Gzip)),
+            (&["GZip"], Some(Gzip)),
+            (&["x-gzip"], Some(Gzip)),
+            (&["gzip, deflate, br, zstd"], Some(Zstd)),
+            (&["gzip", "br"], Some(Brotli)),
+            (&["gzip;q=1.0, br;q=0.9"], Some(Gzip)),
+            (&["gzip; q=0.5, deflate ; Q=0.8"], Some(Zlib)),
+            (&["br;q=0.001, gzip;q=0"], Some(Brotli)),
+            (&["gzip;q=0"], Some(Identity)),
+            (&["br;q=0, *"], Some(Zstd)),
+            (&["*;q=0.5, gzip"], Some(Gzip)),
+            (&["*"], Some(Zstd)),
+            (&["*;q=0"], None),
+            (&["*;q=0, identity"], Some(Identity)),
+            (&["identity;q=0"], None),
+            (&["identity;q=0, gzip;q=0"], None),
+            (&["identity;q=0, gzip;q=0.1"], Some(Gzip)),
+            (&["gzip;q=0.5, identity"], Some(Identity)),
+            (&["gzip, identity;q=0.5"], Some(Gzip)),
+            (&["gzip, identity"], Some(Gzip)),
+            (&["compress, unknown"], Some(Identity)),
+            (&["gzip;q=0, gzip"], Some(Identity)),
+            (&["gzip;level=9"], Some(Gzip)),
+            // Malformed quality values invalidate only their own member
+            (&["br;q=1.5, gzip"], Some(Gzip)),
+            (&["br;q=0.0001, gzip;q=0.5"], Some(Gzip)),
+            (&["br;q=.5, gzip;q=0.5"], Some(Gzip)),
+            (&["br;q=abc"], Some(Identity)),
+            (&["br;q=, identity;q=0"], None),
+            (&[" , ,gzip,, "], Some(Gzip)),
+        ];
+
+        for (values, expected) in cases {
+            assert_eq!(negotiate(&default, values), *expected, "{values:?}");
+        }
+    }
+
+    #[test]
+    fn server_preference() {
+        let policy = CompressionPolicy::new().prefer(&[
+            Encoding::Gzip,
+            Encoding::Identity,
+            Encoding::Brotli,
+            Encoding::Gzip,
+        ]);
+        let cases: &[(&[&str], Option<Encoding>)] = &[
+            (&["br, gzip"], Some(Encoding::Gzip)),
+            (&["br;q=0.9, gzip;q=0.8"], Some(Encoding::Brotli)),
+            (&["*"], Some(Encoding::Gzip)),
+            (&["zstd, deflate"], Some(Encoding::Identity)),
+            (&["zstd, identity;q=0"], None),
+        ];
+
+        for (values, expected) in cases {
+            assert_eq!(negotiate(&policy, values), *expected, "{values:?}");
+        }
+
+        let policy = CompressionPolicy::new().prefer(&[]);
+        assert_eq!(negotiate(&policy, &["gzip, br"]), Some(Encoding::Identity));
+    }
+}
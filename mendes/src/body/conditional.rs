@@ -0,0 +1,222 @@
+use std::time::SystemTime;
+
+use http::header::{
+    HeaderValue, CACHE_CONTROL, CONTENT_LOCATION, DATE, ETAG, EXPIRES, IF_MATCH, IF_MODIFIED_SINCE,
+    IF_NONE_MATCH, IF_UNMODIFIED_SINCE, LAST_MODIFIED, VARY,
+};
+use http::request::Parts;
+use http::{Method, Response, StatusCode};
+
+use super::{Body, InnerBody};
+
+/// Evaluate conditional requests against a response's validators
+pub trait ConditionalResponse {
+    /// Evaluate the preconditions in `req` against the response's `ETag` and `Last-Modified`
+    ///
+    /// Follows the order of evaluation from RFC 9110, section 13.2.2. If `If-Match` or
+    /// `If-Unmodified-Since` fail, the response is replaced with `412 Precondition Failed`.
+    /// If `If-None-Match` or `If-Modified-Since` fail, `GET` and `HEAD` requests get a
+    /// `304 Not Modified` response (other methods get a 412). Only successful responses are
+    /// affected, and preconditions that can't be evaluated because the response lacks the
+    /// relevant validator or the header can't be parsed are ignored.
+    fn conditional(self, req: &Parts) -> Response<Body>;
+
+    /// Add a strong `ETag` computed from the response body
+    ///
+    /// Only applies to in-memory bodies of responses that don't have an `ETag` yet. The
+    /// tag is the 64-bit FNV-1a hash of the body, so it doesn't change between builds.
+    fn with_etag(self) -> Response<Body>;
+}
+
+impl ConditionalResponse for Response<Body> {
+    fn conditional(self, req: &Parts) -> Response<Body> {
+        if !self.status().is_success() {
+            return self;
+        }
+
+        let headers = self.headers();
+        let safe = matches!(req.method, Method::GET | Method::HEAD);
+        let status = if req.headers.contains_key(IF_MATCH) {
+            match matches(req.headers.get_all(IF_MATCH), headers.get(ETAG), false) {
+                true => None,
+                false => Some(StatusCode::PRECONDITION_FAILED),
+            }
+        } else if let (Some(since), Some(modified)) = (
+            date(req.headers.get(IF_UNMODIFIED_SINCE)),
+            date(headers.get(LAST_MODIFIED)),
+        ) {
+            match modified <= since {
+                true => None,
+                false => Some(StatusCode::PRECONDITION_FAILED),
+            }
+        } else {
+            None
+        };
+
+        let status = status.or_else(|| {
+            if req.headers.contains_key(IF_NONE_MATCH) {
+                let etag = headers.get(ETAG);
+                match (
+                    matches(req.headers.get_all(IF_NONE_MATCH), etag, true),
+                    safe,
+                ) {
+                    (false, _) => None,
+                    (true, true) => Some(StatusCode::NOT_MODIFIED),
+                    (true, false) => Some(StatusCode::PRECONDITION_FAILED),
+                }
+            } else if let (true, Some(since), Some(modified)) = (
+                safe,
+                date(req.headers.get(IF_MODIFIED_SINCE)),
+                date(headers.get(LAST_MODIFIED)),
+            ) {
+                match modified <= since {
+                    true => Some(StatusCode::NOT_MODIFIED),
+                    false => None,
+                }
+            } else {
+                None
+            }
+        });
+
+        let status = match status {
+            Some(status) => status,
+            None => return self,
+        };
+
+        let mut rsp = Response::new(Body::empty());
+        *rsp.status_mut() = status;
+        if status == StatusCode::NOT_MODIFIED {
+            // Keep the headers a cache needs to update its stored response
+            let (old, new) = (self.headers(), rsp.headers_mut());
+            for name in [
+                CACHE_CONTROL,
+                CONTENT_LOCATION,
+                DATE,
+                ETAG,
+                EXPIRES,
+                LAST_MODIFIED,
+                VARY,
+            ] {
+                for value in old.get_all(&name) {
+                    new.append(name.clone(), value.clone());
+                }
+            }
+        }
+
+        rsp
+    }
+
+    fn with_etag(mut self) -> Response<Body> {
+        if self.headers().contains_key(ETAG) {
+            return self;
+        }
+
+        let hash = match &self.body().inner {
+            InnerBody::Bytes(bytes) => fnv1a(bytes),
+            _ => return self,
+        };
+
+        let value = HeaderValue::try_from(format!("\"{hash:016x}\"")).unwrap();
+        self.headers_mut().insert(ETAG, value);
+        self
+    }
+}
+
+/// Whether the entity tags in `values` (from `If-Match` or `If-None-Match`) match `etag`
+///
+/// `If-Match` uses strong comparison, `If-None-Match` uses weak comparison. The header may
+/// be repeated, and each value may contain a comma-separated list of tags.
+fn matches<'a>(
+    values: impl IntoIterator<Item = &'a HeaderValue>,
+    etag: Option<&HeaderValue>,
+    weak: bool,
+) -> bool {
+    let mut tags = values
+        .into_iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty());
+
+    let etag = match etag.map(|etag| etag.to_str()) {
+        Some(Ok(etag)) => etag,
+        _ => return tags.any(|tag| tag == "*"),
+    };
+
+    let (etag, etag_weak) = match etag.strip_prefix("W/") {
+        Some(etag) => (etag, true),
+        None => (etag, false),
+    };
+
+    // Strong comparison never matches a weak tag
+    let strong_only = etag_weak && !weak;
+    tags.any(|tag| match tag.strip_prefix("W/") {
+        _ if tag == "*" => true,
+        _ if strong_only => false,
+        Some(tag) => weak && tag == etag,
+        None => tag == etag,
+    })
+}
+
+/// The 64-bit FNV-1a hash of `bytes`
+///
+/// Unlike the standard library's hashers, the result is specified, so entity tags don't
+/// change between builds or Rust versions.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+fn date(value: Option<&HeaderValue>) -> Option<SystemTime> {
+    httpdate::parse_http_date(value?.to_str().ok()?).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use http::HeaderValue;
+
+    use super::{fnv1a, matches};
+
+    #[test]
+    fn entity_tags() {
+        for (values, etag, weak, expected) in [
+            (&["*"][..], None, false, true),
+            (&["\"a\""], None, false, false),
+            (&["\"a\""], Some("\"a\""), false, true),
+            (&["\"b\", \"a\""], Some("\"a\""), false, true),
+            (&["\"b\"", "\"a\""], Some("\"a\""), false, true),
+            (&["\"b\"", "\"c\", \"a\""], Some("\"a\""), false, true),
+            (&["\"b\"", "\"c\""], Some("\"a\""), false, false),
+            (&["\"b\""], Some("\"a\""), false, false),
+            (&["W/\"a\""], Some("\"a\""), false, false),
+            (&["\"a\""], Some("W/\"a\""), false, false),
+            (&["W/\"a\""], Some("\"a\""), true, true),
+            (&["\"a\""], Some("W/\"a\""), true, true),
+            (&["W/\"a\""], Some("W/\"a\""), true, true),
+            (&["W/\"b\""], Some("W/\"a\""), true, false),
+            (&["W/\"b\"", "W/\"a\""], Some("W/\"a\""), true, true),
+        ] {
+            let values = values
+                .iter()
+                .map(|value| HeaderValue::from_static(value))
+                .collect::<Vec<_>>();
+            let etag = etag.map(HeaderValue::from_static);
+            assert_eq!(
+                matches(&values, etag.as_ref(), weak),
+                expected,
+                "{values:?} {etag:?} {weak}"
+            );
+        }
+    }
+
+    #[test]
+    fn stable_hash() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::VecDeque;
+use std::io::{self, SeekFrom};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use http_body::{Frame, SizeHint};
+use tokio::fs::File;
+use tokio::io::AsyncSeek;
+use tokio_util::io::poll_read_buf;
+
+use super::range::Part;
+
+/// Streams ranges of a file in chunks, optionally interleaved with in-memory data
+pub(super) struct FileBody {
+    file: File,
+    /// Length of the complete file
+    len: u64,
+    /// Current offset of the file cursor
+    position: u64,
+    seeking: bool,
+    parts: VecDeque<Part>,
+    /// Read buffer, which reclaims its space once the chunks split off from it are dropped
+    buf: BytesMut,
+}
+
+impl FileBody {
+    pub(super) fn new(file: File, len: u64) -> Self {
+        Self {
+            file,
+            len,
+            position: 0,
+            seeking: false,
+            parts: VecDeque::from([Part::Range(0..len)]),
+            buf: BytesMut::new(),
+        }
+    }
+
+    /// Stream `parts` instead of the complete file
+    pub(super) fn select(self, parts: VecDeque<Part>) -> Self {
+        Self { parts, ..self }
+    }
+
+    pub(super) fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+impl http_body::Body for FileBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        loop {
+            let range = match this.parts.front_mut() {
+                None => return Poll::Ready(None),
+                Some(Part::Range(range)) if !range.is_empty() => range,
+                Some(Part::Range(_)) => {
+                    this.parts.pop_front();
+                    continue;
+                }
+                Some(Part::Data(_)) => match this.parts.pop_front() {
+                    Some(Part::Data(data)) => return Poll::Ready(Some(Ok(Frame::data(data)))),
+                    _ => unreachable!(),
+                },
+            };
+
+            if this.position != range.start {
+                if !this.seeking {
+                    Pin::new(&mut this.file).start_seek(SeekFrom::Start(range.start))?;
+                    this.seeking = true;
+                }
+
+                this.position = ready!(Pin::new(&mut this.file).poll_complete(cx))?;
+                this.seeking = false;
+                continue;
+            }
+
+            let len = Ord::min(range.end - range.start, CHUNK_SIZE as u64) as usize;
+            this.buf.reserve(len);
+            let mut buf = (&mut this.buf).limit(len);
+            let read = ready!(poll_read_buf(Pin::new(&mut this.file), cx, &mut buf))?;
+            if read == 0 {
+                // The file was truncated while we were sending it
+                return Poll::Ready(Some(Err(io::ErrorKind::UnexpectedEof.into())));
+            }
+
+            range.start += read as u64;
+            this.position += read as u64;
+            return Poll::Ready(Some(Ok(Frame::data(this.buf.split().freeze()))));
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.parts.iter().all(|part| part.len() == 0)
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.parts.iter().map(Part::len).sum())
+    }
+}
+
+const CHUNK_SIZE: usize = 64 * 1024;
@@ -0,0 +1,323 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::io;
+use std::ops::Range;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use http::header::{
+    HeaderMap, HeaderValue, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+    IF_RANGE, LAST_MODIFIED, RANGE,
+};
+use http::request::Parts;
+use http::{Method, Response, StatusCode};
+use http_body::{Frame, SizeHint};
+
+use super::{Body, InnerBody};
+
+/// Serve the byte ranges requested in a `Range` header
+pub trait RangeResponse {
+    /// Restrict the response to the byte ranges requested by `req`
+    ///
+    /// Applies to successful responses to `GET` requests with a body of known length: one
+    /// constructed from in-memory data or a static file. Such responses are marked with
+    /// `Accept-Ranges: bytes`. A single range yields a `206 Partial Content` response with
+    /// a `Content-Range` header, while multiple ranges are sent as `multipart/byteranges`.
+    /// If none of the ranges overlap the body, the response is replaced by a
+    /// `416 Range Not Satisfiable` response.
+    ///
+    /// If the request has an `If-Range` header that doesn't match the response's `ETag` or
+    /// `Last-Modified` header, the complete response is returned. Malformed `Range` headers
+    /// are ignored.
+    fn ranged(self, req: &Parts) -> Response<Body>;
+}
+
+impl RangeResponse for Response<Body> {
+    fn ranged(self, req: &Parts) -> Response<Body> {
+        if self.status() != StatusCode::OK || !matches!(req.method, Method::GET | Method::HEAD) {
+            return self;
+        }
+
+        let len = match self.body().range_len() {
+            Some(len) => len,
+            None => return self,
+        };
+
+        let (mut parts, body) = self.into_parts();
+        parts
+            .headers
+            .insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        if req.method != Method::GET || !if_range(&req.headers, &parts.headers) {
+            return Response::from_parts(parts, body);
+        }
+
+        let ranges = match req.headers.get(RANGE).and_then(|v| v.to_str().ok()) {
+            Some(value) => match parse(value, len) {
+                Some(ranges) => ranges,
+                None => return Response::from_parts(parts, body),
+            },
+            None => return Response::from_parts(parts, body),
+        };
+
+        let (status, headers) = (&mut parts.status, &mut parts.headers);
+        let body = match ranges.as_slice() {
+            [] => {
+                *status = StatusCode::RANGE_NOT_SATISFIABLE;
+                headers.remove(CONTENT_LENGTH);
+                headers.insert(CONTENT_RANGE, content_range(None, len));
+                Body::empty()
+            }
+            [range] => {
+                *status = StatusCode::PARTIAL_CONTENT;
+                headers.insert(CONTENT_LENGTH, HeaderValue::from(range.end - range.start));
+                headers.insert(CONTENT_RANGE, content_range(Some(range), len));
+                body.select(VecDeque::from([Part::Range(range.clone())]))
+            }
+            ranges => {
+                // The boundary must not occur in the parts, so it can't be predictable
+                let mut random = [0; 16];
+                if getrandom::getrandom(&mut random).is_err() {
+                    // Servers are always allowed to ignore the `Range` header
+                    return Response::from_parts(parts, body);
+                }
+
+                let boundary = random.iter().fold(String::with_capacity(32), |mut s, b| {
+                    let _ = write!(s, "{b:02x}");
+                    s
+                });
+                let content_type = headers.remove(CONTENT_TYPE);
+                let mut segments = VecDeque::with_capacity(ranges.len() * 2 + 1);
+                for (i, range) in ranges.iter().enumerate() {
+                    let mut header = match i {
+                        0 => format!("--{boundary}\r\n"),
+                        _ => format!("\r\n--{boundary}\r\n"),
+                    };
+
+                    if let Some(content_type) = content_type.as_ref() {
+                        if let Ok(content_type) = content_type.to_str() {
+                            header.push_str(&format!("Content-Type: {content_type}\r\n"));
+                        }
+                    }
+
+                    let content_range = content_range(Some(range), len);
+                    let content_range = content_range.to_str().unwrap();
+                    header.push_str(&format!("Content-Range: {content_range}\r\n\r\n"));
+                    segments.push_back(Part::Data(Bytes::from(header)));
+                    segments.push_back(Part::Range(range.clone()));
+                }
+                segments.push_back(Part::Data(Bytes::from(format!("\r\n--{boundary}--\r\n"))));
+
+                *status = StatusCode::PARTIAL_CONTENT;
+                let len = segments.iter().map(Part::len).sum::<u64>();
+                headers.insert(CONTENT_LENGTH, HeaderValue::from(len));
+                headers.insert(
+                    CONTENT_TYPE,
+                    HeaderValue::try_from(format!("multipart/byteranges; boundary={boundary}"))
+                        .unwrap(),
+                );
+                body.select(segments)
+            }
+        };
+
+        Response::from_parts(parts, body)
+    }
+}
+
+impl Body {
+    /// The length of the body, if it supports selecting byte ranges
+    fn range_len(&self) -> Option<u64> {
+        #[cfg(any(
+            feature = "brotli",
+            feature = "gzip",
+            feature = "zlib",
+            feature = "zstd"
+        ))]
+        if self.encoder.is_some() {
+            return None;
+        }
+
+        match &self.inner {
+            InnerBody::Bytes(bytes) => Some(bytes.len() as u64),
+            #[cfg(feature = "static")]
+            InnerBody::File(file) => Some(file.len()),
+            _ => None,
+        }
+    }
+
+    /// Replace the body with the given `parts`, selecting ranges from the current body
+    fn select(self, parts: VecDeque<Part>) -> Body {
+        match self.inner {
+            InnerBody::Bytes(bytes) => Body::stream(Chunks(
+                parts
+                    .into_iter()
+                    .map(|part| match part {
+                        Part::Data(data) => data,
+                        Part::Range(range) => bytes.slice(range.start as usize..range.end as usize),
+                    })
+                    .collect(),
+            )),
+            #[cfg(feature = "static")]
+            InnerBody::File(file) => Body::new(InnerBody::File(file.select(parts)), false),
+            _ => unreachable!("selecting ranges from unsupported body"),
+        }
+    }
+}
+
+/// Whether the `If-Range` precondition (if any) holds for a response with the given headers
+fn if_range(req: &HeaderMap, rsp: &HeaderMap) -> bool {
+    let value = match req.get(IF_RANGE) {
+        Some(value) => value,
+        None => return true,
+    };
+
+    match value.as_bytes() {
+        // Weak entity tags never match
+        [b'W', b'/', ..] => false,
+        [b'"', ..] => rsp.get(ETAG) == Some(value),
+        // Dates have to be an exact match of the `Last-Modified` header
+        _ => rsp.get(LAST_MODIFIED) == Some(value),
+    }
+}
+
+/// Parse the value of a `Range` header into satisfiable ranges within a body of `len` bytes
+///
+/// Returns `None` if the header is malformed or uses a unit other than bytes. Overlapping
+/// and adjacent ranges are coalesced.
+fn parse(value: &str, len: u64) -> Option<Vec<Range<u64>>> {
+    let (unit, specs) = value.split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+
+    let (mut ranges, mut empty) = (Vec::new(), true);
+    for spec in specs.split(',').map(str::trim) {
+        if spec.is_empty() {
+            continue;
+        }
+
+        empty = false;
+        let range = match spec.split_once('-')? {
+            ("", suffix) => len.saturating_sub(int(suffix)?)..len,
+            (first, "") => int(first)?..len,
+            (first, last) => match (int(first)?, int(last)?) {
+                (first, last) if last >= first => first..Ord::min(last.saturating_add(1), len),
+                _ => return None,
+            },
+        };
+
+        if range.start < range.end {
+            ranges.push(range);
+        }
+    }
+
+    if empty || ranges.len() > MAX_RANGES {
+        return None;
+    }
+
+    ranges.sort_by_key(|range| range.start);
+    let mut coalesced = Vec::<Range<u64>>::with_capacity(ranges.len());
+    for range in ranges {
+        match coalesced.last_mut() {
+            Some(last) if range.start <= last.end => last.end = Ord::max(last.end, range.end),
+            _ => coalesced.push(range),
+        }
+    }
+
+    Some(coalesced)
+}
+
+fn int(s: &str) -> Option<u64> {
+    match !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+        true => s.parse().ok(),
+        false => None,
+    }
+}
+
+fn content_range(range: Option<&Range<u64>>, len: u64) -> HeaderValue {
+    let value = match range {
+        Some(range) => format!("bytes {}-{}/{len}", range.start, range.end - 1),
+        None => format!("bytes */{len}"),
+    };
+
+    HeaderValue::try_from(value).unwrap()
+}
+
+/// A part of a (partial) response body
+pub(super) enum Part {
+    /// Data to send as-is
+    Data(Bytes),
+    /// A range of the original body
+    Range(Range<u64>),
+}
+
+impl Part {
+    pub(super) fn len(&self) -> u64 {
+        match self {
+            Self::Data(data) => data.len() as u64,
+            Self::Range(range) => range.end - range.start,
+        }
+    }
+}
+
+/// Streams a sequence of in-memory chunks
+struct Chunks(VecDeque<Bytes>);
+
+impl http_body::Body for Chunks {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Poll::Ready(
+            self.get_mut()
+                .0
+                .pop_front()
+                .map(|data| Ok(Frame::data(data))),
+        )
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.0.iter().map(|data| data.len() as u64).sum())
+    }
+}
+
+/// Requests with more ranges are served in full
+const MAX_RANGES: usize = 32;
+
+#[cfg(test)]
+mod test {
+    use super::parse;
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn ranges() {
+        for (value, expected) in [
+            ("bytes=0-499", Some(vec![0..500])),
+            ("bytes=500-", Some(vec![500..1000])),
+            ("bytes=-200", Some(vec![800..1000])),
+            ("bytes=-2000", Some(vec![0..1000])),
+            ("bytes=900-1999", Some(vec![900..1000])),
+            ("Bytes = 0-0 , -1", Some(vec![0..1, 999..1000])),
+            ("bytes=0-10,5-20,21-30", Some(vec![0..31])),
+            ("bytes=500-599,0-99", Some(vec![0..100, 500..600])),
+            ("bytes=1000-", Some(vec![])),
+            ("bytes=-0", Some(vec![])),
+            ("bytes=10-5", None),
+            ("bytes=", None),
+            ("bytes=a-b", None),
+            ("bytes=+1-2", None),
+            ("bytes=0-1-2", None),
+            ("items=0-10", None),
+            ("0-10", None),
+        ] {
+            assert_eq!(parse(value, 1000), expected, "{value}");
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use std::fmt::Write;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::Stream;
+use http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use http::request::Parts;
+use http::{HeaderValue, Response, StatusCode};
+use http_body::Frame;
+use pin_project::pin_project;
+use tokio::time::{sleep, Instant, Sleep};
+
+use super::Body;
+use crate::application::{Application, FromContext, IntoResponse, PathState};
+
+/// A `text/event-stream` response that sends events from a `Stream`
+///
+/// Events are serialized as described by the HTML EventSource specification. When no
+/// event has been sent for a while, a comment line is sent to keep the connection alive.
+/// The response ends when the stream ends.
+///
+/// ```ignore
+/// #[handler(GET)]
+/// async fn updates(app: &App, last: LastEventId) -> Result<Sse<impl Stream<Item = Event>>, Error> {
+///     Ok(Sse::new(app.updates_since(last.as_str())))
+/// }
+/// ```
+pub struct Sse<S> {
+    stream: S,
+    keep_alive: Option<Duration>,
+}
+
+impl<S: Stream<Item = Event> + Send + 'static> Sse<S> {
+    /// Send the events from `stream`, with a keepalive comment every 15s by default
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            keep_alive: Some(DEFAULT_KEEP_ALIVE),
+        }
+    }
+
+    /// Send a keepalive comment if no event has been sent for `interval` (default: 15s)
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = Some(interval);
+        self
+    }
+
+    /// Don't send keepalive comments
+    pub fn without_keep_alive(mut self) -> Self {
+        self.keep_alive = None;
+        self
+    }
+
+    /// Build the response, with `Content-Type: text/event-stream` and `Cache-Control: no-cache`
+    ///
+    /// This is also used by the `IntoResponse` implementation, which allows returning an
+    /// `Sse` from a handler directly.
+    pub fn into_response(self) -> Response<Body> {
+        let Self { stream, keep_alive } = self;
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, HeaderValue::from_static(EVENT_STREAM))
+            .header(CACHE_CONTROL, HeaderValue::from_static("no-cache"))
+            .body(Body::stream(SseBody {
+                stream,
+                keep_alive: keep_alive.map(|interval| KeepAlive {
+                    sleep: Box::pin(sleep(interval)),
+                    interval,
+                }),
+            }))
+            .unwrap()
+    }
+}
+
+impl<A, S> IntoResponse<A> for Sse<S>
+where
+    A: Application<ResponseBody = Body>,
+    S: Stream<Item = Event> + Send + 'static,
+{
+    fn into_response(self, _: &A, _: &Parts) -> Response<Body> {
+        Sse::into_response(self)
+    }
+}
+
+#[pin_project]
+struct SseBody<S> {
+    #[pin]
+    stream: S,
+    keep_alive: Option<KeepAlive>,
+}
+
+impl<S: Stream<Item = Event>> http_body::Body for SseBody<S> {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        match this.stream.poll_next(cx) {
+            Poll::Ready(Some(event)) => {
+                if let Some(keep_alive) = this.keep_alive {
+                    keep_alive.reset();
+                }
+                return Poll::Ready(Some(Ok(Frame::data(event.into_bytes()))));
+            }
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => {}
+        }
+
+        let Some(keep_alive) = this.keep_alive else {
+            return Poll::Pending;
+        };
+
+        match keep_alive.sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                keep_alive.reset();
+                Poll::Ready(Some(Ok(Frame::data(Bytes::from_static(b":\n\n")))))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+struct KeepAlive {
+    sleep: Pin<Box<Sleep>>,
+    interval: Duration,
+}
+
+impl KeepAlive {
+    fn reset(&mut self) {
+        self.sleep.as_mut().reset(Instant::now() + self.interval);
+    }
+}
+
+/// A single server-sent event
+#[derive(Clone, Debug, Default)]
+pub struct Event {
+    buf: String,
+}
+
+impl Event {
+    /// Create an event with the given `data`
+    ///
+    /// Data containing line breaks (`\r\n`, `\r` or `\n`) is split over multiple `data`
+    /// fields; clients will join them back together with `\n`.
+    pub fn new(data: &str) -> Self {
+        Self::default().data(data)
+    }
+
+    /// Create an event carrying `value` serialized as JSON
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize>(value: &T) -> Result<Self, serde_json::Error> {
+        Ok(Self::default().data(&serde_json::to_string(value)?))
+    }
+
+    /// Append `data` to the event's data
+    pub fn data(mut self, data: &str) -> Self {
+        for line in lines(data) {
+            self.field("data", line);
+        }
+        self
+    }
+
+    /// Set the event type (the client dispatches to listeners for this type)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` contains a line break, since that would inject other fields into
+    /// the event. Names derived from untrusted input should be validated first.
+    pub fn event(mut self, name: &str) -> Self {
+        assert!(!name.contains(['\n', '\r']), "invalid event name");
+        self.field("event", name);
+        self
+    }
+
+    /// Set the event ID, sent back by the client in the `Last-Event-ID` header on reconnect
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` contains a line break or a null character, since clients would
+    /// misinterpret it. IDs derived from untrusted input should be validated first.
+    pub fn id(mut self, id: &str) -> Self {
+        assert!(!id.contains(['\n', '\r', '\0']), "invalid event ID");
+        self.field("id", id);
+        self
+    }
+
+    /// Tell the client how long to wait before reconnecting
+    pub fn retry(mut self, delay: Duration) -> Self {
+        writeln!(self.buf, "retry: {}", delay.as_millis()).unwrap();
+        self
+    }
+
+    /// Add a comment, which clients ignore
+    pub fn comment(mut self, comment: &str) -> Self {
+        for line in lines(comment) {
+            writeln!(self.buf, ":{line}").unwrap();
+        }
+        self
+    }
+
+    fn field(&mut self, name: &str, value: &str) {
+        writeln!(self.buf, "{name}: {value}").unwrap();
+    }
+
+    fn into_bytes(mut self) -> Bytes {
+        self.buf.push('\n');
+        Bytes::from(self.buf)
+    }
+}
+
+/// The value of the `Last-Event-ID` request header, if any
+///
+/// Clients send this when reconnecting, so the stream can resume after the last event
+/// the client received.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LastEventId(Option<String>);
+
+impl LastEventId {
+    pub fn as_str(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
+    pub fn into_inner(self) -> Option<String> {
+        self.0
+    }
+}
+
+impl<'a, A: Application> FromContext<'a, A> for LastEventId {
+    fn from_context(
+        _: &'a Arc<A>,
+        req: &'a Parts,
+        _: &mut PathState,
+        _: &mut Option<A::RequestBody>,
+    ) -> Result<Self, A::Error> {
+        let value = req
+            .headers
+            .get("last-event-id")
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| !value.is_empty());
+        Ok(Self(value.map(str::to_owned)))
+    }
+}
+
+/// Split `s` on line breaks as defined by the EventSource specification
+///
+/// Unlike `str::lines()`, this treats a lone `\r` as a line break and keeps an empty
+/// last line, so that `"a\n"` yields `["a", ""]`.
+fn lines(s: &str) -> impl Iterator<Item = &str> {
+    let mut rest = Some(s);
+    std::iter::from_fn(move || {
+        let s = rest?;
+        match s.find(['\r', '\n']) {
+            Some(i) => {
+                let skip = match s[i..].starts_with("\r\n") {
+                    true => 2,
+                    false => 1,
+                };
+                rest = Some(&s[i + skip..]);
+                Some(&s[..i])
+            }
+            None => rest.take(),
+        }
+    })
+}
+
+const EVENT_STREAM: &str = "text/event-stream";
+const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::Event;
+
+    #[test]
+    fn serialize() {
+        let event = Event::new("first\nsecond")
+            .event("update")
+            .id("42")
+            .retry(Duration::from_secs(3));
+        assert_eq!(
+            event.into_bytes(),
+            "data: first\ndata: second\nevent: update\nid: 42\nretry: 3000\n\n"
+        );
+
+        let event = Event::default().comment("hello");
+        assert_eq!(event.into_bytes(), ":hello\n\n");
+
+        let event = Event::new("");
+        assert_eq!(event.into_bytes(), "data: \n\n");
+    }
+
+    #[test]
+    fn line_breaks() {
+        // A lone CR must not allow injecting other fields
+        let event = Event::new("x\rid: 666\r\nevent: evil\nretry: 1");
+        assert_eq!(
+            event.into_bytes(),
+            "data: x\ndata: id: 666\ndata: event: evil\ndata: retry: 1\n\n"
+        );
+
+        let event = Event::new("trailing\n");
+        assert_eq!(event.into_bytes(), "data: trailing\ndata: \n\n");
+
+        let event = Event::default().comment("a\rb");
+        assert_eq!(event.into_bytes(), ":a\n:b\n\n");
+    }
+}
@@ -1,36 +1,79 @@
-use std::any::Any;
 use std::convert::Infallible;
 use std::error::Error as StdError;
-use std::future::{pending, Future, Pending};
+#[cfg(feature = "tower")]
+use std::future::poll_fn;
+use std::future::{Future, Pending};
 use std::io;
-use std::marker::Send;
+use std::marker::{PhantomData, Send};
 use std::net::SocketAddr;
-use std::panic::AssertUnwindSafe;
 use std::pin::{pin, Pin};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use futures_util::future::{CatchUnwind, FutureExt, Map};
+use futures_util::future::{join_all, FutureExt};
+#[cfg(feature = "http3")]
+use http::header::ALT_SVC;
 use http::request::Parts;
-use http::{Request, Response, StatusCode};
+#[cfg(feature = "http3")]
+use http::uri::Scheme;
+#[cfg(feature = "http3")]
+use http::HeaderValue;
+use http::{Request, Response};
 use hyper::body::{Body, Incoming};
 use hyper::service::Service;
 use hyper_util::rt::{TokioExecutor, TokioIo};
 use hyper_util::server::conn::auto::Builder;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::watch;
-use tokio::time::sleep;
-use tracing::{debug, error, info};
+use tokio::time::{sleep, timeout};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
 use super::Application;
-use crate::application::{Context, FromContext, PathState};
+use crate::application::{handle_unwind, Error, FromContext, PathState};
+#[cfg(feature = "tower")]
+use crate::tower::AppService;
 
 pub use hyper::body;
 
-pub struct Server<A, F> {
-    listener: TcpListener,
-    app: Arc<A>,
+mod forwarded;
+pub use forwarded::{Forwarded, InvalidNetwork, IpNet, TrustedProxies};
+
+mod access_log;
+pub use access_log::{AccessLog, LogFormat};
+
+#[cfg(feature = "http3")]
+mod http3;
+#[cfg(feature = "http3")]
+pub use http3::Http3Listener;
+
+mod instrument;
+pub use instrument::TrackedBody;
+use instrument::{Instruments, RequestHead, Tracker};
+
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+
+mod proxy;
+pub use proxy::ProxyProtocol;
+
+mod request_id;
+pub use request_id::RequestId;
+
+pub struct Server<A: Application, F, H = Arc<A>> {
+    listeners: Vec<TcpListener>,
+    #[cfg(feature = "http3")]
+    http3: Option<http3::Listeners<A>>,
+    handler: H,
+    app: PhantomData<fn() -> A>,
     signal: Option<F>,
+    shutdown: ShutdownHandle,
+    drain_timeout: Option<Duration>,
+    proxy_protocol: ProxyProtocol,
+    proxy_header_timeout: Duration,
+    trusted_proxies: Option<Arc<TrustedProxies>>,
+    instruments: Instruments,
 }
 
 impl<A: Application> Server<A, Pending<()>> {
@@ -40,49 +83,353 @@ impl<A: Application> Server<A, Pending<()>> {
 
     pub fn new(listener: TcpListener, app: A) -> Server<A, Pending<()>> {
         Server {
-            listener,
-            app: Arc::new(app),
+            listeners: vec![listener],
+            #[cfg(feature = "http3")]
+            http3: None,
+            handler: Arc::new(app),
+            app: PhantomData,
             signal: None,
+            shutdown: ShutdownHandle::new(),
+            drain_timeout: None,
+            proxy_protocol: ProxyProtocol::default(),
+            proxy_header_timeout: DEFAULT_PROXY_HEADER_TIMEOUT,
+            trusted_proxies: None,
+            instruments: Instruments::default(),
         }
     }
 }
 
-impl<A: Application> Server<A, Pending<()>> {
-    pub fn with_graceful_shutdown<F: Future<Output = ()>>(self, signal: F) -> Server<A, F> {
-        let Server { listener, app, .. } = self;
+impl<A: Application, H> Server<A, Pending<()>, H> {
+    pub fn with_graceful_shutdown<F: Future<Output = ()>>(self, signal: F) -> Server<A, F, H> {
+        let Server {
+            listeners,
+            #[cfg(feature = "http3")]
+            http3,
+            handler,
+            shutdown,
+            drain_timeout,
+            proxy_protocol,
+            proxy_header_timeout,
+            trusted_proxies,
+            instruments,
+            ..
+        } = self;
         Server {
-            listener,
-            app,
+            listeners,
+            #[cfg(feature = "http3")]
+            http3,
+            handler,
+            app: PhantomData,
             signal: Some(signal),
+            shutdown,
+            drain_timeout,
+            proxy_protocol,
+            proxy_header_timeout,
+            trusted_proxies,
+            instruments,
         }
     }
 }
 
-impl<A, F> Server<A, F>
+#[cfg(feature = "tower")]
+impl<A: Application + Sync + 'static, F> Server<A, F> {
+    /// Wrap the `Application` in the given tower `layer`
+    ///
+    /// Requests pass through the layer after the server has added its request extensions
+    /// (like `ClientAddr` and `RequestId`). Use `tower::ServiceBuilder` to apply multiple
+    /// layers. Panics in the layer's services are not caught.
+    pub fn layer<L>(self, layer: L) -> Server<A, F, Layered<L::Service, A::RequestBody>>
+    where
+        L: tower::Layer<AppService<A>>,
+    {
+        let Server {
+            listeners,
+            #[cfg(feature = "http3")]
+            http3,
+            handler,
+            signal,
+            shutdown,
+            drain_timeout,
+            proxy_protocol,
+            proxy_header_timeout,
+            trusted_proxies,
+            instruments,
+            ..
+        } = self;
+        Server {
+            listeners,
+            #[cfg(feature = "http3")]
+            http3,
+            handler: Layered {
+                service: layer.layer(AppService::new(handler)),
+                body: PhantomData,
+            },
+            app: PhantomData,
+            signal,
+            shutdown,
+            drain_timeout,
+            proxy_protocol,
+            proxy_header_timeout,
+            trusted_proxies,
+            instruments,
+        }
+    }
+}
+
+impl<A: Application, F, H> Server<A, F, H> {
+    /// Also accept connections from the given `listener`
+    ///
+    /// All listeners serve the same `Application` and are shut down together. Handlers
+    /// can use the `Listener` extractor to find out which listener a request arrived on.
+    pub fn with_listener(mut self, listener: TcpListener) -> Self {
+        self.listeners.push(listener);
+        self
+    }
+
+    /// Also serve HTTP/3 requests from the given QUIC `listener`
+    ///
+    /// HTTP/3 listeners get indexes after the TCP listeners (see `Listener::index()`).
+    /// Responses from the TCP listeners to requests made over TLS advertise the HTTP/3
+    /// listeners' ports in an `Alt-Svc` header, unless the handler already set one. Since
+    /// the TCP listeners don't terminate TLS, this relies on the scheme reported by a
+    /// trusted reverse proxy (see `trusted_proxies()`). Request bodies are passed to the
+    /// `Application` as a `mendes::Body`.
+    #[cfg(feature = "http3")]
+    pub fn with_http3(mut self, listener: Http3Listener) -> Self
+    where
+        A::RequestBody: From<crate::Body>,
+    {
+        self.http3
+            .get_or_insert_with(|| http3::Listeners::new(A::RequestBody::from))
+            .listeners
+            .push(listener);
+        self
+    }
+
+    /// Get a handle that can be used to initiate a graceful shutdown
+    ///
+    /// This is an alternative to passing a future to `with_graceful_shutdown()`.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Forcibly close remaining connections if they haven't finished within `timeout`
+    ///
+    /// The timeout starts when the server stops accepting new connections. By default,
+    /// the server will wait indefinitely for connections to finish.
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = Some(timeout);
+        self
+    }
+
+    /// Look for a PROXY protocol header at the start of accepted connections
+    ///
+    /// If a header is found, the source address from the header is used as the `ClientAddr`
+    /// and the destination address as the `LocalAddr`.
+    pub fn proxy_protocol(mut self, mode: ProxyProtocol) -> Self {
+        self.proxy_protocol = mode;
+        self
+    }
+
+    /// Close connections that haven't sent a complete PROXY protocol header within `timeout`
+    ///
+    /// Only applies if `proxy_protocol()` is enabled. Defaults to 10 seconds.
+    pub fn proxy_header_timeout(mut self, timeout: Duration) -> Self {
+        self.proxy_header_timeout = timeout;
+        self
+    }
+
+    /// Trust forwarding headers from reverse proxies in the given networks
+    ///
+    /// The `Forwarded` extractor will yield the client, scheme and host as reported by
+    /// the trusted proxies.
+    pub fn trusted_proxies(mut self, proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = Some(Arc::new(proxies));
+        self
+    }
+
+    /// Log a line for every request once its response has been sent
+    pub fn access_log(mut self, log: AccessLog) -> Self {
+        self.instruments.access_log = Some(Arc::new(log));
+        self
+    }
+
+    /// Record connection and request metrics in `metrics`
+    ///
+    /// Requests are labeled with their method, response status and the `route!` handler
+    /// that produced the response (if any).
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.instruments.metrics = Some(metrics);
+        self
+    }
+}
+
+impl<A, F, H> Server<A, F, H>
 where
-    A: Application + Sync + 'static,
-    A::RequestBody: From<Incoming>,
-    <<A as Application>::ResponseBody as Body>::Data: Send,
-    <<A as Application>::ResponseBody as Body>::Error: StdError + Send + Sync,
-    <A as Application>::ResponseBody: From<&'static str> + Send,
+    A: Application,
+    H: RequestHandler<RequestBody = A::RequestBody>,
+    H::RequestBody: From<Incoming>,
+    H::ResponseBody: Send,
+    <H::ResponseBody as Body>::Data: Send,
+    <H::ResponseBody as Body>::Error: StdError + Send + Sync,
+    H::Error: StdError + Send + Sync,
     F: Future<Output = ()> + Send + 'static,
 {
     pub async fn serve(self) -> Result<(), io::Error> {
         let Server {
-            listener,
-            app,
+            listeners,
+            #[cfg(feature = "http3")]
+            http3,
+            handler,
             signal,
+            shutdown,
+            drain_timeout,
+            proxy_protocol,
+            proxy_header_timeout,
+            trusted_proxies,
+            instruments,
+            ..
         } = self;
 
-        let (listener_state, conn_state) = states(signal);
-        let mut shutting_down = pin!(async move {
-            match listener_state.shutting_down {
-                Some(shutting_down) => shutting_down.closed().await,
-                None => pending().await,
+        let signal = signal.map(|future| {
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                future.await;
+                shutdown.shutdown();
+            })
+        });
+
+        #[cfg(feature = "http3")]
+        let alt_svc = match &http3 {
+            Some(http3) => Some(http3.alt_svc()?),
+            None => None,
+        };
+
+        let (listener_state, conn_state) = states(&shutdown);
+        let mut acceptors = Vec::with_capacity(listeners.len());
+        for (index, listener) in listeners.into_iter().enumerate() {
+            let info = Listener {
+                index,
+                addr: listener.local_addr()?,
+            };
+
+            acceptors.push(
+                Acceptor {
+                    listener,
+                    info,
+                    shutting_down: listener_state.shutting_down.clone(),
+                    proxy_protocol,
+                    proxy_header_timeout,
+                    trusted_proxies: trusted_proxies.clone(),
+                    instruments: instruments.clone(),
+                    state: conn_state.clone(),
+                    handler: handler.clone(),
+                    #[cfg(feature = "http3")]
+                    alt_svc: alt_svc.clone(),
+                }
+                .run()
+                .boxed(),
+            );
+        }
+
+        #[cfg(feature = "http3")]
+        if let Some(http3) = http3 {
+            let tcp_listeners = acceptors.len();
+            for (i, listener) in http3.listeners.into_iter().enumerate() {
+                let info = Listener {
+                    index: tcp_listeners + i,
+                    addr: listener.local_addr()?,
+                };
+
+                acceptors.push(
+                    http3::Acceptor {
+                        endpoint: listener.endpoint,
+                        info,
+                        shutting_down: listener_state.shutting_down.clone(),
+                        trusted_proxies: trusted_proxies.clone(),
+                        instruments: instruments.clone(),
+                        state: conn_state.clone(),
+                        handler: handler.clone(),
+                        body: http3.body,
+                    }
+                    .run()
+                    .boxed(),
+                );
+            }
+        }
+        join_all(acceptors).await;
+        info!("shutdown signal received, draining...");
+        if let Some(signal) = signal {
+            signal.abort();
+        }
+
+        let ListenerState {
+            task_monitor,
+            force_close,
+            ..
+        } = listener_state;
+        drop(conn_state);
+        let tasks = task_monitor.receiver_count();
+        if tasks > 0 {
+            debug!("waiting for {tasks} task(s) to finish");
+        }
+
+        let drain = task_monitor.closed();
+        match drain_timeout {
+            Some(drain_timeout) => {
+                if timeout(drain_timeout, drain).await.is_err() {
+                    let tasks = task_monitor.receiver_count();
+                    warn!("drain timeout expired, closing {tasks} remaining connection(s)");
+                    force_close.send_replace(true);
+                    task_monitor.closed().await;
+                }
             }
+            None => drain.await,
         }
-        .fuse());
 
+        Ok(())
+    }
+}
+
+struct Acceptor<H> {
+    listener: TcpListener,
+    info: Listener,
+    shutting_down: ShutdownSignal,
+    proxy_protocol: ProxyProtocol,
+    proxy_header_timeout: Duration,
+    trusted_proxies: Option<Arc<TrustedProxies>>,
+    instruments: Instruments,
+    state: ConnectionState,
+    handler: H,
+    #[cfg(feature = "http3")]
+    alt_svc: Option<HeaderValue>,
+}
+
+impl<H: RequestHandler> Acceptor<H>
+where
+    H::RequestBody: From<Incoming>,
+    H::ResponseBody: Send,
+    <H::ResponseBody as Body>::Data: Send,
+    <H::ResponseBody as Body>::Error: StdError + Send + Sync,
+    H::Error: StdError + Send + Sync,
+{
+    async fn run(self) {
+        let Acceptor {
+            listener,
+            info,
+            mut shutting_down,
+            proxy_protocol,
+            proxy_header_timeout,
+            trusted_proxies,
+            instruments,
+            state,
+            handler,
+            #[cfg(feature = "http3")]
+            alt_svc,
+        } = self;
+
+        let mut shutting_down = pin!(async move { shutting_down.wait().await }.fuse());
         loop {
             let (stream, addr) = tokio::select! {
                 res = listener.accept() => {
@@ -95,7 +442,7 @@ where
                             }
 
                             // Sleep for a bit to see if the error clears
-                            error!(%error, "error accepting connection");
+                            error!(listener = %info.addr, %error, "error accepting connection");
                             sleep(Duration::from_secs(1)).await;
                             continue;
                         }
@@ -104,106 +451,142 @@ where
                 _ = shutting_down.as_mut() => break,
             };
 
-            debug!("connection accepted from {addr}");
+            debug!("connection accepted from {addr} on {}", info.addr);
             tokio::spawn(
                 Connection {
                     stream,
                     addr,
-                    state: conn_state.clone(),
-                    app: app.clone(),
+                    listener: info,
+                    proxy_protocol,
+                    proxy_header_timeout,
+                    trusted_proxies: trusted_proxies.clone(),
+                    instruments: instruments.clone(),
+                    state: state.clone(),
+                    handler: handler.clone(),
+                    #[cfg(feature = "http3")]
+                    alt_svc: alt_svc.clone(),
                 }
                 .run(),
             );
         }
-
-        let ListenerState { task_monitor, .. } = listener_state;
-        drop(conn_state);
-        drop(listener);
-        if let Some(task_monitor) = task_monitor {
-            let tasks = task_monitor.receiver_count();
-            if tasks > 0 {
-                debug!("waiting for {tasks} task(s) to finish");
-            }
-            task_monitor.closed().await;
-        }
-
-        Ok(())
     }
 }
 
-fn states(
-    future: Option<impl Future<Output = ()> + Send + 'static>,
-) -> (ListenerState, ConnectionState) {
-    let future = match future {
-        Some(future) => future,
-        None => return (ListenerState::default(), ConnectionState::default()),
-    };
-
-    let (shutting_down, signal) = watch::channel(()); // Axum: `signal_tx`, `signal_rx`
-    let shutting_down = Arc::new(shutting_down);
-    tokio::spawn(async move {
-        future.await;
-        info!("shutdown signal received, draining...");
-        drop(signal);
-    });
-
+fn states(shutdown: &ShutdownHandle) -> (ListenerState, ConnectionState) {
     let (task_monitor, task_done) = watch::channel(()); // Axum: `close_tx`, `close_rx`
+    let (force_close, force_closed) = watch::channel(false);
     (
         ListenerState {
-            shutting_down: Some(shutting_down.clone()),
-            task_monitor: Some(task_monitor),
+            shutting_down: shutdown.signal(),
+            task_monitor,
+            force_close,
         },
         ConnectionState {
-            shutting_down: Some(shutting_down),
-            _task_done: Some(task_done),
+            shutting_down: shutdown.signal(),
+            force_closed,
+            _task_done: task_done,
         },
     )
 }
 
-#[derive(Default)]
 struct ListenerState {
-    /// If `Some` and `closed()`, the server is shutting down
-    shutting_down: Option<Arc<watch::Sender<()>>>,
-    /// If `Some`, `receiver_count()` can be used whether any connections are still going
+    /// Resolves when the server is shutting down
+    shutting_down: ShutdownSignal,
+    /// `receiver_count()` can be used whether any connections are still going
     ///
     /// Call `closed().await` to wait for all connections to finish.
-    task_monitor: Option<watch::Sender<()>>,
+    task_monitor: watch::Sender<()>,
+    /// Send `true` to close remaining connections without waiting for them to finish
+    force_close: watch::Sender<bool>,
 }
 
-struct Connection<A> {
+struct Connection<H> {
     stream: TcpStream,
     addr: SocketAddr,
+    listener: Listener,
+    proxy_protocol: ProxyProtocol,
+    proxy_header_timeout: Duration,
+    trusted_proxies: Option<Arc<TrustedProxies>>,
+    instruments: Instruments,
     state: ConnectionState,
-    app: Arc<A>,
+    handler: H,
+    #[cfg(feature = "http3")]
+    alt_svc: Option<HeaderValue>,
 }
 
-impl<A: Application + 'static> Connection<A>
+impl<H: RequestHandler> Connection<H>
 where
-    A::RequestBody: From<Incoming>,
-    A::ResponseBody: From<&'static str> + Send,
-    <A::ResponseBody as Body>::Data: Send,
-    <A::ResponseBody as Body>::Error: StdError + Send + Sync,
+    H::RequestBody: From<Incoming>,
+    H::ResponseBody: Send,
+    <H::ResponseBody as Body>::Data: Send,
+    <H::ResponseBody as Body>::Error: StdError + Send + Sync,
+    H::Error: StdError + Send + Sync,
 {
     async fn run(self) {
         let Connection {
             stream,
             addr,
+            listener,
+            proxy_protocol,
+            proxy_header_timeout,
+            trusted_proxies,
+            instruments,
             state,
-            app,
+            handler,
+            #[cfg(feature = "http3")]
+            alt_svc,
         } = self;
 
-        let service = ConnectionService { addr, app };
+        let local = match stream.local_addr() {
+            Ok(local) => local,
+            Err(error) => {
+                error!(%addr, %error, "failed to get local address for connection");
+                return;
+            }
+        };
+
+        let header = timeout(proxy_header_timeout, proxy::accept(stream, proxy_protocol));
+        let (stream, addr, local) = match header.await {
+            Ok(Ok((Some(proxied), stream))) => {
+                debug!("connection from {addr} proxied for {}", proxied.source);
+                (stream, proxied.source, proxied.destination)
+            }
+            Ok(Ok((None, stream))) => (stream, addr, local),
+            Ok(Err(error)) => {
+                warn!(%addr, %error, "failed to accept connection");
+                return;
+            }
+            Err(_) => {
+                warn!(%addr, "timed out waiting for PROXY protocol header");
+                return;
+            }
+        };
+
+        instruments.connection_opened();
+        let service = ConnectionService {
+            addr,
+            local,
+            listener,
+            trusted_proxies,
+            instruments: instruments.clone(),
+            shutdown: state.shutting_down.clone(),
+            handler,
+            #[cfg(feature = "http3")]
+            alt_svc,
+        };
 
         let builder = Builder::new(TokioExecutor::new());
         let stream = TokioIo::new(stream);
         let mut conn = pin!(builder.serve_connection_with_upgrades(stream, service));
-        let mut shutting_down = pin!(async move {
-            match state.shutting_down {
-                Some(shutting_down) => shutting_down.closed().await,
-                None => pending().await,
-            }
-        }
-        .fuse());
+        let ConnectionState {
+            mut shutting_down,
+            mut force_closed,
+            _task_done,
+        } = state;
+        let mut shutting_down = pin!(async move { shutting_down.wait().await }.fuse());
+        let mut force_closed = pin!(async move {
+            let _ = force_closed.wait_for(|closed| *closed).await;
+        });
 
         loop {
             tokio::select! {
@@ -217,75 +600,249 @@ where
                     debug!("shutting down connection to {addr}");
                     conn.as_mut().graceful_shutdown();
                 }
+                _ = force_closed.as_mut() => {
+                    debug!("closing connection to {addr}");
+                    break;
+                }
             }
         }
 
+        instruments.connection_closed();
         debug!("connection to {addr} closed");
     }
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone)]
 struct ConnectionState {
-    /// If `Some` and `closed()`, the server is shutting down; don't accept new requests
-    shutting_down: Option<Arc<watch::Sender<()>>>,
+    /// Resolves when the server is shutting down; don't accept new requests
+    shutting_down: ShutdownSignal,
+    /// Resolves to `true` when the connection should be closed immediately
+    force_closed: watch::Receiver<bool>,
     /// Keeping this around will allow the server to wait for the connection to finish
-    _task_done: Option<watch::Receiver<()>>,
+    _task_done: watch::Receiver<()>,
+}
+
+/// Initiates a graceful shutdown of the `Server` it was obtained from
+#[derive(Clone, Debug)]
+pub struct ShutdownHandle(Arc<watch::Sender<bool>>);
+
+impl ShutdownHandle {
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(watch::Sender::new(false)))
+    }
+
+    /// Stop accepting new connections and wait for existing connections to finish
+    pub fn shutdown(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub(crate) fn signal(&self) -> ShutdownSignal {
+        ShutdownSignal(self.0.subscribe())
+    }
 }
 
-pub struct ConnectionService<A> {
+/// Tracks whether the server is shutting down
+///
+/// Long-lived handlers (like server-sent event streams or WebSockets) can use this to
+/// finish cleanly when the server is shutting down, instead of delaying the shutdown.
+#[derive(Clone, Debug)]
+pub struct ShutdownSignal(watch::Receiver<bool>);
+
+impl ShutdownSignal {
+    /// Wait until the server is shutting down
+    pub async fn wait(&mut self) {
+        // If the sender has been dropped, the server is gone
+        let _ = self.0.wait_for(|shutting_down| *shutting_down).await;
+    }
+
+    /// Whether the server is shutting down
+    pub fn is_shutting_down(&self) -> bool {
+        *self.0.borrow()
+    }
+}
+
+impl<'a, A: Application> FromContext<'a, A> for ShutdownSignal {
+    fn from_context(
+        _: &'a Arc<A>,
+        req: &'a Parts,
+        _: &mut PathState,
+        _: &mut Option<A::RequestBody>,
+    ) -> Result<Self, A::Error> {
+        // Inserted by the `Server` and `TestClient`, but not by `AppService`
+        req.extensions
+            .get::<ShutdownSignal>()
+            .cloned()
+            .ok_or_else(|| Error::ExtensionMissing("ShutdownSignal").into())
+    }
+}
+
+pub struct ConnectionService<H> {
     addr: SocketAddr,
-    app: Arc<A>,
+    local: SocketAddr,
+    listener: Listener,
+    trusted_proxies: Option<Arc<TrustedProxies>>,
+    instruments: Instruments,
+    shutdown: ShutdownSignal,
+    handler: H,
+    #[cfg(feature = "http3")]
+    alt_svc: Option<HeaderValue>,
 }
 
-impl<A: Application + 'static> Service<Request<Incoming>> for ConnectionService<A>
+impl<H: RequestHandler> Service<Request<Incoming>> for ConnectionService<H>
 where
-    A::RequestBody: From<Incoming>,
-    A::ResponseBody: From<&'static str>,
+    H::RequestBody: From<Incoming>,
 {
-    type Response = Response<A::ResponseBody>;
-    type Error = Infallible;
-    type Future = UnwindSafeHandlerFuture<Self::Response, Self::Error>;
+    type Response = Response<TrackedBody<H::ResponseBody>>;
+    type Error = H::Error;
+    type Future = ServiceFuture<H>;
+
+    fn call(&self, req: Request<Incoming>) -> Self::Future {
+        self.dispatch(req.map(H::RequestBody::from))
+    }
+}
 
-    fn call(&self, mut req: Request<Incoming>) -> Self::Future {
+impl<H: RequestHandler> ConnectionService<H> {
+    fn dispatch(&self, mut req: Request<H::RequestBody>) -> ServiceFuture<H> {
+        let start = Instant::now();
         req.extensions_mut().insert(ClientAddr(self.addr));
-        let cx = Context::new(self.app.clone(), req.map(|body| body.into()));
-        AssertUnwindSafe(A::handle(cx))
-            .catch_unwind()
-            .map(panic_response)
+        req.extensions_mut().insert(LocalAddr(self.local));
+        req.extensions_mut().insert(self.listener);
+        req.extensions_mut().insert(self.shutdown.clone());
+        if let Some(trusted) = &self.trusted_proxies {
+            let forwarded = Forwarded::resolve(self.addr, req.uri(), req.headers(), trusted);
+            req.extensions_mut().insert(forwarded);
+        }
+
+        // HTTP/3 always uses TLS, so only advertise it to clients that reached us over TLS.
+        // The TCP listeners don't terminate TLS, so this relies on a trusted proxy's report.
+        #[cfg(feature = "http3")]
+        let alt_svc = {
+            let tls = match req.extensions().get::<Forwarded>() {
+                Some(forwarded) => forwarded.scheme() == &Scheme::HTTPS,
+                None => req.uri().scheme() == Some(&Scheme::HTTPS),
+            };
+            self.alt_svc.clone().filter(|_| tls)
+        };
+
+        let id = RequestId::from_headers(req.headers());
+        let span = info_span!(
+            "request",
+            id = %id,
+            method = %req.method(),
+            path = req.uri().path(),
+            status = tracing::field::Empty,
+        );
+        req.extensions_mut().insert(id.clone());
+
+        // Keep what the instruments need from the request head
+        let instruments = self.instruments.clone();
+        let (parts, body) = req.into_parts();
+        let head = RequestHead::new(&parts, start, &instruments);
+        let handle = self.handler.handle(Request::from_parts(parts, body));
+        Box::pin(async move {
+            let mut rsp = handle.instrument(span.clone()).await?;
+            span.record("status", rsp.status().as_u16());
+            // Keep an ID the handler set deliberately
+            rsp.headers_mut()
+                .entry(&request_id::X_REQUEST_ID)
+                .or_insert_with(|| id.header_value().clone());
+            #[cfg(feature = "http3")]
+            if let Some(alt_svc) = alt_svc {
+                rsp.headers_mut().entry(ALT_SVC).or_insert(alt_svc);
+            }
+            let tracker = head.map(|head| Tracker::new(head, start, &rsp, &instruments));
+            Ok(rsp.map(|body| TrackedBody::new(body, tracker)))
+        })
     }
 }
 
-type UnwindSafeHandlerFuture<T, E> = Map<
-    CatchUnwind<AssertUnwindSafe<Pin<Box<dyn Future<Output = T> + Send>>>>,
-    fn(Result<T, Box<dyn Any + Send + 'static>>) -> Result<T, E>,
+type ServiceFuture<H> = Pin<
+    Box<
+        dyn Future<
+                Output = Result<
+                    Response<TrackedBody<<H as RequestHandler>::ResponseBody>>,
+                    <H as RequestHandler>::Error,
+                >,
+            > + Send,
+    >,
 >;
 
-fn panic_response<B: From<&'static str>>(
-    result: Result<Response<B>, Box<dyn Any + Send + 'static>>,
-) -> Result<Response<B>, Infallible> {
-    #[allow(unused_variables)] // Depends on features
-    let error = match result {
-        Ok(rsp) => return Ok(rsp),
-        Err(e) => e,
-    };
+/// Handles requests after the `Server` has added its request extensions
+///
+/// This is implemented for `Arc<A>` (where `A` is an `Application`), which catches panics
+/// from request handlers (see `Application::panic_response()`). With the `tower` feature,
+/// it is also implemented for `Layered` services (see `Server::layer()`).
+pub trait RequestHandler: Clone + Send + Sync + 'static {
+    type RequestBody;
+    type ResponseBody: Body;
+    type Error;
+
+    #[allow(clippy::type_complexity)]
+    fn handle(
+        &self,
+        req: Request<Self::RequestBody>,
+    ) -> Pin<Box<dyn Future<Output = Result<Response<Self::ResponseBody>, Self::Error>> + Send>>;
+}
+
+impl<A: Application + Sync + 'static> RequestHandler for Arc<A>
+where
+    A::ResponseBody: From<&'static str>,
+{
+    type RequestBody = A::RequestBody;
+    type ResponseBody = A::ResponseBody;
+    type Error = Infallible;
 
-    #[cfg(feature = "tracing")]
+    fn handle(
+        &self,
+        req: Request<Self::RequestBody>,
+    ) -> Pin<Box<dyn Future<Output = Result<Response<Self::ResponseBody>, Self::Error>> + Send>>
     {
-        let panic_str = if let Some(s) = error.downcast_ref::<String>() {
-            Some(s.as_str())
-        } else if let Some(s) = error.downcast_ref::<&'static str>() {
-            Some(*s)
-        } else {
-            Some("no error")
-        };
+        let app = self.clone();
+        Box::pin(async move { Ok(handle_unwind(app, req).await) })
+    }
+}
+
+/// A tower `Service` wrapped around an `Application` (see `Server::layer()`)
+#[cfg(feature = "tower")]
+pub struct Layered<S, B> {
+    service: S,
+    body: PhantomData<fn(B)>,
+}
 
-        tracing::error!("caught panic from request handler: {:?}", panic_str);
+#[cfg(feature = "tower")]
+impl<S: Clone, B> Clone for Layered<S, B> {
+    fn clone(&self) -> Self {
+        Self {
+            service: self.service.clone(),
+            body: PhantomData,
+        }
     }
+}
 
-    Ok(Response::builder()
-        .status(StatusCode::INTERNAL_SERVER_ERROR)
-        .body("Caught panic".into())
-        .unwrap())
+#[cfg(feature = "tower")]
+impl<S, B, R> RequestHandler for Layered<S, B>
+where
+    S: tower::Service<Request<B>, Response = Response<R>> + Clone + Send + Sync + 'static,
+    S::Future: Send,
+    S::Error: Send + 'static,
+    B: Send + 'static,
+    R: Body + 'static,
+{
+    type RequestBody = B;
+    type ResponseBody = R;
+    type Error = S::Error;
+
+    fn handle(
+        &self,
+        req: Request<Self::RequestBody>,
+    ) -> Pin<Box<dyn Future<Output = Result<Response<Self::ResponseBody>, Self::Error>> + Send>>
+    {
+        let mut service = self.service.clone();
+        Box::pin(async move {
+            poll_fn(|cx| service.poll_ready(cx)).await?;
+            service.call(req).await
+        })
+    }
 }
 
 impl<'a, A: Application<RequestBody = Incoming>> FromContext<'a, A> for Incoming {
@@ -309,9 +866,11 @@ impl<'a, A: Application> FromContext<'a, A> for ClientAddr {
         _: &mut PathState,
         _: &mut Option<A::RequestBody>,
     ) -> Result<Self, A::Error> {
-        // This is safe because we insert ClientAddr into the request extensions
-        // unconditionally in the ConnectionService::call method.
-        Ok(req.extensions.get::<ClientAddr>().copied().unwrap())
+        // Inserted by the `Server` and `TestClient`, but not by `AppService`
+        req.extensions
+            .get::<ClientAddr>()
+            .copied()
+            .ok_or_else(|| Error::ExtensionMissing("ClientAddr").into())
     }
 }
 
@@ -331,3 +890,77 @@ impl From<SocketAddr> for ClientAddr {
         Self(addr)
     }
 }
+
+impl<'a, A: Application> FromContext<'a, A> for LocalAddr {
+    fn from_context(
+        _: &'a Arc<A>,
+        req: &'a Parts,
+        _: &mut PathState,
+        _: &mut Option<A::RequestBody>,
+    ) -> Result<Self, A::Error> {
+        // Inserted by the `Server` and `TestClient`, but not by `AppService`
+        req.extensions
+            .get::<LocalAddr>()
+            .copied()
+            .ok_or_else(|| Error::ExtensionMissing("LocalAddr").into())
+    }
+}
+
+/// The address the client connected to
+///
+/// If the connection started with a PROXY protocol header, this is the original
+/// destination address from the header rather than the server's own address.
+#[derive(Debug, Clone, Copy)]
+pub struct LocalAddr(SocketAddr);
+
+impl std::ops::Deref for LocalAddr {
+    type Target = SocketAddr;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<SocketAddr> for LocalAddr {
+    fn from(addr: SocketAddr) -> Self {
+        Self(addr)
+    }
+}
+
+impl<'a, A: Application> FromContext<'a, A> for Listener {
+    fn from_context(
+        _: &'a Arc<A>,
+        req: &'a Parts,
+        _: &mut PathState,
+        _: &mut Option<A::RequestBody>,
+    ) -> Result<Self, A::Error> {
+        // Inserted by the `Server` and `TestClient`, but not by `AppService`
+        req.extensions
+            .get::<Listener>()
+            .copied()
+            .ok_or_else(|| Error::ExtensionMissing("Listener").into())
+    }
+}
+
+/// The listener on which the request's connection was accepted
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Listener {
+    pub(crate) index: usize,
+    pub(crate) addr: SocketAddr,
+}
+
+impl Listener {
+    /// The position of the listener in the order they were added to the `Server`
+    ///
+    /// The listener passed to `Server::new()` (or bound by `Server::bind()`) has index 0.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The local address the listener is bound to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+const DEFAULT_PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(10);
@@ -0,0 +1,258 @@
+use std::fmt::{self, Write};
+use std::net::IpAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use http::header::{HeaderName, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, REFERER, USER_AGENT};
+use http::request::Parts;
+use http::HeaderMap;
+use tracing::info;
+
+use super::instrument::Tracker;
+use super::{ClientAddr, Forwarded, RequestId};
+
+/// Configuration for per-request access logs
+///
+/// Access log entries are emitted as `INFO` events with the `mendes::access` target once
+/// the response body has been sent (or the client went away).
+#[derive(Clone, Debug)]
+pub struct AccessLog {
+    format: LogFormat,
+    headers: Vec<HeaderName>,
+    redact: Vec<HeaderName>,
+}
+
+impl AccessLog {
+    /// Log requests in the given `format`
+    ///
+    /// The `Authorization`, `Cookie` and `Proxy-Authorization` headers are redacted by default.
+    pub fn new(format: LogFormat) -> Self {
+        Self {
+            format,
+            headers: Vec::new(),
+            redact: vec![AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION],
+        }
+    }
+
+    /// Include the value of the request header `name` in log entries
+    ///
+    /// For the Common and Combined formats, header values are appended as quoted strings
+    /// in the order in which they were added.
+    pub fn header(mut self, name: HeaderName) -> Self {
+        self.headers.push(name);
+        self
+    }
+
+    /// Replace the value of the request header `name` with `[redacted]`
+    pub fn redact(mut self, name: HeaderName) -> Self {
+        self.redact.push(name);
+        self
+    }
+
+    /// Collect the request details needed for the log entry
+    pub(crate) fn request(&self, req: &Parts, start: Instant) -> RequestInfo {
+        let client = match req.extensions.get::<Forwarded>() {
+            Some(forwarded) => Some(forwarded.ip()),
+            None => req.extensions.get::<ClientAddr>().map(|addr| addr.ip()),
+        };
+
+        let target = match req.uri.path_and_query() {
+            Some(pq) => pq.as_str().to_owned(),
+            None => req.uri.to_string(),
+        };
+
+        RequestInfo {
+            time: SystemTime::now() - start.elapsed(),
+            client,
+            id: req.extensions.get::<RequestId>().cloned(),
+            target,
+            version: req.version,
+            user_agent: self.value(&req.headers, &USER_AGENT),
+            referer: self.value(&req.headers, &REFERER),
+            headers: self
+                .headers
+                .iter()
+                .map(|name| (name.clone(), self.value(&req.headers, name)))
+                .collect(),
+        }
+    }
+
+    fn value(&self, headers: &HeaderMap, name: &HeaderName) -> Option<String> {
+        let value = headers.get(name)?;
+        Some(match self.redact.contains(name) {
+            true => REDACTED.to_owned(),
+            false => String::from_utf8_lossy(value.as_bytes()).into_owned(),
+        })
+    }
+
+    pub(crate) fn log(&self, tracker: &Tracker, req: &RequestInfo, duration: Duration) {
+        match self.format {
+            LogFormat::Common | LogFormat::Combined => {
+                let mut line = String::new();
+                self.write_line(&mut line, tracker, req).unwrap();
+                info!(target: "mendes::access", "{line}");
+            }
+            LogFormat::Structured => {
+                let mut headers = String::new();
+                for (name, value) in &req.headers {
+                    if !headers.is_empty() {
+                        headers.push(' ');
+                    }
+                    let value = value.as_deref().unwrap_or_default();
+                    write!(headers, "{name}={value:?}").unwrap();
+                }
+
+                info!(
+                    target: "mendes::access",
+                    id = req.id.as_ref().map(RequestId::as_str),
+                    method = %tracker.method,
+                    path = %req.target,
+                    route = tracker.route,
+                    status = tracker.status.as_u16(),
+                    bytes = tracker.bytes,
+                    duration_ms = duration.as_secs_f64() * 1000.0,
+                    client = req.client.map(tracing::field::display),
+                    user_agent = req.user_agent.as_deref(),
+                    referer = req.referer.as_deref(),
+                    headers = (!headers.is_empty()).then_some(headers.as_str()),
+                    "request completed",
+                );
+            }
+        }
+    }
+
+    fn write_line(&self, out: &mut String, tracker: &Tracker, req: &RequestInfo) -> fmt::Result {
+        match req.client {
+            Some(ip) => write!(out, "{ip} - - [")?,
+            None => write!(out, "- - - [")?,
+        }
+        write_time(out, req.time)?;
+        write!(out, "] \"{} ", tracker.method)?;
+        escape(out, &req.target)?;
+        write!(out, " {:?}\" {} ", req.version, tracker.status.as_u16())?;
+        match tracker.bytes {
+            0 => out.push('-'),
+            bytes => write!(out, "{bytes}")?,
+        }
+
+        if self.format == LogFormat::Combined {
+            for value in [&req.referer, &req.user_agent] {
+                out.push(' ');
+                quoted(out, value.as_deref())?;
+            }
+        }
+
+        for (_, value) in &req.headers {
+            out.push(' ');
+            quoted(out, value.as_deref())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for AccessLog {
+    fn default() -> Self {
+        Self::new(LogFormat::Structured)
+    }
+}
+
+/// The format of access log entries
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum LogFormat {
+    /// The Common Log Format, as used by Apache and nginx
+    Common,
+    /// The Common Log Format, followed by the `Referer` and `User-Agent` headers
+    Combined,
+    /// A `tracing` event with separate fields for the request details
+    #[default]
+    Structured,
+}
+
+/// Request details captured before the handler takes ownership of the request
+pub(crate) struct RequestInfo {
+    time: SystemTime,
+    client: Option<IpAddr>,
+    id: Option<RequestId>,
+    target: String,
+    version: http::Version,
+    user_agent: Option<String>,
+    referer: Option<String>,
+    headers: Vec<(HeaderName, Option<String>)>,
+}
+
+fn quoted(out: &mut String, value: Option<&str>) -> fmt::Result {
+    match value {
+        Some(value) => {
+            out.push('"');
+            escape(out, value)?;
+            out.push('"');
+        }
+        None => out.push_str("\"-\""),
+    }
+    Ok(())
+}
+
+/// Escape quotes, backslashes and non-printable characters like Apache does
+fn escape(out: &mut String, value: &str) -> fmt::Result {
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_ascii_control() => write!(out, "\\x{:02x}", c as u8)?,
+            c => out.push(c),
+        }
+    }
+    Ok(())
+}
+
+/// Write `time` as `10/Oct/2000:13:55:36 +0000`
+fn write_time(out: &mut String, time: SystemTime) -> fmt::Result {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+
+    // Convert days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    write!(
+        out,
+        "{day:02}/{}/{year}:{:02}:{:02}:{:02} +0000",
+        MONTHS[month as usize - 1],
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    )
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const REDACTED: &str = "[redacted]";
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::write_time;
+
+    #[test]
+    fn time() {
+        let mut out = String::new();
+        write_time(&mut out, UNIX_EPOCH + Duration::from_secs(971_186_136)).unwrap();
+        assert_eq!(out, "10/Oct/2000:13:55:36 +0000");
+
+        let mut out = String::new();
+        write_time(&mut out, UNIX_EPOCH + Duration::from_secs(951_782_400)).unwrap();
+        assert_eq!(out, "29/Feb/2000:00:00:00 +0000");
+    }
+}
@@ -0,0 +1,509 @@
+use std::iter::FromIterator;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use http::header::{HeaderName, FORWARDED, HOST};
+use http::request::Parts;
+use http::uri::{Authority, Scheme, Uri};
+use http::HeaderMap;
+use thiserror::Error;
+
+use super::ClientAddr;
+use crate::application::{Application, Error, FromContext, PathState};
+
+/// The client, scheme and host of a request as reported by trusted reverse proxies
+///
+/// Starting from the directly connected peer, this walks the `Forwarded` header (or, if it
+/// is absent, `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host`) from right to
+/// left for as long as the hops are in the `Server`'s `TrustedProxies`. If the peer is not
+/// a trusted proxy, the forwarding headers are ignored.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Forwarded {
+    ip: IpAddr,
+    scheme: Scheme,
+    host: Option<String>,
+}
+
+impl Forwarded {
+    pub(crate) fn resolve(
+        peer: SocketAddr,
+        uri: &Uri,
+        headers: &HeaderMap,
+        trusted: &TrustedProxies,
+    ) -> Self {
+        let mut forwarded = Forwarded {
+            ip: peer.ip().to_canonical(),
+            scheme: uri.scheme().cloned().unwrap_or(Scheme::HTTP),
+            host: match headers.get(HOST).map(|v| v.to_str()) {
+                Some(Ok(host)) => Some(host.to_owned()),
+                _ => uri.authority().map(|a| a.to_string()),
+            },
+        };
+
+        if !trusted.contains(forwarded.ip) {
+            return forwarded;
+        }
+
+        let hops = match headers.contains_key(FORWARDED) {
+            true => forwarded_hops(headers),
+            false => x_forwarded_hops(headers),
+        };
+
+        let hops = match hops {
+            Some(hops) => hops,
+            None => return forwarded,
+        };
+
+        for hop in hops.into_iter().rev() {
+            // The hop was added by a trusted proxy, so the protocol and host it saw can be used.
+            if let Some(scheme) = hop.proto {
+                forwarded.scheme = scheme;
+            }
+            if let Some(host) = hop.host {
+                forwarded.host = Some(host);
+            }
+
+            match hop.node {
+                Some(ip) => forwarded.ip = ip,
+                None => break,
+            }
+
+            if !trusted.contains(forwarded.ip) {
+                break;
+            }
+        }
+
+        forwarded
+    }
+
+    /// The effective client IP address
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    /// The scheme the client used to make the request
+    pub fn scheme(&self) -> &Scheme {
+        &self.scheme
+    }
+
+    /// The host the client requested
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+}
+
+impl<'a, A: Application> FromContext<'a, A> for Forwarded {
+    fn from_context(
+        _: &'a Arc<A>,
+        req: &'a Parts,
+        _: &mut PathState,
+        _: &mut Option<A::RequestBody>,
+    ) -> Result<Self, A::Error> {
+        if let Some(forwarded) = req.extensions.get::<Forwarded>() {
+            return Ok(forwarded.clone());
+        }
+
+        // Inserted by the `Server` and `TestClient`, but not by `AppService`
+        let addr = req
+            .extensions
+            .get::<ClientAddr>()
+            .ok_or(Error::ExtensionMissing("ClientAddr"))?;
+        let trusted = TrustedProxies::default();
+        Ok(Forwarded::resolve(**addr, &req.uri, &req.headers, &trusted))
+    }
+}
+
+/// Parse the `Forwarded` header into hops, from the client side to the server side
+fn forwarded_hops(headers: &HeaderMap) -> Option<Vec<Hop>> {
+    let mut hops = Vec::new();
+    for value in headers.get_all(FORWARDED) {
+        let mut value = value.to_str().ok()?;
+        loop {
+            let mut hop = Hop::default();
+            loop {
+                let (key, rest) = value.split_once('=')?;
+                let (val, rest) = parse_value(rest.trim_start())?;
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "for" => hop.node = parse_node(&val),
+                    "proto" => hop.proto = Some(Scheme::from_str(&val).ok()?),
+                    "host" => hop.host = Some(Authority::from_str(&val).ok()?.to_string()),
+                    _ => {}
+                }
+
+                value = rest.trim_start();
+                match value.as_bytes().first() {
+                    Some(b';') => value = &value[1..],
+                    _ => break,
+                }
+            }
+
+            hops.push(hop);
+            match value.as_bytes().first() {
+                Some(b',') => value = &value[1..],
+                None => break,
+                Some(_) => return None,
+            }
+        }
+    }
+
+    Some(hops)
+}
+
+/// Parse a token or quoted-string, returning the unescaped value and the remaining input
+fn parse_value(s: &str) -> Option<(String, &str)> {
+    let quoted = match s.strip_prefix('"') {
+        Some(quoted) => quoted,
+        None => {
+            let end = s
+                .find(|c: char| c == ';' || c == ',' || c.is_whitespace())
+                .unwrap_or(s.len());
+            let token = &s[..end];
+            return match token.is_empty() {
+                true => None,
+                false => Some((token.to_owned(), &s[end..])),
+            };
+        }
+    };
+
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &quoted[i + 1..])),
+            '\\' => value.push(chars.next()?.1),
+            c => value.push(c),
+        }
+    }
+
+    None
+}
+
+/// Parse the `X-Forwarded-*` headers into hops, from the client side to the server side
+fn x_forwarded_hops(headers: &HeaderMap) -> Option<Vec<Hop>> {
+    let mut hops = list(headers, &X_FORWARDED_FOR)
+        .into_iter()
+        .map(|node| Hop {
+            node: parse_node(node),
+            ..Hop::default()
+        })
+        .collect::<Vec<_>>();
+
+    // Other headers are often set only by the proxy closest to the server, so align
+    // their values with the `X-Forwarded-For` hops from the right.
+    for (hop, proto) in hops
+        .iter_mut()
+        .rev()
+        .zip(list(headers, &X_FORWARDED_PROTO).into_iter().rev())
+    {
+        hop.proto = Some(Scheme::from_str(proto).ok()?);
+    }
+
+    for (hop, host) in hops
+        .iter_mut()
+        .rev()
+        .zip(list(headers, &X_FORWARDED_HOST).into_iter().rev())
+    {
+        hop.host = Some(Authority::from_str(host).ok()?.to_string());
+    }
+
+    Some(hops)
+}
+
+fn list<'h>(headers: &'h HeaderMap, name: &HeaderName) -> Vec<&'h str> {
+    headers
+        .get_all(name)
+        .into_iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Parse a node identifier, yielding `None` for `unknown` or obfuscated identifiers
+fn parse_node(s: &str) -> Option<IpAddr> {
+    if let Ok(ip) = IpAddr::from_str(s) {
+        return Some(ip.to_canonical());
+    } else if let Ok(addr) = SocketAddr::from_str(s) {
+        return Some(addr.ip().to_canonical());
+    }
+
+    // Forwarded requires IPv6 addresses to be bracketed, even without a port
+    let ip = s.strip_prefix('[')?.strip_suffix(']')?;
+    Some(IpAddr::from_str(ip).ok()?.to_canonical())
+}
+
+#[derive(Debug, Default)]
+struct Hop {
+    node: Option<IpAddr>,
+    proto: Option<Scheme>,
+    host: Option<String>,
+}
+
+/// A set of networks from which forwarding headers are trusted
+///
+/// Can be collected from an iterator of `IpNet`s.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    networks: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+    /// Whether the given address is part of a trusted network
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.networks.iter().any(|net| net.contains(ip))
+    }
+}
+
+impl FromIterator<IpNet> for TrustedProxies {
+    fn from_iter<T: IntoIterator<Item = IpNet>>(iter: T) -> Self {
+        Self {
+            networks: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// An IP network in CIDR notation, like `10.0.0.0/8` or `fd00::/8`
+///
+/// A bare address is parsed as a network containing only that address.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    /// Whether the given address is part of this network
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = InvalidNetwork;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+
+        let addr = IpAddr::from_str(addr)
+            .map_err(|_| InvalidNetwork(s.to_owned()))?
+            .to_canonical();
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+
+        let prefix = match prefix {
+            Some(prefix) => match u8::from_str(prefix) {
+                Ok(prefix) if prefix <= max => prefix,
+                _ => return Err(InvalidNetwork(s.to_owned())),
+            },
+            None => max,
+        };
+
+        Ok(Self { addr, prefix })
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("invalid IP network: {0:?}")]
+pub struct InvalidNetwork(String);
+
+static X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+static X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+static X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+
+#[cfg(test)]
+mod test {
+    use http::Request;
+
+    use super::*;
+
+    #[test]
+    fn networks() {
+        let net = IpNet::from_str("10.0.0.0/8").unwrap();
+        assert!(net.contains("10.1.2.3".parse().unwrap()));
+        assert!(net.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!net.contains("11.0.0.1".parse().unwrap()));
+        assert!(!net.contains("::1".parse().unwrap()));
+
+        let net = IpNet::from_str("2001:db8::/32").unwrap();
+        assert!(net.contains("2001:db8:1::1".parse().unwrap()));
+        assert!(!net.contains("2001:db9::1".parse().unwrap()));
+
+        let net = IpNet::from_str("0.0.0.0/0").unwrap();
+        assert!(net.contains("192.0.2.1".parse().unwrap()));
+
+        let net = IpNet::from_str("192.0.2.1").unwrap();
+        assert!(net.contains("192.0.2.1".parse().unwrap()));
+        assert!(!net.contains("192.0.2.2".parse().unwrap()));
+
+        for invalid in ["10.0.0.0/33", "::/129", "10.0.0/8", "10.0.0.0/", "foo"] {
+            assert!(IpNet::from_str(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn untrusted_peer() {
+        let req = request(&[("x-forwarded-for", "192.0.2.1"), ("host", "example.com")]);
+        let forwarded = resolve(peer("198.51.100.1"), &req, &trusted());
+        assert_eq!(forwarded.ip(), "198.51.100.1".parse::<IpAddr>().unwrap());
+        assert_eq!(forwarded.scheme(), &Scheme::HTTP);
+        assert_eq!(forwarded.host(), Some("example.com"));
+    }
+
+    #[test]
+    fn x_forwarded_for() {
+        let req = request(&[
+            ("x-forwarded-for", "203.0.113.7, 192.0.2.1, 10.0.0.2"),
+            ("x-forwarded-proto", "https"),
+            ("x-forwarded-host", "example.com"),
+            ("host", "internal"),
+        ]);
+        let forwarded = resolve(peer("10.0.0.1"), &req, &trusted());
+        assert_eq!(forwarded.ip(), "192.0.2.1".parse::<IpAddr>().unwrap());
+        assert_eq!(forwarded.scheme(), &Scheme::HTTPS);
+        assert_eq!(forwarded.host(), Some("example.com"));
+    }
+
+    #[test]
+    fn x_forwarded_for_multiple_headers() {
+        let req = request(&[
+            ("x-forwarded-for", "192.0.2.1"),
+            ("x-forwarded-for", "10.0.0.3, 10.0.0.2"),
+        ]);
+        let forwarded = resolve(peer("10.0.0.1"), &req, &trusted());
+        assert_eq!(forwarded.ip(), "192.0.2.1".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn x_forwarded_for_all_trusted() {
+        let req = request(&[("x-forwarded-for", "10.0.0.3, 10.0.0.2")]);
+        let forwarded = resolve(peer("10.0.0.1"), &req, &trusted());
+        assert_eq!(forwarded.ip(), "10.0.0.3".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn x_forwarded_for_garbage() {
+        let req = request(&[("x-forwarded-for", "192.0.2.1, garbage, 10.0.0.2")]);
+        let forwarded = resolve(peer("10.0.0.1"), &req, &trusted());
+        assert_eq!(forwarded.ip(), "10.0.0.2".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn x_forwarded_for_ports() {
+        let req = request(&[("x-forwarded-for", "[2001:db8::1]:1234, 192.0.2.1:5678")]);
+        let forwarded = resolve(peer("10.0.0.1"), &req, &trusted());
+        assert_eq!(forwarded.ip(), "192.0.2.1".parse::<IpAddr>().unwrap());
+
+        let req = request(&[("x-forwarded-for", "[2001:db8::1]:1234, 10.0.0.2")]);
+        let forwarded = resolve(peer("10.0.0.1"), &req, &trusted());
+        assert_eq!(forwarded.ip(), "2001:db8::1".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn forwarded() {
+        let req = request(&[
+            (
+                "forwarded",
+                r#"for=203.0.113.7;proto=http, For="[2001:db8:cafe::17]:4711";proto=https;host="example.com:8443""#,
+            ),
+            ("forwarded", "for=10.0.0.2;by=10.0.0.1;proto=http"),
+            // Ignored in favor of the `Forwarded` header
+            ("x-forwarded-for", "198.51.100.1"),
+        ]);
+        let forwarded = resolve(peer("10.0.0.1"), &req, &trusted());
+        assert_eq!(
+            forwarded.ip(),
+            "2001:db8:cafe::17".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(forwarded.scheme(), &Scheme::HTTPS);
+        assert_eq!(forwarded.host(), Some("example.com:8443"));
+    }
+
+    #[test]
+    fn forwarded_unknown() {
+        let req = request(&[(
+            "forwarded",
+            "for=192.0.2.1, for=unknown;proto=https, for=_hidden",
+        )]);
+        let forwarded = resolve(peer("10.0.0.1"), &req, &trusted());
+        assert_eq!(forwarded.ip(), "10.0.0.1".parse::<IpAddr>().unwrap());
+
+        let req = request(&[("forwarded", "for=192.0.2.1, for=unknown;proto=https")]);
+        let forwarded = resolve(peer("10.0.0.1"), &req, &trusted());
+        assert_eq!(forwarded.ip(), "10.0.0.1".parse::<IpAddr>().unwrap());
+        assert_eq!(forwarded.scheme(), &Scheme::HTTPS);
+    }
+
+    #[test]
+    fn forwarded_quoted() {
+        let req = request(&[("forwarded", r#"for="192.0.2.1";host="a\"b""#)]);
+        let forwarded = resolve(peer("10.0.0.1"), &req, &trusted());
+        // The host is invalid, so the header is ignored
+        assert_eq!(forwarded.ip(), "10.0.0.1".parse::<IpAddr>().unwrap());
+
+        let req = request(&[("forwarded", r#"for="192.0.2.1" ; proto="https""#)]);
+        let forwarded = resolve(peer("10.0.0.1"), &req, &trusted());
+        assert_eq!(forwarded.ip(), "192.0.2.1".parse::<IpAddr>().unwrap());
+        assert_eq!(forwarded.scheme(), &Scheme::HTTPS);
+    }
+
+    #[test]
+    fn forwarded_malformed() {
+        for value in [
+            "for=192.0.2.1;",
+            "for",
+            r#"for="192.0.2.1"#,
+            "for=192.0.2.1 for=192.0.2.2",
+            "for=192.0.2.1;proto=not a scheme",
+        ] {
+            let req = request(&[("forwarded", value)]);
+            let forwarded = resolve(peer("10.0.0.1"), &req, &trusted());
+            assert_eq!(
+                forwarded.ip(),
+                "10.0.0.1".parse::<IpAddr>().unwrap(),
+                "{value}"
+            );
+        }
+    }
+
+    fn trusted() -> TrustedProxies {
+        ["10.0.0.0/8", "fd00::/8"]
+            .iter()
+            .map(|s| s.parse())
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn peer(ip: &str) -> SocketAddr {
+        SocketAddr::new(ip.parse().unwrap(), 1234)
+    }
+
+    fn resolve(peer: SocketAddr, req: &Parts, trusted: &TrustedProxies) -> Forwarded {
+        Forwarded::resolve(peer, &req.uri, &req.headers, trusted)
+    }
+
+    fn request(headers: &[(&str, &str)]) -> Parts {
+        let mut builder = Request::builder().uri("/");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(()).unwrap().into_parts().0
+    }
+}
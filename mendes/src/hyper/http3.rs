@@ -0,0 +1,353 @@
+use std::error::Error as StdError;
+use std::fmt::Write;
+use std::future::poll_fn;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::{pin, Pin};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+use bytes::{Buf, Bytes};
+use futures_util::future::FutureExt;
+use h3::error::Code;
+use h3::server::{RequestResolver, RequestStream};
+use http::{HeaderValue, Method, Response};
+use http_body::{Body as HttpBody, Frame};
+use quinn::crypto::rustls::QuicServerConfig;
+use quinn::{Endpoint, VarInt};
+use tokio::task::JoinSet;
+use tracing::{debug, error, warn};
+
+use super::instrument::Instruments;
+use super::{
+    ConnectionService, ConnectionState, Listener, RequestHandler, ShutdownSignal, TrustedProxies,
+};
+use crate::application::Application;
+use crate::body::Body;
+
+/// A UDP socket accepting HTTP/3 connections over QUIC
+///
+/// Pass it to `Server::with_http3()` to serve the `Application` over HTTP/3, alongside
+/// the server's TCP listeners.
+pub struct Http3Listener {
+    pub(super) endpoint: Endpoint,
+}
+
+impl Http3Listener {
+    /// Bind a UDP socket to `addr`, using the given TLS configuration
+    ///
+    /// This can be the same `rustls` configuration used to terminate TLS for the TCP
+    /// listeners; the ALPN protocols are replaced with `h3`. The configuration must
+    /// support TLS 1.3 with the `TLS13_AES_128_GCM_SHA256` cipher suite, as QUIC
+    /// requires. Must be called from within a tokio runtime.
+    pub fn bind(addr: SocketAddr, tls: &rustls::ServerConfig) -> io::Result<Self> {
+        let mut tls = tls.clone();
+        tls.alpn_protocols = vec![ALPN.to_vec()];
+        let crypto = QuicServerConfig::try_from(tls)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+        let config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+        Ok(Self {
+            endpoint: Endpoint::server(config, addr)?,
+        })
+    }
+
+    /// The local address the listener is bound to
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.endpoint.local_addr()
+    }
+}
+
+/// The HTTP/3 listeners configured for a `Server`
+pub(super) struct Listeners<A: Application> {
+    pub(super) listeners: Vec<Http3Listener>,
+    /// Converts HTTP/3 request bodies into the `Application`'s `RequestBody`
+    pub(super) body: fn(Body) -> A::RequestBody,
+}
+
+impl<A: Application> Listeners<A> {
+    pub(super) fn new(body: fn(Body) -> A::RequestBody) -> Self {
+        Self {
+            listeners: Vec::new(),
+            body,
+        }
+    }
+
+    /// The `Alt-Svc` header value advertising the HTTP/3 listeners
+    pub(super) fn alt_svc(&self) -> io::Result<HeaderValue> {
+        let mut value = String::new();
+        for listener in &self.listeners {
+            if !value.is_empty() {
+                value.push_str(", ");
+            }
+            let port = listener.local_addr()?.port();
+            write!(value, "h3=\":{port}\"; ma={ALT_SVC_MAX_AGE}").unwrap();
+        }
+        Ok(HeaderValue::try_from(value).unwrap())
+    }
+}
+
+pub(super) struct Acceptor<H: RequestHandler> {
+    pub(super) endpoint: Endpoint,
+    pub(super) info: Listener,
+    pub(super) shutting_down: ShutdownSignal,
+    pub(super) trusted_proxies: Option<Arc<TrustedProxies>>,
+    pub(super) instruments: Instruments,
+    pub(super) state: ConnectionState,
+    pub(super) handler: H,
+    pub(super) body: fn(Body) -> H::RequestBody,
+}
+
+impl<H: RequestHandler> Acceptor<H>
+where
+    H::ResponseBody: Send,
+    <H::ResponseBody as HttpBody>::Data: Send,
+    <H::ResponseBody as HttpBody>::Error: StdError + Send + Sync,
+    H::Error: StdError + Send + Sync,
+{
+    pub(super) async fn run(self) {
+        let Acceptor {
+            endpoint,
+            info,
+            mut shutting_down,
+            trusted_proxies,
+            instruments,
+            state,
+            handler,
+            body,
+        } = self;
+
+        let mut shutting_down = pin!(async move { shutting_down.wait().await }.fuse());
+        loop {
+            let incoming = tokio::select! {
+                incoming = endpoint.accept() => match incoming {
+                    Some(incoming) => incoming,
+                    None => break,
+                },
+                _ = shutting_down.as_mut() => break,
+            };
+
+            let addr = incoming.remote_address();
+            debug!("QUIC connection accepted from {addr} on {}", info.addr);
+            let service = ConnectionService {
+                addr,
+                local: info.addr,
+                listener: info,
+                trusted_proxies: trusted_proxies.clone(),
+                instruments: instruments.clone(),
+                shutdown: state.shutting_down.clone(),
+                handler: handler.clone(),
+                alt_svc: None,
+            };
+
+            tokio::spawn(
+                Connection {
+                    incoming,
+                    service: Arc::new(service),
+                    state: state.clone(),
+                    body,
+                }
+                .run(),
+            );
+        }
+
+        // Refuse new connections, but keep the endpoint around for existing ones
+        endpoint.set_server_config(None);
+    }
+}
+
+struct Connection<H: RequestHandler> {
+    incoming: quinn::Incoming,
+    service: Arc<ConnectionService<H>>,
+    state: ConnectionState,
+    body: fn(Body) -> H::RequestBody,
+}
+
+impl<H: RequestHandler> Connection<H>
+where
+    H::ResponseBody: Send,
+    <H::ResponseBody as HttpBody>::Data: Send,
+    <H::ResponseBody as HttpBody>::Error: StdError + Send + Sync,
+    H::Error: StdError + Send + Sync,
+{
+    async fn run(self) {
+        let Connection {
+            incoming,
+            service,
+            state,
+            body,
+        } = self;
+
+        let addr = service.addr;
+        let quic = match incoming.await {
+            Ok(quic) => quic,
+            Err(error) => {
+                warn!(%addr, %error, "failed to accept QUIC connection");
+                return;
+            }
+        };
+
+        let mut conn =
+            match h3::server::Connection::new(h3_quinn::Connection::new(quic.clone())).await {
+                Ok(conn) => conn,
+                Err(error) => {
+                    warn!(%addr, %error, "failed to establish HTTP/3 connection");
+                    return;
+                }
+            };
+
+        service.instruments.connection_opened();
+        let ConnectionState {
+            mut shutting_down,
+            mut force_closed,
+            _task_done,
+        } = state;
+        let mut shutting_down = pin!(async move { shutting_down.wait().await }.fuse());
+        let mut force_closed = pin!(async move {
+            let _ = force_closed.wait_for(|closed| *closed).await;
+        });
+
+        let mut requests = JoinSet::new();
+        let mut closing = false;
+        loop {
+            tokio::select! {
+                result = conn.accept() => match result {
+                    Ok(Some(resolver)) => {
+                        requests.spawn(request(resolver, service.clone(), body));
+                    }
+                    Ok(None) => break,
+                    Err(error) => {
+                        if !error.is_h3_no_error() {
+                            error!(%addr, %error, "failed to serve HTTP/3 connection");
+                        }
+                        break;
+                    }
+                },
+                Some(_) = requests.join_next(), if !requests.is_empty() => {
+                    if closing && requests.is_empty() {
+                        break;
+                    }
+                }
+                _ = shutting_down.as_mut(), if !closing => {
+                    debug!("shutting down HTTP/3 connection to {addr}");
+                    closing = true;
+                    // Send a GOAWAY frame so the client stops sending new requests
+                    if let Err(error) = conn.shutdown(0).await {
+                        debug!(%addr, %error, "failed to send GOAWAY");
+                    }
+                    if requests.is_empty() {
+                        break;
+                    }
+                }
+                _ = force_closed.as_mut() => {
+                    debug!("closing HTTP/3 connection to {addr}");
+                    requests.abort_all();
+                    break;
+                }
+            }
+        }
+
+        quic.close(VarInt::from_u32(Code::H3_NO_ERROR.value() as u32), b"");
+        service.instruments.connection_closed();
+        debug!("HTTP/3 connection to {addr} closed");
+    }
+}
+
+async fn request<H: RequestHandler>(
+    resolver: RequestResolver<h3_quinn::Connection, Bytes>,
+    service: Arc<ConnectionService<H>>,
+    body: fn(Body) -> H::RequestBody,
+) where
+    H::ResponseBody: Send,
+    <H::ResponseBody as HttpBody>::Data: Send,
+    <H::ResponseBody as HttpBody>::Error: StdError + Send + Sync,
+    H::Error: StdError + Send + Sync,
+{
+    let addr = service.addr;
+    let (req, stream) = match resolver.resolve_request().await {
+        Ok(resolved) => resolved,
+        Err(error) => {
+            debug!(%addr, %error, "failed to receive HTTP/3 request");
+            return;
+        }
+    };
+
+    let (mut send, recv) = stream.split();
+    let head = req.method() == Method::HEAD;
+
+    let req = req.map(|()| body(Body::stream(RequestBody { stream: recv })));
+    let rsp = match service.dispatch(req).await {
+        Ok(rsp) => rsp,
+        Err(error) => {
+            error!(%addr, %error, "failed to handle HTTP/3 request");
+            send.stop_stream(Code::H3_INTERNAL_ERROR);
+            return;
+        }
+    };
+
+    let (parts, body) = rsp.into_parts();
+    if let Err(error) = send.send_response(Response::from_parts(parts, ())).await {
+        debug!(%addr, %error, "failed to send HTTP/3 response");
+        return;
+    }
+
+    // Responses to HEAD requests don't have a body
+    let mut body = pin!(body);
+    while let Some(frame) = match head {
+        true => None,
+        false => poll_fn(|cx| body.as_mut().poll_frame(cx)).await,
+    } {
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(error) => {
+                error!(%addr, %error, "failed to produce HTTP/3 response body");
+                send.stop_stream(Code::H3_INTERNAL_ERROR);
+                return;
+            }
+        };
+
+        let result = match frame.into_data() {
+            Ok(mut data) => send.send_data(data.copy_to_bytes(data.remaining())).await,
+            Err(frame) => match frame.into_trailers() {
+                Ok(trailers) => send.send_trailers(trailers).await,
+                Err(_) => continue,
+            },
+        };
+
+        if let Err(error) = result {
+            debug!(%addr, %error, "failed to send HTTP/3 response body");
+            return;
+        }
+    }
+
+    if let Err(error) = send.finish().await {
+        debug!(%addr, %error, "failed to finish HTTP/3 response");
+    }
+}
+
+/// Adapts the receiving half of an HTTP/3 request stream to `http_body::Body`
+struct RequestBody {
+    stream: RequestStream<h3_quinn::RecvStream, Bytes>,
+}
+
+impl HttpBody for RequestBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match ready!(self.get_mut().stream.poll_recv_data(cx)) {
+            Ok(Some(mut data)) => {
+                let data = data.copy_to_bytes(data.remaining());
+                Poll::Ready(Some(Ok(Frame::data(data))))
+            }
+            Ok(None) => Poll::Ready(None),
+            Err(error) => Poll::Ready(Some(Err(io::Error::other(error)))),
+        }
+    }
+}
+
+const ALPN: &[u8] = b"h3";
+/// How long clients may remember the HTTP/3 endpoints advertised in `Alt-Svc` (in seconds)
+const ALT_SVC_MAX_AGE: u32 = 86_400;
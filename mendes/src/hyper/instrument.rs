@@ -0,0 +1,177 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Buf;
+use http::request::Parts;
+use http::{Method, Response, StatusCode};
+use hyper::body::{Body, Frame, SizeHint};
+use pin_project::{pin_project, pinned_drop};
+
+use super::access_log::{AccessLog, RequestInfo};
+#[cfg(feature = "metrics")]
+use super::Metrics;
+use crate::application::Route;
+
+/// Response body wrapper that reports on the response once the body is complete
+#[pin_project(PinnedDrop)]
+pub struct TrackedBody<B> {
+    #[pin]
+    inner: B,
+    tracker: Option<Box<Tracker>>,
+}
+
+impl<B> TrackedBody<B> {
+    pub(crate) fn new(inner: B, tracker: Option<Box<Tracker>>) -> Self {
+        Self { inner, tracker }
+    }
+}
+
+impl<B: Body> Body for TrackedBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let result = ready!(this.inner.poll_frame(cx));
+        match &result {
+            Some(Ok(frame)) => {
+                if let (Some(tracker), Some(data)) = (this.tracker.as_mut(), frame.data_ref()) {
+                    tracker.bytes += data.remaining() as u64;
+                }
+            }
+            Some(Err(_)) | None => {
+                if let Some(tracker) = this.tracker.take() {
+                    tracker.finish();
+                }
+            }
+        }
+
+        Poll::Ready(result)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<B> PinnedDrop for TrackedBody<B> {
+    fn drop(self: Pin<&mut Self>) {
+        // The body may not be polled to completion (or at all, if it's empty)
+        if let Some(tracker) = self.project().tracker.take() {
+            tracker.finish();
+        }
+    }
+}
+
+/// The parts of a request head the instruments need, captured before the request is handled
+pub(crate) struct RequestHead {
+    method: Method,
+    /// Request details for the access log, if enabled
+    request: Option<RequestInfo>,
+}
+
+impl RequestHead {
+    pub(crate) fn new(req: &Parts, start: Instant, instruments: &Instruments) -> Option<Self> {
+        if !instruments.enabled() {
+            return None;
+        }
+
+        Some(Self {
+            method: req.method.clone(),
+            request: instruments
+                .access_log
+                .as_ref()
+                .map(|log| log.request(req, start)),
+        })
+    }
+}
+
+/// Collects information about a request while its response is being sent
+pub(crate) struct Tracker {
+    pub(crate) method: Method,
+    pub(crate) status: StatusCode,
+    pub(crate) route: Option<&'static str>,
+    /// Time between receiving the request and sending the response head
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))] // Depends on features
+    pub(crate) latency: Duration,
+    pub(crate) bytes: u64,
+    start: Instant,
+    /// Request details for the access log, if enabled
+    request: Option<RequestInfo>,
+    instruments: Instruments,
+}
+
+impl Tracker {
+    pub(crate) fn new<B>(
+        head: RequestHead,
+        start: Instant,
+        rsp: &Response<B>,
+        instruments: &Instruments,
+    ) -> Box<Self> {
+        Box::new(Self {
+            method: head.method,
+            status: rsp.status(),
+            route: rsp.extensions().get::<Route>().map(|route| route.0),
+            latency: start.elapsed(),
+            bytes: 0,
+            start,
+            request: head.request,
+            instruments: instruments.clone(),
+        })
+    }
+
+    fn finish(self) {
+        if let (Some(log), Some(req)) = (&self.instruments.access_log, &self.request) {
+            log.log(&self, req, self.start.elapsed());
+        }
+
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.instruments.metrics {
+            metrics.request(&self);
+        }
+    }
+}
+
+/// Optional observers for the server's connections and requests
+#[derive(Clone, Default)]
+pub(crate) struct Instruments {
+    pub(crate) access_log: Option<Arc<AccessLog>>,
+    #[cfg(feature = "metrics")]
+    pub(crate) metrics: Option<Arc<Metrics>>,
+}
+
+impl Instruments {
+    pub(crate) fn connection_opened(&self) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.connection_opened();
+        }
+    }
+
+    pub(crate) fn connection_closed(&self) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.connection_closed();
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        #[allow(unused_mut)] // Depends on features
+        let mut enabled = self.access_log.is_some();
+        #[cfg(feature = "metrics")]
+        {
+            enabled |= self.metrics.is_some();
+        }
+        enabled
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use http::{Method, StatusCode};
+
+use super::instrument::Tracker;
+
+/// Request and connection metrics collected by the `Server`
+///
+/// Pass an `Arc<Metrics>` to `Server::metrics()` to start collecting, and keep a clone
+/// around (for example, in the `Application`) to render the metrics in a handler:
+///
+/// ```ignore
+/// #[handler(GET)]
+/// async fn metrics(app: &App) -> Result<Response<Body>, Error> {
+///     Ok(Response::builder()
+///         .header(CONTENT_TYPE, Metrics::CONTENT_TYPE)
+///         .body(app.metrics.render().into())
+///         .unwrap())
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct Metrics {
+    connections_opened: AtomicU64,
+    connections_closed: AtomicU64,
+    requests: Mutex<HashMap<RequestKey, RequestStats>>,
+}
+
+impl Metrics {
+    /// The content type for the Prometheus text exposition format
+    pub const CONTENT_TYPE: &'static str = "text/plain; version=0.0.4; charset=utf-8";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Render the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out).unwrap();
+        out
+    }
+
+    fn write(&self, out: &mut String) -> fmt::Result {
+        let opened = self.connections_opened.load(Ordering::Relaxed);
+        let closed = self.connections_closed.load(Ordering::Relaxed);
+        header(
+            out,
+            "connections_opened_total",
+            "counter",
+            "Connections accepted",
+        )?;
+        writeln!(out, "{PREFIX}connections_opened_total {opened}")?;
+        header(
+            out,
+            "connections_closed_total",
+            "counter",
+            "Connections closed",
+        )?;
+        writeln!(out, "{PREFIX}connections_closed_total {closed}")?;
+        header(
+            out,
+            "connections_open",
+            "gauge",
+            "Connections currently open",
+        )?;
+        writeln!(
+            out,
+            "{PREFIX}connections_open {}",
+            opened.saturating_sub(closed)
+        )?;
+
+        let requests = self.requests.lock().unwrap();
+        let mut requests = requests.iter().collect::<Vec<_>>();
+        requests.sort_by(|(a, _), (b, _)| {
+            (a.route, a.method, a.status).cmp(&(b.route, b.method, b.status))
+        });
+
+        header(out, "requests_total", "counter", "Requests handled")?;
+        for (key, stats) in &requests {
+            writeln!(out, "{PREFIX}requests_total{{{key}}} {}", stats.count)?;
+        }
+
+        header(
+            out,
+            "request_duration_seconds",
+            "histogram",
+            "Time until the response head was ready",
+        )?;
+        for (key, stats) in &requests {
+            for (bound, count) in BUCKETS.iter().zip(&stats.buckets) {
+                let name = "request_duration_seconds_bucket";
+                writeln!(out, "{PREFIX}{name}{{{key},le=\"{bound}\"}} {count}")?;
+            }
+            let name = "request_duration_seconds";
+            writeln!(
+                out,
+                "{PREFIX}{name}_bucket{{{key},le=\"+Inf\"}} {}",
+                stats.count
+            )?;
+            writeln!(out, "{PREFIX}{name}_sum{{{key}}} {}", stats.duration_sum)?;
+            writeln!(out, "{PREFIX}{name}_count{{{key}}} {}", stats.count)?;
+        }
+
+        header(
+            out,
+            "response_bytes_total",
+            "counter",
+            "Response body bytes sent",
+        )?;
+        for (key, stats) in &requests {
+            writeln!(out, "{PREFIX}response_bytes_total{{{key}}} {}", stats.bytes)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn connection_opened(&self) {
+        self.connections_opened.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connection_closed(&self) {
+        self.connections_closed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn request(&self, tracker: &Tracker) {
+        let key = RequestKey {
+            method: method_label(&tracker.method),
+            status: tracker.status,
+            route: tracker.route,
+        };
+
+        let mut requests = self.requests.lock().unwrap();
+        let stats = requests.entry(key).or_default();
+        let duration = tracker.latency.as_secs_f64();
+        stats.count += 1;
+        stats.duration_sum += duration;
+        stats.bytes += tracker.bytes;
+        for (bound, count) in BUCKETS.iter().zip(stats.buckets.iter_mut()) {
+            if duration <= *bound {
+                *count += 1;
+            }
+        }
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) -> fmt::Result {
+    writeln!(out, "# HELP {PREFIX}{name} {help}")?;
+    writeln!(out, "# TYPE {PREFIX}{name} {kind}")
+}
+
+/// The label for `method`, collapsing non-standard methods into `OTHER`
+///
+/// Clients can send arbitrary methods, so using them as labels as-is would let them grow
+/// the set of series without bound.
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::CONNECT => "CONNECT",
+        Method::OPTIONS => "OPTIONS",
+        Method::TRACE => "TRACE",
+        Method::PATCH => "PATCH",
+        _ => "OTHER",
+    }
+}
+
+#[derive(Debug, Eq, Hash, PartialEq)]
+struct RequestKey {
+    method: &'static str,
+    status: StatusCode,
+    route: Option<&'static str>,
+}
+
+impl fmt::Display for RequestKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "method=\"")?;
+        escape(f, self.method)?;
+        write!(f, "\",status=\"{}\",route=\"", self.status.as_u16())?;
+        escape(f, self.route.unwrap_or_default())?;
+        write!(f, "\"")
+    }
+}
+
+fn escape(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    for c in value.chars() {
+        match c {
+            '\\' => f.write_str("\\\\")?,
+            '"' => f.write_str("\\\"")?,
+            '\n' => f.write_str("\\n")?,
+            c => f.write_char(c)?,
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct RequestStats {
+    count: u64,
+    buckets: [u64; BUCKETS.len()],
+    duration_sum: f64,
+    bytes: u64,
+}
+
+const PREFIX: &str = "mendes_";
+/// Upper bounds for the latency histogram buckets (in seconds)
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
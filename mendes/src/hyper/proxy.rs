@@ -0,0 +1,417 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::str::{self, FromStr};
+use std::task::{Context, Poll};
+use std::{cmp, io};
+
+use bytes::{Buf, BytesMut};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+
+/// Whether connections are expected to start with a PROXY protocol header
+///
+/// Load balancers like HAProxy or AWS NLB can prepend a PROXY protocol header (v1 or v2)
+/// to each connection they forward, such that the server can learn about the original
+/// client and destination addresses.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ProxyProtocol {
+    /// Don't look for a PROXY protocol header (the default)
+    #[default]
+    Disabled,
+    /// Parse a PROXY protocol header if the connection starts with one
+    ///
+    /// Only use this if all clients that can reach the listener are trusted, since
+    /// any client can send a PROXY protocol header to spoof its address.
+    Optional,
+    /// Reject connections that don't start with a valid PROXY protocol header
+    Required,
+}
+
+/// Read a PROXY protocol header from the start of the `stream`, if present
+///
+/// Yields the source and destination addresses from the header (if any), and a wrapper
+/// around the stream that will replay any data read beyond the header.
+pub(crate) async fn accept<S: AsyncRead + Unpin>(
+    mut stream: S,
+    mode: ProxyProtocol,
+) -> Result<(Option<Addresses>, Rewind<S>), Error> {
+    let mut buf = BytesMut::new();
+    if mode == ProxyProtocol::Disabled {
+        return Ok((None, Rewind { buf, inner: stream }));
+    }
+
+    loop {
+        match parse(&buf)? {
+            Parsed::Incomplete => {}
+            Parsed::Missing => match mode {
+                ProxyProtocol::Required => return Err(Error::Missing),
+                _ => return Ok((None, Rewind { buf, inner: stream })),
+            },
+            Parsed::Header { addresses, len } => {
+                buf.advance(len);
+                return Ok((addresses, Rewind { buf, inner: stream }));
+            }
+        }
+
+        if stream.read_buf(&mut buf).await? == 0 {
+            return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+    }
+}
+
+fn parse(buf: &[u8]) -> Result<Parsed, Error> {
+    if buf.starts_with(V2_SIGNATURE) {
+        return parse_v2(buf);
+    } else if buf.starts_with(V1_PREFIX) {
+        return parse_v1(buf);
+    }
+
+    let v1 = &V1_PREFIX[..cmp::min(buf.len(), V1_PREFIX.len())];
+    let v2 = &V2_SIGNATURE[..cmp::min(buf.len(), V2_SIGNATURE.len())];
+    Ok(match buf.starts_with(v1) || buf.starts_with(v2) {
+        true => Parsed::Incomplete,
+        false => Parsed::Missing,
+    })
+}
+
+fn parse_v1(buf: &[u8]) -> Result<Parsed, Error> {
+    let end = match buf.windows(2).position(|w| w == b"\r\n") {
+        Some(end) if end + 2 <= V1_MAX_LEN => end,
+        Some(_) => return Err(Error::Invalid("v1 header too long")),
+        None if buf.len() >= V1_MAX_LEN => return Err(Error::Invalid("v1 header too long")),
+        None => return Ok(Parsed::Incomplete),
+    };
+
+    let line = str::from_utf8(&buf[V1_PREFIX.len()..end])
+        .map_err(|_| Error::Invalid("non-ASCII v1 header"))?;
+    let mut parts = line.split(' ');
+    let ipv6 = match parts.next() {
+        Some("TCP4") => false,
+        Some("TCP6") => true,
+        // The receiver must ignore anything presented after `UNKNOWN`.
+        Some("UNKNOWN") => {
+            return Ok(Parsed::Header {
+                addresses: None,
+                len: end + 2,
+            })
+        }
+        _ => return Err(Error::Invalid("unknown v1 protocol")),
+    };
+
+    let mut next = || parts.next().ok_or(Error::Invalid("missing v1 field"));
+    let (src, dst) = (next()?, next()?);
+    let (src_port, dst_port) = (next()?, next()?);
+    if parts.next().is_some() {
+        return Err(Error::Invalid("trailing data in v1 header"));
+    }
+
+    let ip = |s: &str| match (ipv6, IpAddr::from_str(s)) {
+        (false, Ok(ip @ IpAddr::V4(_))) | (true, Ok(ip @ IpAddr::V6(_))) => Ok(ip),
+        _ => Err(Error::Invalid("invalid v1 address")),
+    };
+
+    let port = |s: &str| match s.starts_with('0') && s.len() > 1 {
+        false => u16::from_str(s).map_err(|_| Error::Invalid("invalid v1 port")),
+        true => Err(Error::Invalid("invalid v1 port")),
+    };
+
+    Ok(Parsed::Header {
+        addresses: Some(Addresses {
+            source: SocketAddr::new(ip(src)?, port(src_port)?),
+            destination: SocketAddr::new(ip(dst)?, port(dst_port)?),
+        }),
+        len: end + 2,
+    })
+}
+
+fn parse_v2(buf: &[u8]) -> Result<Parsed, Error> {
+    if buf.len() < 16 {
+        return Ok(Parsed::Incomplete);
+    }
+
+    let (version, command) = (buf[12] >> 4, buf[12] & 0x0f);
+    if version != 2 {
+        return Err(Error::Invalid("unsupported v2 version"));
+    }
+
+    let len = 16 + u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    if buf.len() < len {
+        return Ok(Parsed::Incomplete);
+    }
+
+    match command {
+        // LOCAL: the connection was established by the proxy itself (e.g. health checks)
+        0x0 => {
+            return Ok(Parsed::Header {
+                addresses: None,
+                len,
+            })
+        }
+        0x1 => {}
+        _ => return Err(Error::Invalid("unsupported v2 command")),
+    }
+
+    let mut data = &buf[16..len];
+    // The low nibble has the transport protocol; both streams and datagrams are accepted.
+    let addresses = match buf[13] >> 4 {
+        0x1 if data.len() >= 12 => {
+            let src = Ipv4Addr::from(data.get_u32());
+            let dst = Ipv4Addr::from(data.get_u32());
+            Some(Addresses {
+                source: SocketAddr::new(src.into(), data.get_u16()),
+                destination: SocketAddr::new(dst.into(), data.get_u16()),
+            })
+        }
+        0x2 if data.len() >= 36 => {
+            let src = Ipv6Addr::from(data.get_u128());
+            let dst = Ipv6Addr::from(data.get_u128());
+            Some(Addresses {
+                source: SocketAddr::new(src.into(), data.get_u16()),
+                destination: SocketAddr::new(dst.into(), data.get_u16()),
+            })
+        }
+        0x1 | 0x2 => return Err(Error::Invalid("v2 address block too short")),
+        // UNSPEC or AF_UNIX: the receiver should fall back to the connection's addresses.
+        _ => None,
+    };
+
+    Ok(Parsed::Header { addresses, len })
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Parsed {
+    Incomplete,
+    Missing,
+    Header {
+        addresses: Option<Addresses>,
+        len: usize,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Addresses {
+    pub(crate) source: SocketAddr,
+    pub(crate) destination: SocketAddr,
+}
+
+/// Replays data read while looking for the PROXY protocol header before reading from `inner`
+pub(crate) struct Rewind<S> {
+    buf: BytesMut,
+    inner: S,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Rewind<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.buf.is_empty() {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+
+        let len = cmp::min(this.buf.len(), buf.remaining());
+        buf.put_slice(&this.buf.split_to(len));
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Rewind<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum Error {
+    #[error("missing PROXY protocol header")]
+    Missing,
+    #[error("invalid PROXY protocol header: {0}")]
+    Invalid(&'static str),
+    #[error("failed to read PROXY protocol header: {0}")]
+    Io(#[from] io::Error),
+}
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+/// Maximum length of a v1 header, including the CRLF
+const V1_MAX_LEN: usize = 107;
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn v1_tcp4() {
+        let buf = b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\r\nGET / HTTP/1.1\r\n";
+        assert_eq!(
+            parse(buf).unwrap(),
+            Parsed::Header {
+                addresses: Some(Addresses {
+                    source: "192.0.2.1:56324".parse().unwrap(),
+                    destination: "198.51.100.2:443".parse().unwrap(),
+                }),
+                len: 45,
+            }
+        );
+    }
+
+    #[test]
+    fn v1_tcp6() {
+        let buf = b"PROXY TCP6 2001:db8::1 2001:db8::2 4000 80\r\n";
+        assert_eq!(
+            parse(buf).unwrap(),
+            Parsed::Header {
+                addresses: Some(Addresses {
+                    source: "[2001:db8::1]:4000".parse().unwrap(),
+                    destination: "[2001:db8::2]:80".parse().unwrap(),
+                }),
+                len: buf.len(),
+            }
+        );
+    }
+
+    #[test]
+    fn v1_unknown() {
+        let buf = b"PROXY UNKNOWN ffff::1 ffff::2 1 2\r\n";
+        assert_eq!(
+            parse(buf).unwrap(),
+            Parsed::Header {
+                addresses: None,
+                len: buf.len(),
+            }
+        );
+    }
+
+    #[test]
+    fn v1_invalid() {
+        for buf in [
+            &b"PROXY TCP4 192.0.2.1 198.51.100.2 56324\r\n"[..],
+            b"PROXY TCP4 2001:db8::1 198.51.100.2 56324 443\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 056324 443\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 65536 443\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 1 2 3\r\n",
+            b"PROXY UDP4 192.0.2.1 198.51.100.2 1 2\r\n",
+        ] {
+            assert!(parse(buf).is_err(), "{}", String::from_utf8_lossy(buf));
+        }
+
+        let long = [&b"PROXY UNKNOWN "[..], &[b'x'; 100]].concat();
+        assert!(parse(&long).is_err());
+    }
+
+    #[test]
+    fn v2_tcp4() {
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.extend([0x21, 0x11, 0x00, 0x0c]);
+        buf.extend([192, 0, 2, 1, 198, 51, 100, 2]);
+        buf.extend(56324u16.to_be_bytes());
+        buf.extend(443u16.to_be_bytes());
+        let len = buf.len();
+        buf.extend(b"GET / HTTP/1.1\r\n");
+
+        assert_eq!(
+            parse(&buf).unwrap(),
+            Parsed::Header {
+                addresses: Some(Addresses {
+                    source: "192.0.2.1:56324".parse().unwrap(),
+                    destination: "198.51.100.2:443".parse().unwrap(),
+                }),
+                len,
+            }
+        );
+    }
+
+    #[test]
+    fn v2_tcp6_with_tlv() {
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.extend([0x21, 0x21, 0x00, 36 + 7]);
+        buf.extend("2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        buf.extend("2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        buf.extend(4000u16.to_be_bytes());
+        buf.extend(80u16.to_be_bytes());
+        // PP2_TYPE_AUTHORITY TLV, which is ignored
+        buf.extend([0x02, 0x00, 0x04]);
+        buf.extend(b"host");
+
+        assert_eq!(
+            parse(&buf).unwrap(),
+            Parsed::Header {
+                addresses: Some(Addresses {
+                    source: "[2001:db8::1]:4000".parse().unwrap(),
+                    destination: "[2001:db8::2]:80".parse().unwrap(),
+                }),
+                len: buf.len(),
+            }
+        );
+    }
+
+    #[test]
+    fn v2_local() {
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.extend([0x20, 0x00, 0x00, 0x00]);
+        assert_eq!(
+            parse(&buf).unwrap(),
+            Parsed::Header {
+                addresses: None,
+                len: 16,
+            }
+        );
+    }
+
+    #[test]
+    fn v2_invalid() {
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.extend([0x11, 0x11, 0x00, 0x00]);
+        assert!(parse(&buf).is_err());
+
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.extend([0x21, 0x11, 0x00, 0x04, 1, 2, 3, 4]);
+        assert!(parse(&buf).is_err());
+    }
+
+    #[test]
+    fn incomplete() {
+        assert_eq!(parse(b"").unwrap(), Parsed::Incomplete);
+        assert_eq!(parse(b"PRO").unwrap(), Parsed::Incomplete);
+        assert_eq!(parse(b"PROXY TCP4 192.0.2.1").unwrap(), Parsed::Incomplete);
+        assert_eq!(parse(b"\r\n\r\n").unwrap(), Parsed::Incomplete);
+        assert_eq!(parse(&V2_SIGNATURE[..12]).unwrap(), Parsed::Incomplete);
+
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.extend([0x21, 0x11, 0x00, 0x0c, 192, 0, 2]);
+        assert_eq!(parse(&buf).unwrap(), Parsed::Incomplete);
+    }
+
+    #[test]
+    fn missing() {
+        assert_eq!(parse(b"GET / HTTP/1.1\r\n").unwrap(), Parsed::Missing);
+        assert_eq!(parse(b"PRI * HTTP/2.0").unwrap(), Parsed::Missing);
+        assert_eq!(parse(b"\r\n\r\n\0\r\nQUIT!").unwrap(), Parsed::Missing);
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use http::header::HeaderName;
+use http::request::Parts;
+use http::{HeaderMap, HeaderValue};
+
+use crate::application::{Error, FromContext, PathState};
+use crate::Application;
+
+/// Identifies a request in logs and traces
+///
+/// If the request has a (reasonably sized, printable) `X-Request-Id` header, its value
+/// is used; otherwise, the server generates an ID that is unique for the process, starting
+/// with a random prefix. The ID is echoed back in the `X-Request-Id` header of the response,
+/// unless the handler already set that header.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct RequestId(HeaderValue);
+
+impl RequestId {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        let value = headers
+            .get(&X_REQUEST_ID)
+            .filter(|value| valid(value.as_bytes()));
+        match value {
+            Some(value) => Self(value.clone()),
+            None => Self::generate(),
+        }
+    }
+
+    /// Generate an ID from a random per-process prefix and a counter
+    fn generate() -> Self {
+        static PREFIX: OnceLock<u64> = OnceLock::new();
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let prefix = PREFIX.get_or_init(|| {
+            // The counter keeps IDs unique within the process even if this fails
+            let mut random = [0; 8];
+            let _ = getrandom::getrandom(&mut random);
+            u64::from_ne_bytes(random)
+        });
+
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        Self(HeaderValue::try_from(format!("{prefix:016x}{count:016x}")).unwrap())
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only visible ASCII characters are accepted, see `valid()`
+        self.0.to_str().unwrap()
+    }
+
+    pub(crate) fn header_value(&self) -> &HeaderValue {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<'a, A: Application> FromContext<'a, A> for RequestId {
+    fn from_context(
+        _: &'a Arc<A>,
+        req: &'a Parts,
+        _: &mut PathState,
+        _: &mut Option<A::RequestBody>,
+    ) -> Result<Self, A::Error> {
+        // Inserted by the `Server` and `TestClient`, but not by `AppService`
+        req.extensions
+            .get::<RequestId>()
+            .cloned()
+            .ok_or_else(|| Error::ExtensionMissing("RequestId").into())
+    }
+}
+
+fn valid(value: &[u8]) -> bool {
+    !value.is_empty() && value.len() <= MAX_LEN && value.iter().all(|b| b.is_ascii_graphic())
+}
+
+pub(crate) static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+const MAX_LEN: usize = 128;
+
+#[cfg(test)]
+mod test {
+    use http::{HeaderMap, HeaderValue};
+
+    use super::{RequestId, X_REQUEST_ID};
+
+    #[test]
+    fn request_id() {
+        let mut headers = HeaderMap::new();
+        headers.insert(&X_REQUEST_ID, HeaderValue::from_static("abc-123"));
+        assert_eq!(RequestId::from_headers(&headers).as_str(), "abc-123");
+
+        headers.insert(&X_REQUEST_ID, HeaderValue::from_static("has spaces"));
+        let generated = RequestId::from_headers(&headers);
+        assert_eq!(generated.as_str().len(), 32);
+        assert_ne!(generated, RequestId::from_headers(&HeaderMap::new()));
+    }
+}
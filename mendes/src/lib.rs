@@ -41,6 +41,16 @@ pub mod utils;
 /// Optional features that require hyper
 pub mod hyper;
 
+#[cfg(feature = "testing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+/// In-process test client for applications
+pub mod testing;
+
+#[cfg(feature = "tower")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
+/// Integration with tower services and middleware
+pub mod tower;
+
 #[cfg(feature = "uploads")]
 mod multipart;
 
@@ -0,0 +1,635 @@
+use std::error::Error as StdError;
+use std::fmt::Write;
+use std::future::poll_fn;
+#[cfg(feature = "hyper")]
+use std::net::SocketAddr;
+use std::pin::pin;
+use std::str;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use bytes::{Buf, Bytes, BytesMut};
+use http::header::{HeaderName, CONTENT_TYPE, COOKIE, SET_COOKIE};
+use http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use http_body::Body as HttpBody;
+#[cfg(feature = "cookies")]
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::application::{expect_continue, Application, Context};
+#[cfg(feature = "cookies")]
+use crate::cookies::{AppWithCookies, CookieData};
+#[cfg(feature = "hyper")]
+use crate::hyper::{ClientAddr, Listener, LocalAddr, RequestId, ShutdownHandle};
+use crate::Body;
+
+/// Dispatches requests to an `Application` in-process
+///
+/// The client keeps a cookie jar: cookies set by responses (for example, through
+/// `AppWithCookies::set_cookie()`) are sent with subsequent requests, until they expire
+/// or are deleted. The `Path` attribute is honored; `Domain` and `Secure` are ignored.
+///
+/// With the `hyper` feature, requests get the same extensions as requests from the hyper
+/// `Server`, so extractors like `ClientAddr` and `RequestId` work in tests.
+///
+/// ```ignore
+/// let mut client = TestClient::new(App::default());
+/// client
+///     .post("/login")
+///     .form(&Login { user: "admin", password: "secret" })
+///     .send()
+///     .await
+///     .assert_status(StatusCode::SEE_OTHER);
+/// client.get("/").send().await.assert_text("Hello, admin");
+/// ```
+pub struct TestClient<A> {
+    app: Arc<A>,
+    jar: Vec<StoredCookie>,
+    #[cfg(feature = "hyper")]
+    client_addr: SocketAddr,
+    #[cfg(feature = "hyper")]
+    local_addr: SocketAddr,
+    #[cfg(feature = "hyper")]
+    shutdown: ShutdownHandle,
+}
+
+impl<A: Application> TestClient<A>
+where
+    A::RequestBody: TestRequestBody,
+    <A::ResponseBody as HttpBody>::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    pub fn new(app: A) -> Self {
+        Self::from_arc(Arc::new(app))
+    }
+
+    pub fn from_arc(app: Arc<A>) -> Self {
+        Self {
+            app,
+            jar: Vec::new(),
+            #[cfg(feature = "hyper")]
+            client_addr: SocketAddr::from(([127, 0, 0, 1], 49152)),
+            #[cfg(feature = "hyper")]
+            local_addr: SocketAddr::from(([127, 0, 0, 1], 80)),
+            #[cfg(feature = "hyper")]
+            shutdown: ShutdownHandle::new(),
+        }
+    }
+
+    /// Set the address the requests appear to come from (default: `127.0.0.1:49152`)
+    #[cfg(feature = "hyper")]
+    pub fn client_addr(mut self, addr: SocketAddr) -> Self {
+        self.client_addr = addr;
+        self
+    }
+
+    /// Set the address the requests appear to be sent to (default: `127.0.0.1:80`)
+    #[cfg(feature = "hyper")]
+    pub fn local_addr(mut self, addr: SocketAddr) -> Self {
+        self.local_addr = addr;
+        self
+    }
+
+    /// Get a handle to trigger the `ShutdownSignal` passed to request handlers
+    #[cfg(feature = "hyper")]
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    pub fn get(&mut self, path: &str) -> TestRequest<'_, A> {
+        self.request(Method::GET, path)
+    }
+
+    pub fn head(&mut self, path: &str) -> TestRequest<'_, A> {
+        self.request(Method::HEAD, path)
+    }
+
+    pub fn post(&mut self, path: &str) -> TestRequest<'_, A> {
+        self.request(Method::POST, path)
+    }
+
+    pub fn put(&mut self, path: &str) -> TestRequest<'_, A> {
+        self.request(Method::PUT, path)
+    }
+
+    pub fn patch(&mut self, path: &str) -> TestRequest<'_, A> {
+        self.request(Method::PATCH, path)
+    }
+
+    pub fn delete(&mut self, path: &str) -> TestRequest<'_, A> {
+        self.request(Method::DELETE, path)
+    }
+
+    /// Start building a request for `path`, which may include a query string
+    pub fn request(&mut self, method: Method, path: &str) -> TestRequest<'_, A> {
+        assert!(path.starts_with('/'), "path must start with a slash");
+        TestRequest {
+            client: self,
+            method,
+            path: path.to_owned(),
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+        }
+    }
+
+    /// The value of the cookie `name` in the cookie jar
+    pub fn cookie_value(&self, name: &str) -> Option<&str> {
+        let now = Instant::now();
+        self.jar
+            .iter()
+            .find(|cookie| cookie.name == name && !cookie.expired(now))
+            .map(|cookie| cookie.value.as_str())
+    }
+
+    /// Decode the cookie for `T` from the cookie jar with the `Application`'s key
+    #[cfg(feature = "cookies")]
+    pub fn cookie<T: CookieData + DeserializeOwned>(&self) -> Option<T>
+    where
+        A: AppWithCookies,
+    {
+        let value = format!("{}={}", T::NAME, self.cookie_value(T::NAME)?);
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, HeaderValue::try_from(value).ok()?);
+        self.app.cookie(&headers)
+    }
+
+    /// Store a cookie for `data`, encrypted with the `Application`'s key
+    ///
+    /// This is useful to make requests as if some earlier response had set the cookie.
+    #[cfg(feature = "cookies")]
+    pub fn set_cookie<T: CookieData + Serialize>(&mut self, data: T)
+    where
+        A: AppWithCookies,
+    {
+        let value = self.app.set_cookie_header(Some(data)).unwrap();
+        self.store_cookie(&value, "/");
+    }
+
+    /// Remove all cookies from the cookie jar
+    pub fn clear_cookies(&mut self) {
+        self.jar.clear();
+    }
+
+    fn cookie_header(&self, path: &str) -> Option<HeaderValue> {
+        let now = Instant::now();
+        let mut value = String::new();
+        for cookie in &self.jar {
+            if cookie.expired(now) || !path_matches(&cookie.path, path) {
+                continue;
+            }
+
+            if !value.is_empty() {
+                value.push_str("; ");
+            }
+            write!(value, "{}={}", cookie.name, cookie.value).unwrap();
+        }
+
+        match value.is_empty() {
+            true => None,
+            false => HeaderValue::try_from(value).ok(),
+        }
+    }
+
+    fn store_cookie(&mut self, header: &HeaderValue, request_path: &str) {
+        let Ok(header) = header.to_str() else {
+            return;
+        };
+
+        let mut attributes = header.split(';');
+        let Some((name, value)) = attributes.next().and_then(|pair| pair.split_once('=')) else {
+            return;
+        };
+
+        let now = Instant::now();
+        // Like browsers, default to the "directory" of the request path
+        let mut path = match request_path.rfind('/') {
+            Some(0) | None => "/".to_owned(),
+            Some(idx) => request_path[..idx].to_owned(),
+        };
+        let (mut max_age, mut expires) = (None, None);
+        for attribute in attributes {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let (key, value) = (key.trim(), value.trim());
+            if key.eq_ignore_ascii_case("path") && value.starts_with('/') {
+                path = value.to_owned();
+            } else if key.eq_ignore_ascii_case("max-age") {
+                if let Ok(secs) = value.parse::<i64>() {
+                    max_age = Some(secs);
+                }
+            } else if key.eq_ignore_ascii_case("expires") {
+                if let Ok(date) = httpdate::parse_http_date(value) {
+                    expires = Some(date);
+                }
+            }
+        }
+
+        // `Max-Age` takes precedence over `Expires`; a date in the past deletes the cookie
+        let remaining = match (max_age, expires) {
+            (Some(secs), _) => Some(Duration::from_secs(u64::try_from(secs).unwrap_or(0))),
+            (None, Some(date)) => Some(
+                date.duration_since(SystemTime::now())
+                    .unwrap_or(Duration::ZERO),
+            ),
+            (None, None) => None,
+        };
+
+        let (name, value) = (name.trim(), value.trim());
+        self.jar
+            .retain(|cookie| !(cookie.name == name && cookie.path == path));
+        if !remaining.is_some_and(|remaining| remaining.is_zero()) {
+            let expires = remaining.map(|remaining| now + remaining);
+            self.jar.push(StoredCookie {
+                name: name.to_owned(),
+                value: value.to_owned(),
+                path,
+                expires,
+            });
+        }
+    }
+}
+
+/// A request being built by a `TestClient`
+pub struct TestRequest<'a, A> {
+    client: &'a mut TestClient<A>,
+    method: Method,
+    path: String,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl<A: Application> TestRequest<'_, A>
+where
+    A::RequestBody: TestRequestBody,
+    <A::ResponseBody as HttpBody>::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    /// Append a header to the request
+    ///
+    /// Panics if the name or value is invalid.
+    pub fn header<K, V>(mut self, name: K, value: V) -> Self
+    where
+        K: TryInto<HeaderName>,
+        K::Error: std::fmt::Debug,
+        V: TryInto<HeaderValue>,
+        V::Error: std::fmt::Debug,
+    {
+        let name = name.try_into().expect("invalid header name");
+        let value = value.try_into().expect("invalid header value");
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Append `query` to the request path as a URL-encoded query string
+    pub fn query<T: Serialize>(mut self, query: &T) -> Self {
+        let query = serde_urlencoded::to_string(query).expect("failed to encode query");
+        self.path
+            .push(if self.path.contains('?') { '&' } else { '?' });
+        self.path.push_str(&query);
+        self
+    }
+
+    /// Set the request body
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Set the request body to `data` encoded as JSON
+    #[cfg(feature = "json")]
+    pub fn json<T: Serialize>(self, data: &T) -> Self {
+        let body = serde_json::to_vec(data).expect("failed to encode JSON");
+        self.header(CONTENT_TYPE, crate::types::JSON).body(body)
+    }
+
+    /// Set the request body to `data` encoded as a URL-encoded form
+    pub fn form<T: Serialize>(self, data: &T) -> Self {
+        let body = serde_urlencoded::to_string(data).expect("failed to encode form");
+        self.header(CONTENT_TYPE, FORM).body(body)
+    }
+
+    /// Set the request body to a multipart form
+    pub fn multipart(self, form: Multipart) -> Self {
+        let content_type = format!("multipart/form-data; boundary={}", form.boundary);
+        self.header(CONTENT_TYPE, content_type).body(form.finish())
+    }
+
+    /// Send the request to the `Application` and wait for the full response
+    pub async fn send(self) -> TestResponse {
+        let Self {
+            client,
+            method,
+            path,
+            mut headers,
+            body,
+        } = self;
+
+        let request_path = path.split('?').next().unwrap_or_default().to_owned();
+        if let Some(cookies) = client.cookie_header(&request_path) {
+            headers.append(COOKIE, cookies);
+        }
+
+        let mut req = Request::builder()
+            .method(method)
+            .uri(format!("https://localhost{path}"))
+            .body(A::RequestBody::from_bytes(body))
+            .expect("invalid request");
+        *req.headers_mut() = headers;
+
+        #[cfg(feature = "hyper")]
+        {
+            let id = RequestId::from_headers(req.headers());
+            let extensions = req.extensions_mut();
+            extensions.insert(ClientAddr::from(client.client_addr));
+            extensions.insert(LocalAddr::from(client.local_addr));
+            extensions.insert(Listener {
+                index: 0,
+                addr: client.local_addr,
+            });
+            extensions.insert(client.shutdown.signal());
+            extensions.insert(id);
+        }
+
+        // Like the server, let the `Application` reject requests that expect `100 Continue`
+        let (head, body) = req.into_parts();
+        let rsp = match expect_continue(&*client.app, &head) {
+            Some(rsp) => rsp,
+            None => {
+                let req = Request::from_parts(head, body);
+                A::handle(Context::new(client.app.clone(), req)).await
+            }
+        };
+        let (parts, body) = rsp.into_parts();
+        for value in parts.headers.get_all(SET_COOKIE) {
+            client.store_cookie(value, &request_path);
+        }
+
+        let mut body = pin!(body);
+        let mut buf = BytesMut::new();
+        while let Some(frame) = poll_fn(|cx| body.as_mut().poll_frame(cx)).await {
+            let frame = match frame {
+                Ok(frame) => frame,
+                Err(error) => panic!("failed to read response body: {}", error.into()),
+            };
+
+            if let Ok(mut data) = frame.into_data() {
+                while data.has_remaining() {
+                    let chunk = data.chunk();
+                    buf.extend_from_slice(chunk);
+                    let len = chunk.len();
+                    data.advance(len);
+                }
+            }
+        }
+
+        TestResponse {
+            rsp: Response::from_parts(parts, buf.freeze()),
+        }
+    }
+}
+
+/// A response received by the `TestClient`
+///
+/// The assertion methods panic with a descriptive message if the assertion fails, and
+/// return `&Self` so they can be chained.
+#[derive(Debug)]
+pub struct TestResponse {
+    rsp: Response<Bytes>,
+}
+
+impl TestResponse {
+    pub fn status(&self) -> StatusCode {
+        self.rsp.status()
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        self.rsp.headers()
+    }
+
+    /// The value of the first header with the given `name`, if it is valid UTF-8
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.rsp.headers().get(name)?.to_str().ok()
+    }
+
+    pub fn bytes(&self) -> &Bytes {
+        self.rsp.body()
+    }
+
+    /// The response body as a string
+    ///
+    /// Panics if the body is not valid UTF-8.
+    pub fn text(&self) -> &str {
+        str::from_utf8(self.rsp.body()).expect("response body is not valid UTF-8")
+    }
+
+    /// Decode the response body as JSON
+    ///
+    /// Panics if the body can't be decoded as a `T`.
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(self.rsp.body()).expect("failed to decode response body as JSON")
+    }
+
+    pub fn into_response(self) -> Response<Bytes> {
+        self.rsp
+    }
+
+    #[track_caller]
+    pub fn assert_status(&self, status: StatusCode) -> &Self {
+        assert_eq!(
+            self.status(),
+            status,
+            "unexpected status (body: {:?})",
+            String::from_utf8_lossy(self.bytes())
+        );
+        self
+    }
+
+    #[track_caller]
+    pub fn assert_header(&self, name: &str, value: &str) -> &Self {
+        match self.rsp.headers().get(name) {
+            Some(actual) => assert_eq!(actual, value, "unexpected value for header {name:?}"),
+            None => panic!("missing header {name:?}"),
+        }
+        self
+    }
+
+    #[track_caller]
+    pub fn assert_no_header(&self, name: &str) -> &Self {
+        if let Some(value) = self.rsp.headers().get(name) {
+            panic!("unexpected header {name:?} with value {value:?}");
+        }
+        self
+    }
+
+    #[track_caller]
+    pub fn assert_text(&self, text: &str) -> &Self {
+        assert_eq!(self.text(), text, "unexpected response body");
+        self
+    }
+
+    #[track_caller]
+    pub fn assert_contains(&self, needle: &str) -> &Self {
+        let text = self.text();
+        assert!(
+            text.contains(needle),
+            "response body {text:?} does not contain {needle:?}"
+        );
+        self
+    }
+
+    /// Assert that the response body is JSON equal to `expected` (after serialization)
+    #[cfg(feature = "json")]
+    #[track_caller]
+    pub fn assert_json<T: Serialize>(&self, expected: &T) -> &Self {
+        let actual = self.json::<serde_json::Value>();
+        let expected = serde_json::to_value(expected).expect("failed to encode expected JSON");
+        assert_eq!(actual, expected, "unexpected JSON response body");
+        self
+    }
+}
+
+/// Builder for `multipart/form-data` request bodies
+#[derive(Debug)]
+pub struct Multipart {
+    boundary: String,
+    body: Vec<u8>,
+}
+
+impl Multipart {
+    pub fn new() -> Self {
+        // The boundary must not occur in the parts, so it can't be predictable
+        let mut random = [0; 16];
+        getrandom::getrandom(&mut random).expect("failed to generate multipart boundary");
+        let mut boundary = String::from("mendes-test-");
+        for byte in random {
+            let _ = write!(boundary, "{byte:02x}");
+        }
+
+        Self {
+            boundary,
+            body: Vec::new(),
+        }
+    }
+
+    /// Add a text field
+    pub fn text(self, name: &str, value: &str) -> Self {
+        self.part(name, None, value.as_bytes())
+    }
+
+    /// Add a file field with the given `filename`, `content_type` and `data`
+    pub fn file(mut self, name: &str, filename: &str, content_type: &str, data: &[u8]) -> Self {
+        self = self.part(name, Some((filename, content_type)), data);
+        self
+    }
+
+    fn part(mut self, name: &str, file: Option<(&str, &str)>, data: &[u8]) -> Self {
+        let mut header = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{name}\"",
+            self.boundary
+        );
+        if let Some((filename, content_type)) = file {
+            write!(
+                header,
+                "; filename=\"{filename}\"\r\nContent-Type: {content_type}"
+            )
+            .unwrap();
+        }
+        header.push_str("\r\n\r\n");
+
+        self.body.extend_from_slice(header.as_bytes());
+        self.body.extend_from_slice(data);
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.body
+            .extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        self.body
+    }
+}
+
+impl Default for Multipart {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Request body types that the `TestClient` can construct
+///
+/// hyper's `Incoming` body can't be constructed outside of hyper; applications that
+/// use it as their `RequestBody` can switch to `Body` (which implements `From<Incoming>`).
+pub trait TestRequestBody: Sized {
+    fn from_bytes(bytes: Bytes) -> Self;
+}
+
+impl TestRequestBody for Body {
+    fn from_bytes(bytes: Bytes) -> Self {
+        Body::from(bytes)
+    }
+}
+
+impl TestRequestBody for Bytes {
+    fn from_bytes(bytes: Bytes) -> Self {
+        bytes
+    }
+}
+
+impl TestRequestBody for Vec<u8> {
+    fn from_bytes(bytes: Bytes) -> Self {
+        bytes.into()
+    }
+}
+
+impl TestRequestBody for String {
+    fn from_bytes(bytes: Bytes) -> Self {
+        String::from_utf8(bytes.into()).expect("request body is not valid UTF-8")
+    }
+}
+
+impl TestRequestBody for () {
+    fn from_bytes(_: Bytes) -> Self {}
+}
+
+struct StoredCookie {
+    name: String,
+    value: String,
+    path: String,
+    expires: Option<Instant>,
+}
+
+impl StoredCookie {
+    fn expired(&self, now: Instant) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+}
+
+/// Whether `request_path` is within the cookie's `path` (RFC 6265, section 5.1.4)
+fn path_matches(path: &str, request_path: &str) -> bool {
+    match request_path.strip_prefix(path) {
+        Some(rest) => rest.is_empty() || path.ends_with('/') || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+const FORM: &str = "application/x-www-form-urlencoded";
+
+#[cfg(test)]
+mod test {
+    use super::{path_matches, Multipart};
+
+    #[test]
+    fn cookie_paths() {
+        assert!(path_matches("/", "/foo"));
+        assert!(path_matches("/foo", "/foo"));
+        assert!(path_matches("/foo", "/foo/bar"));
+        assert!(path_matches("/foo/", "/foo/bar"));
+        assert!(!path_matches("/foo", "/foobar"));
+        assert!(!path_matches("/foo", "/"));
+    }
+
+    #[test]
+    fn multipart_boundary() {
+        let (a, b) = (Multipart::new(), Multipart::new());
+        assert_ne!(a.boundary, b.boundary);
+        assert!(a.boundary.starts_with("mendes-test-"));
+    }
+}
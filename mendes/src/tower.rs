@@ -0,0 +1,135 @@
+use std::convert::Infallible;
+use std::error::Error as StdError;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use http::uri::{PathAndQuery, Uri};
+use http::{Request, Response};
+use tower::Service;
+
+use crate::application::{self, handle_unwind, Application, IntoResponse, Mount};
+use crate::Body;
+
+/// A tower `Service` that handles requests with an `Application`
+///
+/// This can be used to serve an `Application` from other tower-based servers, or to wrap it
+/// in tower middleware. Request bodies are converted into the `Application`'s `RequestBody`.
+/// If a request handler panics, the response is generated by `Application::panic_response()`
+/// (or a plain 500 response if that returns `None`).
+///
+/// Unlike the hyper `Server`, `AppService` doesn't know about the connection, so it doesn't
+/// add the request extensions for `ClientAddr`, `LocalAddr`, `Listener`, `ShutdownSignal` and
+/// `RequestId`. Handlers using those extractors fail with `Error::ExtensionMissing`, unless
+/// the extensions are inserted by the caller (or by a middleware layer).
+pub struct AppService<A> {
+    app: Arc<A>,
+}
+
+impl<A> AppService<A> {
+    /// Create a service that handles requests with `app`
+    pub fn new(app: Arc<A>) -> Self {
+        Self { app }
+    }
+
+    /// The `Application` handling requests
+    pub fn app(&self) -> &Arc<A> {
+        &self.app
+    }
+}
+
+impl<A> Clone for AppService<A> {
+    fn clone(&self) -> Self {
+        Self {
+            app: self.app.clone(),
+        }
+    }
+}
+
+impl<A> From<Arc<A>> for AppService<A> {
+    fn from(app: Arc<A>) -> Self {
+        Self::new(app)
+    }
+}
+
+impl<A, B> Service<Request<B>> for AppService<A>
+where
+    A: Application + Sync + 'static,
+    A::ResponseBody: From<&'static str>,
+    B: Into<A::RequestBody>,
+{
+    type Response = Response<A::ResponseBody>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let app = self.app.clone();
+        let req = req.map(Into::into);
+        Box::pin(async move { Ok(handle_unwind(app, req).await) })
+    }
+}
+
+/// Forward requests for the remaining path to a tower service
+///
+/// The service gets a request with the path components consumed by `route!` stripped from
+/// the URI path, and the request body converted to a mendes `Body`. The response body is
+/// wrapped in a `Body` and converted to the `Application`'s `ResponseBody`. Errors from the
+/// service result in an `Error::MountedService` error. If the request body was already taken
+/// (for example, by a scope), the response is generated from an `Error::BodyTaken` error.
+#[async_trait]
+impl<A, S, B> Mount<A> for S
+where
+    A: Application + Sync,
+    A::ResponseBody: From<Body>,
+    Body: From<A::RequestBody>,
+    S: Service<Request<Body>, Response = Response<B>> + Send,
+    S::Future: Send,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    B: http_body::Body + Send + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    async fn mount(mut self, cx: &mut application::Context<A>) -> Response<A::ResponseBody> {
+        let body = match cx.take_body() {
+            Some(body) => Body::from(body),
+            None => {
+                let error = crate::Error::BodyTaken;
+                return A::Error::from(error).into_response(&cx.app, &cx.req);
+            }
+        };
+
+        let mut parts = cx.req.clone();
+        let rest = cx.path.rest(cx.req.uri.path());
+        let path = match cx.req.uri.query() {
+            Some(query) => format!("/{rest}?{query}"),
+            None => format!("/{rest}"),
+        };
+
+        let mut uri = parts.uri.into_parts();
+        // The remaining path was part of a valid path, so this can't fail
+        uri.path_and_query = Some(PathAndQuery::try_from(path).unwrap());
+        parts.uri = Uri::from_parts(uri).unwrap();
+
+        let ready = poll_fn(|cx| self.poll_ready(cx)).await.map_err(Into::into);
+        let result = match ready {
+            Ok(()) => self
+                .call(Request::from_parts(parts, body))
+                .await
+                .map_err(Into::into),
+            Err(error) => Err(error),
+        };
+
+        match result {
+            Ok(rsp) => rsp.map(|body| Body::wrap(body).into()),
+            Err(error) => {
+                let error = crate::Error::MountedService(error);
+                A::Error::from(error).into_response(&cx.app, &cx.req)
+            }
+        }
+    }
+}
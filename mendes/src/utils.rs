@@ -1,11 +1,68 @@
 #[cfg(feature = "static")]
 mod file_mod {
+    use std::fs::Metadata;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::time::UNIX_EPOCH;
+
+    #[cfg(any(
+        feature = "brotli",
+        feature = "gzip",
+        feature = "zlib",
+        feature = "zstd"
+    ))]
+    use http::header::{CONTENT_ENCODING, VARY};
+    use http::header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG, LAST_MODIFIED};
+    use http::request::Parts;
+    use http::{Method, StatusCode};
+    use tokio::fs::{self, File};
+
     use crate::application::Error;
-    use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
-    use http::StatusCode;
-    use std::path::PathBuf;
-    use tokio::fs;
+    use crate::body::{Body, ConditionalResponse, RangeResponse};
+    #[cfg(any(
+        feature = "brotli",
+        feature = "gzip",
+        feature = "zlib",
+        feature = "zstd"
+    ))]
+    use crate::body::{CompressionPolicy, Encoding, NoCompression};
+
+    /// Respond with the contents of the file at `path`
+    ///
+    /// The file is streamed in chunks rather than read into memory. If `path` is a directory,
+    /// its `index.html` is served instead. The response carries a strong `ETag` derived from
+    /// the file's size and modification time, as well as a `Last-Modified` header, and
+    /// conditional requests are evaluated as described for
+    /// [`ConditionalResponse::conditional()`]. Responses to `HEAD` requests have the headers of
+    /// the `GET` response, without a body. Byte ranges are served as described for
+    /// [`RangeResponse::ranged()`]. Text files get a `charset=utf-8` parameter in their
+    /// `Content-Type`.
+    ///
+    /// With the `zstd`, `brotli` or `gzip` features, precompressed siblings of the file (like
+    /// `app.js.zst`, `app.js.br` or `app.js.gz` for `app.js`) are served instead if the
+    /// request's `Accept-Encoding` allows it, with a `Content-Encoding` header and the
+    /// `NoCompression` extension so that `EncodeResponse` leaves them alone.
+    ///
+    /// Fails with `Error::FileNotFound` if the file doesn't exist, `Error::FileForbidden` if
+    /// the server may not read it, and `Error::FileRead` for other I/O errors.
+    pub async fn serve_file(req: &Parts, mut path: PathBuf) -> Result<http::Response<Body>, Error> {
+        let mut metadata = fs::metadata(&path).await.map_err(Error::from_io)?;
+        if metadata.is_dir() {
+            path = path.join("index.html");
+            metadata = fs::metadata(&path).await.map_err(Error::from_io)?;
+        }
 
+        serve(req, &path, &metadata).await
+    }
+
+    /// Respond with the contents of the file at `path`, read into memory
+    ///
+    /// If `path` is a directory, its `index.html` is served instead. Fails with
+    /// `Error::FileNotFound` if the file can't be read.
+    #[deprecated(
+        note = "use `serve_file()`, which streams the file and handles conditional \
+        and range requests"
+    )]
     pub async fn file<B>(mut path: PathBuf) -> Result<http::Response<B>, Error>
     where
         B: From<Vec<u8>>,
@@ -27,8 +84,189 @@ mod file_mod {
         let bytes = fs::read(path).await.map_err(|_| Error::FileNotFound)?;
         Ok(builder.body(B::from(bytes)).unwrap())
     }
+
+    /// Respond with the contents of the file at `path`, given its `metadata`
+    pub(super) async fn serve(
+        req: &Parts,
+        path: &Path,
+        metadata: &Metadata,
+    ) -> Result<http::Response<Body>, Error> {
+        if !metadata.is_file() {
+            return Err(Error::FileNotFound);
+        }
+
+        let mut builder = http::Response::builder().status(StatusCode::OK);
+        if let Some(mime) = mime_guess::from_path(path).first() {
+            builder = builder.header(CONTENT_TYPE, content_type(&mime));
+        }
+
+        #[cfg(any(
+            feature = "brotli",
+            feature = "gzip",
+            feature = "zlib",
+            feature = "zstd"
+        ))]
+        let sidecar = match precompressed(req, path).await {
+            (true, sidecar) => {
+                builder = builder.header(VARY, "accept-encoding");
+                sidecar
+            }
+            (false, _) => None,
+        };
+
+        // Serve the precompressed sibling instead, if there's one the client accepts
+        #[cfg(any(
+            feature = "brotli",
+            feature = "gzip",
+            feature = "zlib",
+            feature = "zstd"
+        ))]
+        let (path, metadata, variant) = match &sidecar {
+            Some((encoding, path, metadata)) => {
+                let name = encoding.as_str().unwrap_or_default();
+                builder = builder
+                    .header(CONTENT_ENCODING, name)
+                    .extension(NoCompression);
+                (path.as_path(), metadata, name)
+            }
+            None => (path, metadata, ""),
+        };
+        #[cfg(not(any(
+            feature = "brotli",
+            feature = "gzip",
+            feature = "zlib",
+            feature = "zstd"
+        )))]
+        let variant = "";
+
+        builder = builder.header(CONTENT_LENGTH, metadata.len());
+        if let Ok(modified) = metadata.modified() {
+            let nanos = modified
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_nanos())
+                .unwrap_or_default();
+            let etag = match variant {
+                "" => format!("\"{:x}-{nanos:x}\"", metadata.len()),
+                variant => format!("\"{:x}-{nanos:x}-{variant}\"", metadata.len()),
+            };
+
+            builder = builder
+                .header(ETAG, etag)
+                .header(LAST_MODIFIED, httpdate::fmt_http_date(modified));
+        }
+
+        let file = File::open(path).await.map_err(Error::from_io)?;
+        let mut rsp = builder
+            .body(Body::file(file, metadata.len()))
+            .unwrap()
+            .conditional(req)
+            .ranged(req);
+
+        if req.method == Method::HEAD {
+            *rsp.body_mut() = Body::empty();
+        }
+
+        Ok(rsp)
+    }
+
+    /// Find the precompressed siblings of `path` (like `app.js.br` for `app.js`)
+    ///
+    /// Returns whether there are any, and the one selected by `req`'s `Accept-Encoding`
+    /// header, if the client doesn't prefer the uncompressed file.
+    #[cfg(any(
+        feature = "brotli",
+        feature = "gzip",
+        feature = "zlib",
+        feature = "zstd"
+    ))]
+    async fn precompressed(
+        req: &Parts,
+        path: &Path,
+    ) -> (bool, Option<(Encoding, PathBuf, Metadata)>) {
+        let mut available = Vec::new();
+        for &(encoding, extension) in SIDECARS {
+            let mut sidecar = path.as_os_str().to_owned();
+            sidecar.push(extension);
+            let sidecar = PathBuf::from(sidecar);
+            if let Ok(metadata) = fs::metadata(&sidecar).await {
+                if metadata.is_file() {
+                    available.push((encoding, sidecar, metadata));
+                }
+            }
+        }
+
+        if available.is_empty() {
+            return (false, None);
+        }
+
+        let preference = available.iter().map(|(e, ..)| *e).collect::<Vec<_>>();
+        let selected = CompressionPolicy::new()
+            .prefer(&preference)
+            .negotiate(&req.headers);
+        let sidecar = available.into_iter().find(|(e, ..)| Some(*e) == selected);
+        (true, sidecar)
+    }
+
+    /// The `Content-Type` for `mime`, declaring UTF-8 as the charset for text types
+    fn content_type(mime: &mime_guess::Mime) -> String {
+        let text = match (mime.type_(), mime.subtype()) {
+            (mime_guess::mime::TEXT, _) => true,
+            (mime_guess::mime::APPLICATION, subtype) => {
+                matches!(subtype.as_str(), "javascript" | "json" | "xml")
+            }
+            (mime_guess::mime::IMAGE, subtype) => subtype == "svg",
+            _ => false,
+        };
+
+        match text && mime.get_param(mime_guess::mime::CHARSET).is_none() {
+            true => format!("{mime}; charset=utf-8"),
+            false => mime.to_string(),
+        }
+    }
+
+    /// Extensions of precompressed siblings, in order of preference
+    #[cfg(any(
+        feature = "brotli",
+        feature = "gzip",
+        feature = "zlib",
+        feature = "zstd"
+    ))]
+    const SIDECARS: &[(Encoding, &str)] = &[
+        #[cfg(feature = "zstd")]
+        (Encoding::Zstd, ".zst"),
+        #[cfg(feature = "brotli")]
+        (Encoding::Brotli, ".br"),
+        #[cfg(feature = "gzip")]
+        (Encoding::Gzip, ".gz"),
+    ];
+
+    impl Error {
+        pub(super) fn from_io(error: io::Error) -> Self {
+            match error.kind() {
+                io::ErrorKind::NotFound | io::ErrorKind::NotADirectory => Error::FileNotFound,
+                io::ErrorKind::PermissionDenied => Error::FileForbidden,
+                _ => Error::FileRead(error),
+            }
+        }
+    }
 }
 
 #[cfg(feature = "static")]
 #[cfg_attr(docsrs, doc(cfg(feature = "static")))]
-pub use file_mod::file;
+#[allow(deprecated)]
+pub use file_mod::{file, serve_file};
+
+#[cfg(feature = "embed")]
+mod embedded;
+#[cfg(feature = "embed")]
+#[cfg_attr(docsrs, doc(cfg(feature = "embed")))]
+pub use embedded::{Embedded, EmbeddedFile};
+#[cfg(feature = "embed")]
+#[cfg_attr(docsrs, doc(cfg(feature = "embed")))]
+pub use mendes_macros::embed;
+
+#[cfg(feature = "static")]
+mod static_dir;
+#[cfg(feature = "static")]
+#[cfg_attr(docsrs, doc(cfg(feature = "static")))]
+pub use static_dir::StaticDir;
@@ -0,0 +1,162 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+#[cfg(any(feature = "brotli", feature = "gzip"))]
+use http::header::{CONTENT_ENCODING, VARY};
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG};
+use http::request::Parts;
+use http::{Method, Response, StatusCode};
+
+use super::static_dir::{redirect_to_dir, segments};
+use crate::application::{Application, Context, Error, IntoResponse, Mount};
+use crate::body::{Body, ConditionalResponse, RangeResponse};
+#[cfg(any(feature = "brotli", feature = "gzip"))]
+use crate::body::{CompressionPolicy, Encoding, NoCompression};
+
+/// Files embedded into the binary by the `embed!` macro, for use as a `route!` target
+///
+/// The part of the request path not yet consumed by `route!` selects the file, with the
+/// same restrictions as for [`StaticDir`](super::StaticDir). Requests for a directory get
+/// its `index.html`, after a redirect to the path with a trailing slash if necessary.
+/// Responses carry the `ETag` computed at build time, and conditional and range requests
+/// are handled like for [`serve_file()`](super::serve_file()). If compressed variants were embedded
+/// (with the `embed-brotli` or `embed-gzip` features), the best one acceptable to the
+/// client is served.
+///
+/// ```ignore
+/// static ASSETS: Embedded = embed!("assets");
+///
+/// route!(match cx.path() {
+///     Some("assets") => &ASSETS,
+///     _ => index,
+/// })
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Embedded {
+    files: &'static [EmbeddedFile],
+}
+
+impl Embedded {
+    // This should only be used by the `embed!` macro, which sorts the files by path.
+    #[doc(hidden)]
+    pub const fn new(files: &'static [EmbeddedFile]) -> Self {
+        Self { files }
+    }
+
+    /// Get the file at `path`, relative to the embedded directory
+    pub fn get(&self, path: &str) -> Option<&'static EmbeddedFile> {
+        let files = self.files;
+        let i = files.binary_search_by(|file| file.path.cmp(path)).ok()?;
+        Some(&files[i])
+    }
+
+    /// Respond to `req` with the file at `rest`, the percent-encoded path relative to the
+    /// embedded directory
+    pub fn serve(&self, req: &Parts, rest: &str) -> Result<Response<Body>, Error> {
+        if !matches!(req.method, Method::GET | Method::HEAD) {
+            return Err(Error::MethodNotAllowed);
+        }
+
+        let path = segments(rest)?.join("/");
+        let index = match path.is_empty() {
+            true => "index.html".to_owned(),
+            false => format!("{path}/index.html"),
+        };
+
+        let file = match (self.get(&path), self.get(&index)) {
+            (Some(file), _) => file,
+            // Relative links in the index only resolve correctly from `dir/`
+            (None, Some(_)) if !req.uri.path().ends_with('/') => return Ok(redirect_to_dir(req)),
+            (None, Some(file)) => file,
+            (None, None) => return Err(Error::FileNotFound),
+        };
+
+        #[allow(unused_mut)] // Depends on features
+        let mut builder = Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, file.content_type);
+
+        #[cfg(any(feature = "brotli", feature = "gzip"))]
+        let (data, etag) = {
+            let variants = [
+                #[cfg(feature = "brotli")]
+                (Encoding::Brotli, file.brotli),
+                #[cfg(feature = "gzip")]
+                (Encoding::Gzip, file.gzip),
+            ];
+
+            let available = variants
+                .iter()
+                .filter_map(|&(encoding, data)| data.map(|_| encoding))
+                .collect::<Vec<_>>();
+            if !available.is_empty() {
+                builder = builder.header(VARY, "accept-encoding");
+            }
+
+            let selected = CompressionPolicy::new()
+                .prefer(&available)
+                .negotiate(&req.headers);
+            let variant = variants.iter().find_map(|&(encoding, data)| {
+                (Some(encoding) == selected).then_some(data).flatten()
+            });
+
+            match (variant, selected.and_then(Encoding::as_str)) {
+                (Some(data), Some(name)) => {
+                    builder = builder
+                        .header(CONTENT_ENCODING, name)
+                        .extension(NoCompression);
+                    let etag = file.etag.trim_end_matches('"');
+                    (data, format!("{etag}-{name}\""))
+                }
+                _ => (file.data, file.etag.to_owned()),
+            }
+        };
+        #[cfg(not(any(feature = "brotli", feature = "gzip")))]
+        let (data, etag) = (file.data, file.etag);
+
+        let mut rsp = builder
+            .header(CONTENT_LENGTH, data.len())
+            .header(ETAG, etag)
+            .body(Body::from(Bytes::from_static(data)))
+            .unwrap()
+            .conditional(req)
+            .ranged(req);
+
+        if req.method == Method::HEAD {
+            *rsp.body_mut() = Body::empty();
+        }
+
+        Ok(rsp)
+    }
+}
+
+#[async_trait]
+impl<A> Mount<A> for &Embedded
+where
+    A: Application + Sync,
+    A::ResponseBody: From<Body>,
+{
+    async fn mount(self, cx: &mut Context<A>) -> Response<A::ResponseBody> {
+        let rest = cx.path.rest(cx.req.uri.path());
+        match self.serve(&cx.req, rest) {
+            Ok(rsp) => rsp.map(A::ResponseBody::from),
+            Err(error) => A::Error::from(error).into_response(&cx.app, &cx.req),
+        }
+    }
+}
+
+/// A file embedded by the `embed!` macro
+#[derive(Debug)]
+pub struct EmbeddedFile {
+    /// The path relative to the embedded directory, with `/` as separator
+    pub path: &'static str,
+    /// The value for the `Content-Type` header
+    pub content_type: &'static str,
+    /// A strong entity tag, including the quotes
+    pub etag: &'static str,
+    /// The file's contents, as read at build time
+    pub data: &'static [u8],
+    /// Brotli-compressed data, if it's smaller
+    pub brotli: Option<&'static [u8]>,
+    /// Gzip-compressed data, if it's smaller
+    pub gzip: Option<&'static [u8]>,
+}
@@ -0,0 +1,392 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use http::header::{ACCEPT, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE, LOCATION};
+use http::request::Parts;
+use http::{HeaderValue, Method, Response, StatusCode};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use tokio::fs;
+
+use super::file_mod::serve;
+use crate::application::{Application, Context, Error, IntoResponse, Mount};
+use crate::body::{Body, ConditionalResponse};
+
+/// Serves files from a directory, for use as a `route!` target
+///
+/// The part of the request path not yet consumed by `route!` is resolved relative to the
+/// root directory. Path segments are percent-decoded one by one; requests for paths with
+/// `.` or `..` segments or encoded separators fail with `Error::FileInvalidPath`, and hidden
+/// files (with a name starting with `.`) are not served. Files are served as described for
+/// [`serve_file()`](super::serve_file()). Requests for a directory without a trailing slash are
+/// redirected to the path with the slash, so that relative links in the directory's
+/// `index.html` work. Only `GET` and `HEAD` requests are allowed.
+///
+/// `StaticDir` is cheap to clone, so it can be stored in the `Application` and cloned into
+/// the `route!` target:
+///
+/// ```ignore
+/// route!(match cx.path() {
+///     Some("assets") => cx.app.assets.clone(),
+///     _ => index,
+/// })
+/// ```
+#[derive(Clone, Debug)]
+pub struct StaticDir(Arc<Config>);
+
+impl StaticDir {
+    /// Serve the files in `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self(Arc::new(Config {
+            root: root.into(),
+            listing: false,
+            fallback: false,
+            cache_control: Vec::new(),
+        }))
+    }
+
+    /// Render a listing for directories without an `index.html` (disabled by default)
+    pub fn listing(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.0).listing = enabled;
+        self
+    }
+
+    /// Serve the root's `index.html` for HTML requests that don't match a file
+    ///
+    /// This is useful for single-page applications that do their own routing. Only requests
+    /// that accept `text/html` get the fallback, so that missing scripts and stylesheets are
+    /// still answered with a 404. Disabled by default.
+    pub fn fallback(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.0).fallback = enabled;
+        self
+    }
+
+    /// Set `Cache-Control` to `value` for files with a path matching `pattern`
+    ///
+    /// Patterns are matched against the path relative to the root, where `*` matches any
+    /// sequence of characters other than `/` and `**` matches any sequence of characters.
+    /// Patterns without a `/` are matched against the file name only. The first matching
+    /// pattern applies.
+    ///
+    /// ```ignore
+    /// StaticDir::new("public")
+    ///     .cache_control("assets/**", "public, max-age=31536000, immutable")
+    ///     .cache_control("*.html", "no-cache")
+    /// ```
+    pub fn cache_control(mut self, pattern: impl Into<String>, value: &'static str) -> Self {
+        Arc::make_mut(&mut self.0)
+            .cache_control
+            .push((pattern.into(), HeaderValue::from_static(value)));
+        self
+    }
+
+    async fn respond(&self, req: &Parts, rest: &str) -> Result<Response<Body>, Error> {
+        if !matches!(req.method, Method::GET | Method::HEAD) {
+            return Err(Error::MethodNotAllowed);
+        }
+
+        let (relative, mut rsp) = match self.serve(req, resolve(rest)?).await {
+            Err(Error::FileNotFound) if self.0.fallback && accepts_html(req) => {
+                let index = PathBuf::from("index.html");
+                let rsp = serve_relative(req, &self.0.root, &index).await?;
+                (index, rsp)
+            }
+            result => result?,
+        };
+
+        let redirect = rsp.status() == StatusCode::MOVED_PERMANENTLY;
+        if let Some(value) = self.0.cache_control_for(&relative).filter(|_| !redirect) {
+            if !rsp.headers().contains_key(CACHE_CONTROL) {
+                rsp.headers_mut().insert(CACHE_CONTROL, value.clone());
+            }
+        }
+
+        Ok(rsp)
+    }
+
+    /// Serve the file or directory at `relative`, returning the path of the file served
+    async fn serve(
+        &self,
+        req: &Parts,
+        relative: PathBuf,
+    ) -> Result<(PathBuf, Response<Body>), Error> {
+        let path = self.0.root.join(&relative);
+        let metadata = fs::metadata(&path).await.map_err(Error::from_io)?;
+        if !metadata.is_dir() {
+            return Ok((relative, serve(req, &path, &metadata).await?));
+        }
+
+        // Relative links in the index or listing only resolve correctly from `dir/`
+        if !req.uri.path().ends_with('/') {
+            return Ok((relative, redirect_to_dir(req)));
+        }
+
+        let index = relative.join("index.html");
+        match serve_relative(req, &self.0.root, &index).await {
+            Ok(rsp) => Ok((index, rsp)),
+            Err(Error::FileNotFound) if self.0.listing => {
+                Ok((relative, listing(req, &path).await?))
+            }
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[async_trait]
+impl<A> Mount<A> for StaticDir
+where
+    A: Application + Sync,
+    A::ResponseBody: From<Body>,
+{
+    async fn mount(self, cx: &mut Context<A>) -> Response<A::ResponseBody> {
+        let rest = cx.path.rest(cx.req.uri.path());
+        match self.respond(&cx.req, rest).await {
+            Ok(rsp) => rsp.map(A::ResponseBody::from),
+            Err(error) => A::Error::from(error).into_response(&cx.app, &cx.req),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Config {
+    root: PathBuf,
+    listing: bool,
+    fallback: bool,
+    cache_control: Vec<(String, HeaderValue)>,
+}
+
+impl Config {
+    fn cache_control_for(&self, relative: &Path) -> Option<&HeaderValue> {
+        let path = relative.to_str()?;
+        let name = relative.file_name()?.to_str()?;
+        self.cache_control.iter().find_map(|(pattern, value)| {
+            let target = match pattern.contains('/') {
+                true => path,
+                false => name,
+            };
+
+            glob(pattern.as_bytes(), target.as_bytes()).then_some(value)
+        })
+    }
+}
+
+async fn serve_relative(
+    req: &Parts,
+    root: &Path,
+    relative: &Path,
+) -> Result<Response<Body>, Error> {
+    let path = root.join(relative);
+    let metadata = fs::metadata(&path).await.map_err(Error::from_io)?;
+    serve(req, &path, &metadata).await
+}
+
+/// Redirect a request for a directory to the same path with a trailing slash
+///
+/// Repeated leading slashes are collapsed, because browsers would read a `Location` like
+/// `//docs/` as a URL on another host.
+pub(super) fn redirect_to_dir(req: &Parts) -> Response<Body> {
+    let path = req.uri.path().trim_start_matches(['/', '\\']);
+    let location = match req.uri.query() {
+        Some(query) => format!("/{path}/?{query}"),
+        None => format!("/{path}/"),
+    };
+
+    Response::builder()
+        .status(StatusCode::MOVED_PERMANENTLY)
+        .header(LOCATION, location)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Render an HTML listing of the (non-hidden) entries in the directory at `path`
+async fn listing(req: &Parts, path: &Path) -> Result<Response<Body>, Error> {
+    let mut entries = Vec::new();
+    let mut dir = fs::read_dir(path).await.map_err(Error::from_io)?;
+    while let Some(entry) = dir.next_entry().await.map_err(Error::from_io)? {
+        let name = match entry.file_name().into_string() {
+            Ok(name) if !name.starts_with('.') => name,
+            _ => continue,
+        };
+
+        let is_dir = entry.file_type().await.map_err(Error::from_io)?.is_dir();
+        entries.push((!is_dir, name));
+    }
+    entries.sort();
+
+    let base = escape(req.uri.path().trim_end_matches('/'));
+    let title = escape(&percent_decode_str(req.uri.path()).decode_utf8_lossy());
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index of {title}</title></head>\n\
+         <body>\n<h1>Index of {title}</h1>\n<ul>\n"
+    );
+
+    for (is_file, name) in entries {
+        let slash = if is_file { "" } else { "/" };
+        let href = escape(&utf8_percent_encode(&name, SEGMENT).to_string());
+        let name = escape(&name);
+        writeln!(
+            html,
+            "<li><a href=\"{base}/{href}{slash}\">{name}{slash}</a></li>"
+        )
+        .unwrap();
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+
+    let mut rsp = Response::builder()
+        .header(CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(CONTENT_LENGTH, html.len())
+        .body(Body::from(html))
+        .unwrap()
+        .with_etag()
+        .conditional(req);
+
+    if req.method == Method::HEAD {
+        *rsp.body_mut() = Body::empty();
+    }
+
+    Ok(rsp)
+}
+
+/// Resolve the (percent-encoded) remainder of the request path to a relative path
+fn resolve(rest: &str) -> Result<PathBuf, Error> {
+    Ok(segments(rest)?.iter().map(|segment| &**segment).collect())
+}
+
+/// Decode and validate the segments of the (percent-encoded) remainder of the request path
+///
+/// Empty segments are skipped. Fails for `.` and `..` segments, encoded separators and
+/// hidden files.
+pub(super) fn segments(rest: &str) -> Result<Vec<Cow<'_, str>>, Error> {
+    let mut segments = Vec::new();
+    for segment in rest.split('/').filter(|segment| !segment.is_empty()) {
+        let segment = percent_decode_str(segment)
+            .decode_utf8()
+            .map_err(|_| Error::PathDecode)?;
+
+        if segment.contains(['/', '\\', '\0']) {
+            return Err(Error::FileInvalidPath);
+        }
+
+        // Rejects `.` and `..`, as well as prefixes like `C:` on Windows
+        let mut components = Path::new(&*segment).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => {}
+            _ => return Err(Error::FileInvalidPath),
+        }
+
+        if segment.starts_with('.') {
+            return Err(Error::FileNotFound);
+        }
+
+        segments.push(segment);
+    }
+
+    Ok(segments)
+}
+
+fn accepts_html(req: &Parts) -> bool {
+    req.headers.get_all(ACCEPT).iter().any(|value| {
+        value.to_str().is_ok_and(|value| {
+            value.split(',').any(|range| {
+                let media = range.split(';').next().unwrap_or_default().trim();
+                media.eq_ignore_ascii_case("text/html")
+            })
+        })
+    })
+}
+
+/// Match `path` against a glob `pattern` (see `StaticDir::cache_control()`)
+fn glob(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| glob(rest, &path[i..])),
+        [b'*', rest @ ..] => (0..=path.len())
+            .take_while(|&i| i == 0 || path[i - 1] != b'/')
+            .any(|i| glob(rest, &path[i..])),
+        [c, rest @ ..] => path.first() == Some(c) && glob(rest, &path[1..]),
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Characters to percent-encode in a path segment
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{glob, resolve, Error};
+
+    #[test]
+    fn resolve_paths() {
+        for (rest, expected) in [
+            ("", Some("")),
+            ("app.js", Some("app.js")),
+            ("css//site.css", Some("css/site.css")),
+            ("docs/", Some("docs")),
+            ("hello%20world.txt", Some("hello world.txt")),
+            ("..", None),
+            ("a/../../etc/passwd", None),
+            ("%2e%2e/etc/passwd", None),
+            ("a%2f..%2f..%2fetc", None),
+            ("a%5c..%5cb", None),
+            ("a%00", None),
+            ("./a", None),
+        ] {
+            assert_eq!(resolve(rest).ok(), expected.map(PathBuf::from), "{rest:?}");
+        }
+
+        assert!(matches!(resolve(".env"), Err(Error::FileNotFound)));
+        assert!(matches!(resolve(".git/config"), Err(Error::FileNotFound)));
+    }
+
+    #[test]
+    fn glob_patterns() {
+        for (pattern, path, expected) in [
+            ("*.js", "app.js", true),
+            ("*.js", "app.css", false),
+            ("*.js", "js/app.js", false),
+            ("assets/*", "assets/app.js", true),
+            ("assets/*", "assets/js/app.js", false),
+            ("assets/**", "assets/js/app.js", true),
+            ("**.js", "js/app.js", true),
+            ("index.html", "index.html", true),
+            ("a*b*c", "abxbc", true),
+            ("a*b*c", "abxbd", false),
+        ] {
+            assert_eq!(
+                glob(pattern.as_bytes(), path.as_bytes()),
+                expected,
+                "{pattern} {path}"
+            );
+        }
+    }
+}
@@ -75,6 +75,18 @@ async fn test_named() {
     assert_eq!(rsp.into_body(), "Hello, Foo");
 }
 
+#[cfg(all(feature = "tower", feature = "hyper"))]
+#[tokio::test]
+async fn test_tower_service_connection_info() {
+    use mendes::tower::AppService;
+    use tower::ServiceExt;
+
+    // `AppService` doesn't know about the connection, so extraction fails gracefully
+    let service = AppService::new(Arc::new(App {}));
+    let rsp = service.oneshot(path_request("/client-addr")).await.unwrap();
+    assert_eq!(rsp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
 #[tokio::test]
 async fn test_named_no_arg() {
     let rsp = handle(path_request("/named")).await;
@@ -106,6 +118,18 @@ async fn basic() {
     assert_eq!(rsp.status(), StatusCode::OK);
 }
 
+#[cfg(feature = "tower")]
+#[tokio::test]
+async fn test_tower_service() {
+    use mendes::tower::AppService;
+    use tower::ServiceExt;
+
+    let service = AppService::new(Arc::new(App {}));
+    let rsp = service.oneshot(path_request("/named/Foo")).await.unwrap();
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(rsp.into_body(), "Hello, Foo");
+}
+
 fn path_request(path: &str) -> Request<()> {
     Request::builder()
         .uri(format!("https://example.com{path}"))
@@ -140,6 +164,8 @@ impl Application for App {
                 POST => named,
             },
             Some("custom_hello") => custom_error,
+            #[cfg(feature = "hyper")]
+            Some("client-addr") => client_addr,
 
             Some("query") => with_query,
         })
@@ -210,6 +236,15 @@ async fn hello(_: &App) -> Result<Response<String>, Error> {
         .unwrap())
 }
 
+#[cfg(feature = "hyper")]
+#[handler(GET)]
+async fn client_addr(_: &App, addr: mendes::hyper::ClientAddr) -> Result<Response<String>, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(format!("client address: {}", *addr))
+        .unwrap())
+}
+
 #[handler(GET)]
 async fn custom_error(_: &App, _x: ContextExtraction) -> Result<Response<String>, HandlerError> {
     Err(HandlerError::Test)
@@ -2,12 +2,22 @@
 
 use std::sync::Arc;
 
+#[cfg(feature = "zstd")]
+use async_compression::tokio::write::ZstdDecoder;
 #[cfg(all(feature = "compression", feature = "zlib"))]
-use async_compression::tokio::write::ZlibDecoder;
+use async_compression::tokio::write::{ZlibDecoder, ZlibEncoder};
 use async_trait::async_trait;
+#[cfg(all(feature = "compression", feature = "zlib"))]
+use bytes::Bytes;
 use http::header::{ACCEPT_ENCODING, CONTENT_TYPE};
+#[cfg(all(feature = "compression", feature = "zlib"))]
+use http::header::{CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, VARY};
+#[cfg(all(feature = "compression", feature = "zlib"))]
+use http_body::Frame;
 use http_body_util::BodyExt;
 #[cfg(all(feature = "compression", feature = "zlib"))]
+use http_body_util::StreamBody;
+#[cfg(any(feature = "zlib", feature = "zstd"))]
 use tokio::io::AsyncWriteExt;
 
 use mendes::application::IntoResponse;
@@ -29,8 +39,11 @@ async fn test_json_decode() {
 #[cfg(all(feature = "compression", feature = "zlib"))]
 #[tokio::test]
 async fn test_deflate_compression() {
-    let rsp = handle(path_request("/echo", "hello world", Some("deflate"))).await;
+    let rsp = handle(path_request("/echo", TEXT, Some("deflate"))).await;
     assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(rsp.headers()[CONTENT_ENCODING], "deflate");
+    assert_eq!(rsp.headers()[VARY], "accept-encoding");
+    assert!(!rsp.headers().contains_key(CONTENT_LENGTH));
     let body = rsp.into_body().collect().await.unwrap().to_bytes();
     // If the lower half of the first byte is 0x08, then the stream is
     // a zlib stream, otherwise it's a
@@ -38,15 +51,377 @@ async fn test_deflate_compression() {
     assert_eq!(body[0] & 0x0F, 0x8);
 
     // Decode as Zlib container
-    let mut decoder = ZlibDecoder::new(Vec::new());
+    assert_eq!(inflate(&body).await, TEXT);
+}
+
+#[cfg(feature = "zstd")]
+#[tokio::test]
+async fn test_zstd_compression() {
+    let rsp = handle(path_request("/echo", TEXT, Some("gzip;q=0.5, zstd"))).await;
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(rsp.headers()[http::header::CONTENT_ENCODING], "zstd");
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    // Zstandard frames start with the magic number 0xFD2FB528 (little-endian)
+    assert_eq!(body[..4], [0x28, 0xb5, 0x2f, 0xfd]);
+
+    let mut decoder = ZstdDecoder::new(Vec::new());
     decoder.write_all(&body).await.unwrap();
     decoder.shutdown().await.unwrap();
+    assert_eq!(String::from_utf8_lossy(&decoder.into_inner()), TEXT);
+}
+
+#[cfg(all(feature = "compression", feature = "zlib"))]
+#[tokio::test]
+async fn test_not_acceptable() {
+    let rsp = handle(path_request(
+        "/echo",
+        TEXT,
+        Some("identity;q=0, deflate;q=0"),
+    ))
+    .await;
+    assert_eq!(rsp.status(), StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(rsp.headers()[VARY], "accept-encoding");
+
+    // Also for bodies the policy wouldn't compress
+    let rsp = handle(path_request("/echo", "hi", Some("identity;q=0"))).await;
+    assert_eq!(rsp.status(), StatusCode::NOT_ACCEPTABLE);
+    let rsp = Response::builder()
+        .header(CONTENT_TYPE, "image/png")
+        .body(Body::from(TEXT))
+        .unwrap()
+        .encoded(&accept_encoding("identity;q=0, deflate;q=0"));
+    assert_eq!(rsp.status(), StatusCode::NOT_ACCEPTABLE);
+
+    // Error responses are sent unencoded rather than replaced
+    let rsp = handle(path_request("/missing", "", Some("*;q=0"))).await;
+    assert_eq!(rsp.status(), StatusCode::NOT_FOUND);
+    assert!(!rsp.headers().contains_key(CONTENT_ENCODING));
+}
+
+#[cfg(all(feature = "zlib", feature = "gzip"))]
+#[tokio::test]
+async fn test_server_preference() {
+    use mendes::body::{CompressionPolicy, Encoding};
+
+    let policy = CompressionPolicy::new().prefer(&[Encoding::Zlib, Encoding::Gzip]);
+    let req = accept_encoding("gzip, deflate, br");
+    let rsp = Response::new(Body::from(TEXT)).encoded_with(&req, &policy);
+    assert_eq!(rsp.headers()[CONTENT_ENCODING], "deflate");
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(inflate(&body).await, TEXT);
+
+    // Quality values take precedence over the server's preference
+    let req = accept_encoding("gzip, deflate;q=0.5");
+    let rsp = Response::new(Body::from(TEXT)).encoded_with(&req, &policy);
+    assert_eq!(rsp.headers()[CONTENT_ENCODING], "gzip");
+}
+
+#[cfg(all(feature = "compression", feature = "zlib"))]
+#[tokio::test]
+async fn test_compression_policy() {
+    use mendes::body::{CompressionPolicy, Encoding, Level, NoCompression};
+
+    let req = accept_encoding("deflate");
+    let default = CompressionPolicy::new();
+    let compressed = |rsp: &Response<Body>| rsp.headers().contains_key(CONTENT_ENCODING);
+
+    // Small bodies aren't worth compressing
+    let rsp = Response::new(Body::from("tiny")).encoded_with(&req, &default);
+    assert!(!compressed(&rsp));
+    assert!(!rsp.headers().contains_key(VARY));
+    let rsp = Response::new(Body::from("tiny")).encoded_with(&req, &default.clone().min_size(0));
+    assert!(compressed(&rsp));
+
+    // Already compressed media types are skipped by default
+    let png = || {
+        Response::builder()
+            .header(CONTENT_TYPE, "image/png")
+            .body(Body::from(TEXT))
+            .unwrap()
+    };
+    assert!(!compressed(&png().encoded_with(&req, &default)));
+    let policy = default.clone().deny_types(&["text/*"]);
+    assert!(compressed(&png().encoded_with(&req, &policy)));
+
+    let text = |ty: &'static str| {
+        Response::builder()
+            .header(CONTENT_TYPE, ty)
+            .body(Body::from(TEXT))
+            .unwrap()
+    };
+    let policy = default.clone().allow_types(&["text/*", "application/json"]);
+    assert!(compressed(
+        &text("text/html; charset=utf-8").encoded_with(&req, &policy)
+    ));
+    assert!(compressed(
+        &text("Application/JSON").encoded_with(&req, &policy)
+    ));
+    assert!(!compressed(
+        &text("application/xml").encoded_with(&req, &policy)
+    ));
+    let untyped = Response::new(Body::from(TEXT)).encoded_with(&req, &policy);
+    assert!(!compressed(&untyped));
+    assert!(!compressed(
+        &text("text/plain").encoded_with(&req, &policy.deny_types(&["text/plain"]))
+    ));
+
+    // Responses can opt out
+    let mut rsp = Response::new(Body::from(TEXT));
+    rsp.extensions_mut().insert(NoCompression);
+    assert!(!compressed(&rsp.encoded_with(&req, &default)));
+    let rsp = Response::builder()
+        .header(CACHE_CONTROL, "public, No-Transform")
+        .body(Body::from(TEXT))
+        .unwrap();
+    assert!(!compressed(&rsp.encoded_with(&req, &default)));
+
+    // Level 0 stores the data without compressing it
+    let data = "a".repeat(1000);
+    let rsp = Response::new(Body::from(data.clone())).encoded_with(
+        &req,
+        &default.clone().level(Encoding::Zlib, Level::Precise(0)),
+    );
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert!(body.len() > data.len());
+    assert_eq!(inflate(&body).await, data);
+
+    let rsp = Response::new(Body::from(data.clone())).encoded_with(&req, &default);
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert!(body.len() < 100);
+    assert_eq!(inflate(&body).await, data);
+}
+
+#[cfg(all(feature = "compression", feature = "zlib"))]
+#[tokio::test]
+async fn test_request_decompression() {
+    let rsp = handle(encoded_request(
+        "/echo",
+        deflate(TEXT.as_bytes()).await,
+        "deflate",
+    ))
+    .await;
+    assert_eq!(rsp.status(), StatusCode::OK);
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, TEXT);
+
+    // The length limit applies to the decompressed body
+    let compressed = deflate(&[b'a'; 1000]).await;
+    assert!(compressed.len() < 100);
+    let rsp = handle(encoded_request("/echo", compressed, "deflate")).await;
+    assert_eq!(rsp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    // Bodies that expand too much are rejected even if they'd fit
+    let rsp = handle(encoded_request(
+        "/large",
+        deflate(&[0; 1 << 20]).await,
+        "Deflate",
+    ))
+    .await;
+    assert_eq!(rsp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "request body compression ratio too high");
+
+    let rsp = handle(encoded_request(
+        "/echo",
+        b"not deflated".to_vec(),
+        "deflate",
+    ))
+    .await;
+    assert_eq!(rsp.status(), StatusCode::BAD_REQUEST);
+
+    let rsp = handle(encoded_request("/echo", b"compressed".to_vec(), "compress")).await;
+    assert_eq!(rsp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let rsp = handle(encoded_request(
+        "/echo",
+        b"twice".to_vec(),
+        "deflate, deflate",
+    ))
+    .await;
+    assert_eq!(rsp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let rsp = handle(encoded_request("/echo", TEXT.into(), "identity")).await;
+    assert_eq!(rsp.status(), StatusCode::OK);
+}
+
+#[cfg(all(feature = "json", feature = "zlib"))]
+#[tokio::test]
+async fn test_json_decompression() {
+    let rsp = handle(encoded_request(
+        "/sum",
+        deflate(b"[1,2,3]").await,
+        "deflate",
+    ))
+    .await;
+    assert_eq!(rsp.status(), StatusCode::OK);
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(String::from_utf8_lossy(&body), "6");
+}
+
+#[cfg(all(feature = "compression", feature = "zlib"))]
+#[tokio::test]
+async fn test_lazy_compression() {
+    let rsp = Response::builder()
+        .header(CONTENT_LENGTH, TEXT.len())
+        .header(VARY, "Accept-Encoding")
+        .body(Body::lazy(async { Ok(TEXT.into()) }))
+        .unwrap()
+        .encoded(&accept_encoding("deflate"));
+    assert_eq!(rsp.headers()[CONTENT_ENCODING], "deflate");
+    assert_eq!(rsp.headers().get_all(VARY).iter().count(), 1);
+    assert!(!rsp.headers().contains_key(CONTENT_LENGTH));
+
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(inflate(&body).await, TEXT);
+}
+
+#[cfg(all(feature = "compression", feature = "zlib"))]
+#[tokio::test]
+async fn test_streaming_compression() {
+    let frames = ["hello", " ", "world"].map(|s| Ok(Frame::data(Bytes::from(s))));
+    let body = Body::stream(StreamBody::new(futures_util::stream::iter(frames)));
+    let rsp = Response::new(body).encoded(&accept_encoding("deflate"));
+    assert_eq!(rsp.headers()[CONTENT_ENCODING], "deflate");
+
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(inflate(&body).await, "hello world");
+
+    // Responses that were already encoded are left alone
+    let rsp = Response::builder()
+        .header(CONTENT_ENCODING, "br")
+        .body(Body::from("compressed"))
+        .unwrap()
+        .encoded(&accept_encoding("deflate"));
+    assert_eq!(rsp.headers()[CONTENT_ENCODING], "br");
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "compressed");
+
+    // Without an acceptable encoding, the response still varies on `Accept-Encoding`
+    let rsp = Response::new(Body::from(TEXT)).encoded(&accept_encoding("identity"));
+    assert!(!rsp.headers().contains_key(CONTENT_ENCODING));
+    assert_eq!(rsp.headers()[VARY], "accept-encoding");
+}
+
+#[cfg(all(feature = "sse", feature = "compression", feature = "zlib"))]
+#[tokio::test]
+async fn test_sse_compression() {
+    use futures_util::StreamExt;
+    use mendes::body::{Event, Sse};
+
+    let stream =
+        futures_util::stream::iter([Event::new("first")]).chain(futures_util::stream::pending());
+    let rsp = Sse::new(stream)
+        .into_response()
+        .encoded(&accept_encoding("deflate"));
+    assert_eq!(rsp.headers()[CONTENT_ENCODING], "deflate");
+
+    // Each event is flushed, so it can be decoded before the stream ends
+    let mut body = rsp.into_body();
+    let frame = body.frame().await.unwrap().unwrap();
+    let mut decoder = ZlibDecoder::new(Vec::new());
+    decoder.write_all(frame.data_ref().unwrap()).await.unwrap();
+    decoder.flush().await.unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(decoder.get_ref()),
+        "data: first\n\n"
+    );
+}
+
+#[cfg(feature = "sse")]
+#[tokio::test]
+async fn test_sse() {
+    let rsp = handle(
+        Request::builder()
+            .uri("https://example.com/events")
+            .header("last-event-id", "1")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(rsp.headers()[CONTENT_TYPE], "text/event-stream");
+    assert_eq!(rsp.headers()["cache-control"], "no-cache");
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
     assert_eq!(
-        String::from_utf8_lossy(&decoder.into_inner()),
-        "hello world"
+        String::from_utf8_lossy(&body),
+        "event: update\nid: 2\ndata: after 1\n\ndata: multi\ndata: line\n\n"
     );
 }
 
+#[cfg(feature = "sse")]
+#[tokio::test]
+async fn test_sse_keep_alive() {
+    use std::time::Duration;
+
+    use mendes::body::{Event, Sse};
+
+    let stream = futures_util::stream::pending::<Event>();
+    let rsp = Sse::new(stream)
+        .keep_alive(Duration::from_millis(10))
+        .into_response();
+    let mut body = rsp.into_body();
+    let frame = body.frame().await.unwrap().unwrap();
+    assert_eq!(frame.into_data().unwrap(), ":\n\n");
+}
+
+#[cfg(feature = "tower")]
+#[tokio::test]
+async fn test_mount_service() {
+    let rsp = handle(path_request("/mounted/foo/bar?baz=1", "hello", None)).await;
+    assert_eq!(rsp.status(), StatusCode::OK);
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(String::from_utf8_lossy(&body), "POST /foo/bar?baz=1: hello");
+
+    let rsp = handle(path_request("/mounted", "", None)).await;
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(String::from_utf8_lossy(&body), "POST /: ");
+
+    let rsp = handle(path_request("/failing/foo", "", None)).await;
+    assert_eq!(rsp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    // The body can't be forwarded if it was already taken
+    let rsp = handle(path_request("/taken", "hello", None)).await;
+    assert_eq!(rsp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[cfg(feature = "compression")]
+const TEXT: &str = "Hello, world! Hello, world! Hello, world!";
+
+#[cfg(all(feature = "compression", feature = "zlib"))]
+fn accept_encoding(encoding: &'static str) -> Parts {
+    let req = Request::builder()
+        .header(ACCEPT_ENCODING, encoding)
+        .body(())
+        .unwrap();
+    req.into_parts().0
+}
+
+#[cfg(all(feature = "compression", feature = "zlib"))]
+async fn inflate(data: &[u8]) -> String {
+    let mut decoder = ZlibDecoder::new(Vec::new());
+    decoder.write_all(data).await.unwrap();
+    decoder.shutdown().await.unwrap();
+    String::from_utf8(decoder.into_inner()).unwrap()
+}
+
+#[cfg(all(feature = "compression", feature = "zlib"))]
+fn encoded_request(path: &str, body: Vec<u8>, encoding: &str) -> Request<Body> {
+    Request::builder()
+        .method(Method::POST)
+        .uri(format!("https://example.com{path}"))
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_ENCODING, encoding)
+        .body(body.into())
+        .unwrap()
+}
+
+#[cfg(all(feature = "compression", feature = "zlib"))]
+async fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new());
+    encoder.write_all(data).await.unwrap();
+    encoder.shutdown().await.unwrap();
+    encoder.into_inner()
+}
+
 fn path_request(path: &str, body: &str, compression: Option<&'static str>) -> Request<Body> {
     let mut request = Request::builder()
         .method(Method::POST)
@@ -75,6 +450,21 @@ impl Application for App {
             #[cfg(feature = "json")]
             Some("sum") => sum,
             Some("echo") => echo,
+            #[cfg(feature = "compression")]
+            Some("large") => large,
+            #[cfg(feature = "sse")]
+            Some("events") => events,
+            #[cfg(feature = "tower")]
+            Some("mounted") => tower::service_fn(mounted),
+            #[cfg(feature = "tower")]
+            Some("taken") => {
+                cx.take_body();
+                tower::service_fn(mounted)
+            }
+            #[cfg(feature = "tower")]
+            Some("failing") => tower::service_fn(|_: Request<Body>| async {
+                Err::<Response<Body>, _>(std::io::Error::other("failed"))
+            }),
         });
 
         #[cfg(feature = "compression")]
@@ -94,11 +484,53 @@ async fn sum(_: &App, req: &Parts, body: Body) -> Result<Response<Body>, Error>
 }
 
 #[handler(POST)]
-async fn echo(_: &App, _req: &Parts, body: Body) -> Result<Response<Body>, Error> {
-    let content = App::body_bytes(body, 100).await.unwrap();
+async fn echo(_: &App, req: &Parts, body: Body) -> Result<Response<Body>, Error> {
+    let content = App::body_bytes_decoded(req, body, 100).await?;
     Ok(Response::builder().body(content.into()).unwrap())
 }
 
+#[cfg(feature = "compression")]
+#[handler(POST)]
+async fn large(_: &App, req: &Parts, body: Body) -> Result<Response<Body>, Error> {
+    let content = App::body_bytes_decoded(req, body, 16 << 20).await?;
+    Ok(Response::new(format!("{} bytes", content.len()).into()))
+}
+
+#[cfg(feature = "tower")]
+async fn mounted(req: Request<Body>) -> Result<Response<String>, std::convert::Infallible> {
+    let (parts, body) = req.into_parts();
+    let body = body.collect().await.unwrap().to_bytes();
+    Ok(Response::new(format!(
+        "{} {}: {}",
+        parts.method,
+        parts.uri.path_and_query().unwrap(),
+        String::from_utf8_lossy(&body)
+    )))
+}
+
+#[cfg(feature = "sse")]
+type Events = futures_util::stream::Iter<std::vec::IntoIter<mendes::body::Event>>;
+
+#[cfg(feature = "sse")]
+#[handler(GET)]
+async fn events(
+    _: &App,
+    last: mendes::body::LastEventId,
+) -> Result<mendes::body::Sse<Events>, Error> {
+    use mendes::body::{Event, Sse};
+
+    let last = last.as_str().unwrap_or("0");
+    let next = last.parse::<u32>().unwrap() + 1;
+    let events = vec![
+        Event::default()
+            .event("update")
+            .id(&next.to_string())
+            .data(&format!("after {last}")),
+        Event::new("multi\nline"),
+    ];
+    Ok(Sse::new(futures_util::stream::iter(events)))
+}
+
 #[derive(Debug)]
 enum Error {
     Mendes(mendes::Error),
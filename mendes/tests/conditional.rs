@@ -0,0 +1,124 @@
+#![cfg(all(feature = "application", feature = "body-util"))]
+
+use http::header::{
+    CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    IF_UNMODIFIED_SINCE, LAST_MODIFIED,
+};
+use http_body_util::BodyExt;
+
+use mendes::body::ConditionalResponse;
+use mendes::http::request::Parts;
+use mendes::http::{Method, Request, Response, StatusCode};
+use mendes::Body;
+
+#[tokio::test]
+async fn test_not_modified() {
+    let rsp = response().conditional(&request(Method::GET, &[(IF_NONE_MATCH, "W/\"v1\"")]));
+    assert_eq!(rsp.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(rsp.headers()[ETAG], "\"v1\"");
+    assert_eq!(rsp.headers()[CACHE_CONTROL], "max-age=60");
+    assert!(!rsp.headers().contains_key(CONTENT_TYPE));
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert!(body.is_empty());
+
+    let rsp = response().conditional(&request(Method::GET, &[(IF_NONE_MATCH, "\"v0\"")]));
+    assert_eq!(rsp.status(), StatusCode::OK);
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "hello world");
+
+    let rsp = response().conditional(&request(
+        Method::HEAD,
+        &[(IF_MODIFIED_SINCE, "Wed, 21 Oct 2015 07:28:00 GMT")],
+    ));
+    assert_eq!(rsp.status(), StatusCode::NOT_MODIFIED);
+
+    let rsp = response().conditional(&request(
+        Method::GET,
+        &[(IF_MODIFIED_SINCE, "Tue, 20 Oct 2015 07:28:00 GMT")],
+    ));
+    assert_eq!(rsp.status(), StatusCode::OK);
+
+    // `If-None-Match` takes precedence over `If-Modified-Since`
+    let rsp = response().conditional(&request(
+        Method::GET,
+        &[
+            (IF_NONE_MATCH, "\"v0\""),
+            (IF_MODIFIED_SINCE, "Wed, 21 Oct 2015 07:28:00 GMT"),
+        ],
+    ));
+    assert_eq!(rsp.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_precondition_failed() {
+    let rsp = response().conditional(&request(Method::PUT, &[(IF_MATCH, "\"v0\"")]));
+    assert_eq!(rsp.status(), StatusCode::PRECONDITION_FAILED);
+
+    let rsp = response().conditional(&request(Method::PUT, &[(IF_MATCH, "\"v0\", \"v1\"")]));
+    assert_eq!(rsp.status(), StatusCode::OK);
+
+    let rsp = response().conditional(&request(
+        Method::DELETE,
+        &[(IF_UNMODIFIED_SINCE, "Tue, 20 Oct 2015 07:28:00 GMT")],
+    ));
+    assert_eq!(rsp.status(), StatusCode::PRECONDITION_FAILED);
+
+    // `If-None-Match` fails with 412 for unsafe methods
+    let rsp = response().conditional(&request(Method::POST, &[(IF_NONE_MATCH, "*")]));
+    assert_eq!(rsp.status(), StatusCode::PRECONDITION_FAILED);
+
+    // Error responses are left alone
+    let mut rsp = response();
+    *rsp.status_mut() = StatusCode::NOT_FOUND;
+    let rsp = rsp.conditional(&request(Method::PUT, &[(IF_MATCH, "\"v0\"")]));
+    assert_eq!(rsp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_computed_etag() {
+    let rsp = Response::new(Body::from("hello world")).with_etag();
+    let etag = rsp.headers()[ETAG].clone();
+    assert!(etag.to_str().unwrap().starts_with('"'));
+    assert_eq!(
+        Response::new(Body::from("hello world"))
+            .with_etag()
+            .headers()[ETAG],
+        etag
+    );
+    assert_ne!(
+        Response::new(Body::from("hello there"))
+            .with_etag()
+            .headers()[ETAG],
+        etag
+    );
+
+    let req = request(Method::GET, &[(IF_NONE_MATCH, etag.to_str().unwrap())]);
+    let rsp = Response::new(Body::from("hello world"))
+        .with_etag()
+        .conditional(&req);
+    assert_eq!(rsp.status(), StatusCode::NOT_MODIFIED);
+
+    // Existing entity tags are kept, and streaming bodies are left alone
+    assert_eq!(response().with_etag().headers()[ETAG], "\"v1\"");
+    let rsp = Response::new(Body::lazy(async { Ok("hello world".into()) })).with_etag();
+    assert!(!rsp.headers().contains_key(ETAG));
+}
+
+fn response() -> Response<Body> {
+    Response::builder()
+        .header(CONTENT_TYPE, "text/plain")
+        .header(CACHE_CONTROL, "max-age=60")
+        .header(ETAG, "\"v1\"")
+        .header(LAST_MODIFIED, "Wed, 21 Oct 2015 07:28:00 GMT")
+        .body(Body::from("hello world"))
+        .unwrap()
+}
+
+fn request(method: Method, headers: &[(http::HeaderName, &str)]) -> Parts {
+    let mut builder = Request::builder().method(method).uri("/");
+    for (name, value) in headers {
+        builder = builder.header(name, *value);
+    }
+
+    builder.body(()).unwrap().into_parts().0
+}
@@ -0,0 +1,168 @@
+#![cfg(all(feature = "embed", feature = "body-util"))]
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use http::header::{
+    CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_NONE_MATCH, LOCATION, RANGE,
+};
+use http_body_util::BodyExt;
+
+use mendes::application::IntoResponse;
+use mendes::body::ConditionalResponse;
+use mendes::http::request::Parts;
+use mendes::http::{Method, Request, Response, StatusCode};
+use mendes::utils::{embed, Embedded};
+use mendes::{route, Application, Body, Context};
+
+static ASSETS: Embedded = embed!("tests/embed");
+
+const CSS: &str = include_str!("embed/css/site.css");
+
+#[tokio::test]
+async fn test_embedded() {
+    let file = ASSETS.get("css/site.css").unwrap();
+    assert_eq!(file.data, CSS.as_bytes());
+    assert!(ASSETS.get(".env").is_none());
+
+    let rsp = get("/assets/css/site.css", &[]).await;
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(rsp.headers()[CONTENT_TYPE], "text/css; charset=utf-8");
+    assert_eq!(rsp.headers()[CONTENT_LENGTH], CSS.len().to_string());
+    let etag = rsp.headers()[ETAG].clone();
+    assert_eq!(etag, file.etag);
+    // The build-time tag uses the same hash as `with_etag()`
+    let tagged = Response::new(Body::from(CSS)).with_etag();
+    assert_eq!(tagged.headers()[ETAG], etag);
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, CSS);
+
+    let rsp = get("/assets/docs", &[]).await;
+    assert_eq!(rsp.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(rsp.headers()[LOCATION], "/assets/docs/");
+    let rsp = get("//docs", &[]).await;
+    assert_eq!(rsp.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(rsp.headers()[LOCATION], "/docs/");
+
+    let rsp = get("/assets", &[]).await;
+    assert_eq!(rsp.status(), StatusCode::MOVED_PERMANENTLY);
+    let rsp = get("/assets/", &[]).await;
+    assert_eq!(rsp.headers()[CONTENT_TYPE], "text/html; charset=utf-8");
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "<h1>embedded</h1>\n");
+
+    let rsp = get("/assets/docs/", &[]).await;
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "<h1>docs</h1>\n");
+
+    for path in [
+        "/assets/.env",
+        "/assets/missing.css",
+        "/assets/docs/../.env",
+    ] {
+        let rsp = get(path, &[]).await;
+        assert_eq!(rsp.status(), StatusCode::NOT_FOUND, "{path}");
+    }
+}
+
+#[tokio::test]
+async fn test_conditional_and_ranges() {
+    let etag = ASSETS.get("css/site.css").unwrap().etag;
+    let rsp = get("/assets/css/site.css", &[(IF_NONE_MATCH, etag)]).await;
+    assert_eq!(rsp.status(), StatusCode::NOT_MODIFIED);
+
+    let rsp = get("/assets/css/site.css", &[(RANGE, "bytes=0-7")]).await;
+    assert_eq!(rsp.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        rsp.headers()[CONTENT_RANGE],
+        format!("bytes 0-7/{}", CSS.len())
+    );
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, CSS[..8]);
+}
+
+#[cfg(all(feature = "embed-brotli", feature = "embed-gzip"))]
+#[tokio::test]
+async fn test_precompressed() {
+    use http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, VARY};
+
+    let file = ASSETS.get("css/site.css").unwrap();
+    let (brotli, gzip) = (file.brotli.unwrap(), file.gzip.unwrap());
+    assert!(brotli.len() < CSS.len() && gzip.len() < CSS.len());
+
+    let rsp = get("/assets/css/site.css", &[(ACCEPT_ENCODING, "gzip, br")]).await;
+    assert_eq!(rsp.headers()[CONTENT_ENCODING], "br");
+    assert_eq!(rsp.headers()[VARY], "accept-encoding");
+    assert_eq!(rsp.headers()[CONTENT_LENGTH], brotli.len().to_string());
+    assert_ne!(rsp.headers()[ETAG], file.etag);
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, brotli);
+
+    let rsp = get("/assets/css/site.css", &[(ACCEPT_ENCODING, "gzip")]).await;
+    assert_eq!(rsp.headers()[CONTENT_ENCODING], "gzip");
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, gzip);
+
+    let rsp = get("/assets/css/site.css", &[(ACCEPT_ENCODING, "identity")]).await;
+    assert!(!rsp.headers().contains_key(CONTENT_ENCODING));
+    assert_eq!(rsp.headers()[VARY], "accept-encoding");
+
+    // Compressing tiny files isn't worth it
+    let rsp = get("/assets/index.html", &[(ACCEPT_ENCODING, "br")]).await;
+    assert!(!rsp.headers().contains_key(CONTENT_ENCODING));
+    assert!(!rsp.headers().contains_key(VARY));
+}
+
+async fn get(path: &str, headers: &[(http::HeaderName, &str)]) -> Response<Body> {
+    let mut req = Request::builder().method(Method::GET).uri(path);
+    for (name, value) in headers {
+        req = req.header(name, *value);
+    }
+
+    let req = req.body(Body::empty()).unwrap();
+    App::handle(Context::new(Arc::new(App {}), req)).await
+}
+
+struct App {}
+
+#[async_trait]
+impl Application for App {
+    type RequestBody = Body;
+    type ResponseBody = Body;
+    type Error = Error;
+
+    async fn handle(mut cx: Context<Self>) -> Response<Self::ResponseBody> {
+        route!(match cx.path() {
+            Some("assets") => &ASSETS,
+            _ => &ASSETS,
+        })
+    }
+}
+
+#[derive(Debug)]
+enum Error {
+    Mendes(mendes::Error),
+}
+
+impl From<mendes::Error> for Error {
+    fn from(e: mendes::Error) -> Self {
+        Error::Mendes(e)
+    }
+}
+
+impl From<&Error> for StatusCode {
+    fn from(e: &Error) -> StatusCode {
+        let Error::Mendes(e) = e;
+        StatusCode::from(e)
+    }
+}
+
+impl IntoResponse<App> for Error {
+    fn into_response(self, _: &App, _: &Parts) -> Response<Body> {
+        let Error::Mendes(err) = self;
+        Response::builder()
+            .status(StatusCode::from(&err))
+            .body(err.to_string().into())
+            .unwrap()
+    }
+}
@@ -0,0 +1,271 @@
+#![cfg(all(feature = "http3", feature = "body-util"))]
+
+use std::future::poll_fn;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::{Buf, Bytes};
+use mendes::application::IntoResponse;
+use mendes::http::header::ALT_SVC;
+use mendes::http::request::Parts;
+use mendes::http::{Method, Request, Response, StatusCode, Version};
+use mendes::hyper::{ClientAddr, Http3Listener, Listener, Server};
+use mendes::{handler, route, Application, Body, Context};
+use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+use tokio::time::{sleep, timeout};
+
+#[tokio::test]
+async fn test_http3() {
+    let addr = "127.0.0.1:12360".parse::<SocketAddr>().unwrap();
+    let (cert, tls) = tls_config();
+    let server = Server::bind(addr, App)
+        .await
+        .unwrap()
+        .trusted_proxies(["127.0.0.1".parse().unwrap()].into_iter().collect())
+        .with_http3(Http3Listener::bind(addr, &tls).unwrap());
+    let shutdown = server.shutdown_handle();
+    let handle = tokio::spawn(server.serve());
+    sleep(Duration::from_millis(10)).await;
+
+    // Plain HTTP responses don't advertise the HTTP/3 endpoint
+    let rsp = reqwest::get(format!("http://{addr}/listener"))
+        .await
+        .unwrap();
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert!(!rsp.headers().contains_key(ALT_SVC));
+    assert_eq!(rsp.text().await.unwrap(), "listener 0");
+
+    // Responses to requests a trusted proxy received over TLS do
+    let rsp = reqwest::Client::new()
+        .get(format!("http://{addr}/listener"))
+        .header("x-forwarded-for", "203.0.113.7")
+        .header("x-forwarded-proto", "https")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(rsp.headers()[ALT_SVC], "h3=\":12360\"; ma=86400");
+
+    let (endpoint, mut send_request, driver) = connect(addr, cert).await;
+
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri(format!("https://localhost:{}/echo", addr.port()))
+        .body(())
+        .unwrap();
+    let mut stream = send_request.send_request(req).await.unwrap();
+    stream.send_data(Bytes::from("hello")).await.unwrap();
+    stream.finish().await.unwrap();
+
+    let rsp = stream.recv_response().await.unwrap();
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(rsp.version(), Version::HTTP_3);
+    assert!(rsp.headers().contains_key("x-request-id"));
+    assert!(!rsp.headers().contains_key(ALT_SVC));
+
+    let mut body = Vec::new();
+    while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+        while chunk.has_remaining() {
+            let len = chunk.chunk().len();
+            body.extend_from_slice(chunk.chunk());
+            chunk.advance(len);
+        }
+    }
+    assert_eq!(
+        String::from_utf8(body).unwrap(),
+        "HTTP/3.0 from 127.0.0.1 on listener 1: hello"
+    );
+
+    // Shutting down sends a GOAWAY and closes the idle connection
+    shutdown.shutdown();
+    timeout(Duration::from_secs(5), handle)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    timeout(Duration::from_secs(5), driver)
+        .await
+        .unwrap()
+        .unwrap();
+    endpoint.wait_idle().await;
+}
+
+#[tokio::test]
+async fn test_http3_graceful_shutdown() {
+    let addr = "127.0.0.1:12363".parse::<SocketAddr>().unwrap();
+    let (cert, tls) = tls_config();
+    let server = Server::bind(addr, App)
+        .await
+        .unwrap()
+        .with_http3(Http3Listener::bind(addr, &tls).unwrap());
+    let shutdown = server.shutdown_handle();
+    let handle = tokio::spawn(server.serve());
+    sleep(Duration::from_millis(10)).await;
+
+    let (endpoint, mut send_request, driver) = connect(addr, cert).await;
+    let req = Request::builder()
+        .uri(format!("https://localhost:{}/slow", addr.port()))
+        .body(())
+        .unwrap();
+    let mut stream = send_request.send_request(req).await.unwrap();
+    stream.finish().await.unwrap();
+
+    // Shut down while the request is in flight; the server waits for it to complete
+    sleep(Duration::from_millis(50)).await;
+    shutdown.shutdown();
+    sleep(Duration::from_millis(50)).await;
+    assert!(!handle.is_finished());
+
+    let rsp = stream.recv_response().await.unwrap();
+    assert_eq!(rsp.status(), StatusCode::OK);
+    let mut body = Vec::new();
+    while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+        while chunk.has_remaining() {
+            let len = chunk.chunk().len();
+            body.extend_from_slice(chunk.chunk());
+            chunk.advance(len);
+        }
+    }
+    assert_eq!(body, b"done");
+
+    timeout(Duration::from_secs(5), handle)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    timeout(Duration::from_secs(5), driver)
+        .await
+        .unwrap()
+        .unwrap();
+    endpoint.wait_idle().await;
+}
+
+async fn connect(
+    addr: SocketAddr,
+    cert: CertificateDer<'static>,
+) -> (
+    quinn::Endpoint,
+    h3::client::SendRequest<h3_quinn::OpenStreams, Bytes>,
+    tokio::task::JoinHandle<()>,
+) {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert).unwrap();
+    let mut tls = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])
+    .unwrap()
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    tls.alpn_protocols = vec![b"h3".to_vec()];
+
+    let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(tls).unwrap();
+    let mut endpoint = quinn::Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+
+    let conn = endpoint.connect(addr, "localhost").unwrap().await.unwrap();
+    let (mut driver, send_request) = h3::client::new(h3_quinn::Connection::new(conn))
+        .await
+        .unwrap();
+    let driver = tokio::spawn(async move {
+        poll_fn(|cx| driver.poll_close(cx)).await;
+    });
+
+    (endpoint, send_request, driver)
+}
+
+fn tls_config() -> (CertificateDer<'static>, rustls::ServerConfig) {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+    let cert = certified.cert.der().clone();
+    let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+    let tls = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])
+    .unwrap()
+    .with_no_client_auth()
+    .with_single_cert(vec![cert.clone()], key.into())
+    .unwrap();
+    (cert, tls)
+}
+
+struct App;
+
+#[async_trait]
+impl Application for App {
+    type RequestBody = Body;
+    type ResponseBody = Body;
+    type Error = Error;
+
+    async fn handle(mut cx: Context<Self>) -> Response<Self::ResponseBody> {
+        route!(match cx.path() {
+            Some("echo") => echo,
+            Some("listener") => listener,
+            Some("slow") => slow,
+        })
+    }
+}
+
+#[handler(POST)]
+async fn echo(
+    _: &App,
+    req: &Parts,
+    addr: ClientAddr,
+    listener: Listener,
+    body: Body,
+) -> Result<Response<Body>, Error> {
+    let body = App::body_bytes_decoded(req, body, 1024).await?;
+    Ok(Response::new(
+        format!(
+            "{:?} from {} on listener {}: {}",
+            req.version,
+            addr.ip(),
+            listener.index(),
+            String::from_utf8_lossy(&body)
+        )
+        .into(),
+    ))
+}
+
+#[handler(GET)]
+async fn listener(_: &App, listener: Listener) -> Result<Response<Body>, Error> {
+    Ok(Response::new(
+        format!("listener {}", listener.index()).into(),
+    ))
+}
+
+#[handler(GET)]
+async fn slow(_: &App) -> Result<Response<Body>, Error> {
+    sleep(Duration::from_millis(200)).await;
+    Ok(Response::new("done".into()))
+}
+
+#[derive(Debug)]
+enum Error {
+    Mendes(mendes::Error),
+}
+
+impl From<mendes::Error> for Error {
+    fn from(e: mendes::Error) -> Self {
+        Error::Mendes(e)
+    }
+}
+
+impl From<&Error> for StatusCode {
+    fn from(e: &Error) -> StatusCode {
+        let Error::Mendes(e) = e;
+        StatusCode::from(e)
+    }
+}
+
+impl IntoResponse<App> for Error {
+    fn into_response(self, _: &App, _: &Parts) -> Response<Body> {
+        let Error::Mendes(err) = self;
+        Response::builder()
+            .status(StatusCode::from(&err))
+            .body(err.to_string().into())
+            .unwrap()
+    }
+}
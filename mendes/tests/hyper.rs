@@ -1,18 +1,27 @@
 #![cfg(feature = "hyper")]
 
+use std::any::Any;
 use std::fmt::{self, Display};
+use std::future::Pending;
 use std::io;
 use std::net::SocketAddr;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use bytes::Bytes;
-use mendes::application::IntoResponse;
+use mendes::application::{IntoResponse, Route};
+use mendes::http::header::ACCEPT;
 use mendes::http::request::Parts;
 use mendes::http::{Response, StatusCode};
 use mendes::hyper::body::Incoming;
-use mendes::hyper::{ClientAddr, Server};
+use mendes::hyper::{
+    ClientAddr, Forwarded, Listener, LocalAddr, ProxyProtocol, RequestId, Server, ShutdownSignal,
+};
 use mendes::{handler, route, Application, Body, Context};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
 use tokio::task::JoinHandle;
 use tokio::time::sleep;
 
@@ -22,7 +31,11 @@ struct ServerRunner {
 
 impl ServerRunner {
     async fn run(addr: SocketAddr) -> Self {
-        let handle = tokio::spawn(Server::bind(addr, App::default()).await.unwrap().serve());
+        Self::spawn(Server::bind(addr, App::default()).await.unwrap()).await
+    }
+
+    async fn spawn(server: Server<App, Pending<()>>) -> Self {
+        let handle = tokio::spawn(server.serve());
         sleep(Duration::from_millis(10)).await;
         Self { handle }
     }
@@ -54,6 +67,508 @@ async fn test_client_addr() {
     runner.stop();
 }
 
+#[tokio::test]
+async fn test_proxy_protocol_v1() {
+    let addr = "127.0.0.1:12346".parse::<SocketAddr>().unwrap();
+    let server = Server::bind(addr, App::default()).await.unwrap();
+    let runner = ServerRunner::spawn(server.proxy_protocol(ProxyProtocol::Required)).await;
+
+    let header = b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\r\n";
+    let rsp = raw_request(addr, header, "/client-addr").await;
+    assert!(rsp.starts_with("HTTP/1.1 200 OK\r\n"), "{rsp}");
+    assert!(rsp.ends_with("client_addr: 192.0.2.1"), "{rsp}");
+
+    let rsp = raw_request(addr, header, "/local-addr").await;
+    assert!(rsp.ends_with("local_addr: 198.51.100.2:443"), "{rsp}");
+
+    runner.stop();
+}
+
+#[tokio::test]
+async fn test_proxy_protocol_v2() {
+    let addr = "127.0.0.1:12347".parse::<SocketAddr>().unwrap();
+    let server = Server::bind(addr, App::default()).await.unwrap();
+    let runner = ServerRunner::spawn(server.proxy_protocol(ProxyProtocol::Required)).await;
+
+    let mut header = b"\r\n\r\n\0\r\nQUIT\n\x21\x21\x00\x24".to_vec();
+    header.extend([0x20, 0x01, 0x0d, 0xb8].iter().chain(&[0; 11]).chain(&[1]));
+    header.extend([0x20, 0x01, 0x0d, 0xb8].iter().chain(&[0; 11]).chain(&[2]));
+    header.extend(4000u16.to_be_bytes());
+    header.extend(80u16.to_be_bytes());
+
+    let rsp = raw_request(addr, &header, "/client-addr").await;
+    assert!(rsp.ends_with("client_addr: 2001:db8::1"), "{rsp}");
+
+    let rsp = raw_request(addr, &header, "/local-addr").await;
+    assert!(rsp.ends_with("local_addr: [2001:db8::2]:80"), "{rsp}");
+
+    // Without a PROXY protocol header, the connection is rejected
+    let rsp = raw_request(addr, b"", "/client-addr").await;
+    assert_eq!(rsp, "");
+
+    runner.stop();
+}
+
+#[tokio::test]
+async fn test_proxy_protocol_optional() {
+    let addr = "127.0.0.1:12348".parse::<SocketAddr>().unwrap();
+    let server = Server::bind(addr, App::default()).await.unwrap();
+    let runner = ServerRunner::spawn(server.proxy_protocol(ProxyProtocol::Optional)).await;
+
+    let header = b"PROXY TCP6 2001:db8::1 2001:db8::2 4000 80\r\n";
+    let rsp = raw_request(addr, header, "/client-addr").await;
+    assert!(rsp.ends_with("client_addr: 2001:db8::1"), "{rsp}");
+
+    let rsp = reqwest::get(format!("http://{addr}/client-addr"))
+        .await
+        .unwrap();
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(rsp.text().await.unwrap(), "client_addr: 127.0.0.1");
+
+    runner.stop();
+}
+
+#[tokio::test]
+async fn test_proxy_header_timeout() {
+    let addr = "127.0.0.1:12362".parse::<SocketAddr>().unwrap();
+    let server = Server::bind(addr, App::default()).await.unwrap();
+    let server = server
+        .proxy_protocol(ProxyProtocol::Required)
+        .proxy_header_timeout(Duration::from_millis(50));
+    let runner = ServerRunner::spawn(server).await;
+
+    // Send an incomplete header and wait for the server to close the connection
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"PROXY TCP4 ").await.unwrap();
+    let start = Instant::now();
+    let mut buf = Vec::new();
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut buf)).await;
+    assert_eq!(read.unwrap().unwrap(), 0);
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    runner.stop();
+}
+
+#[tokio::test]
+async fn test_trusted_proxies() {
+    let addr = "127.0.0.1:12349".parse::<SocketAddr>().unwrap();
+    let trusted = ["127.0.0.0/8".parse().unwrap()].into_iter().collect();
+    let server = Server::bind(addr, App::default()).await.unwrap();
+    let runner = ServerRunner::spawn(server.trusted_proxies(trusted)).await;
+
+    let client = reqwest::Client::new();
+    let rsp = client
+        .get(format!("http://{addr}/forwarded"))
+        .header("x-forwarded-for", "192.0.2.1, 198.51.100.7")
+        .header("x-forwarded-proto", "https")
+        .header("x-forwarded-host", "example.com")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(rsp.text().await.unwrap(), "198.51.100.7 https example.com");
+
+    let rsp = reqwest::get(format!("http://{addr}/forwarded"))
+        .await
+        .unwrap();
+    assert_eq!(rsp.text().await.unwrap(), format!("127.0.0.1 http {addr}"));
+
+    runner.stop();
+}
+
+#[tokio::test]
+async fn test_untrusted_proxy() {
+    let addr = "127.0.0.1:12350".parse::<SocketAddr>().unwrap();
+    let runner = ServerRunner::run(addr).await;
+
+    let client = reqwest::Client::new();
+    let rsp = client
+        .get(format!("http://{addr}/forwarded"))
+        .header("forwarded", "for=192.0.2.1;proto=https")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rsp.text().await.unwrap(), format!("127.0.0.1 http {addr}"));
+
+    runner.stop();
+}
+
+#[tokio::test]
+async fn test_multiple_listeners() {
+    let public = "127.0.0.1:12351".parse::<SocketAddr>().unwrap();
+    let admin = "127.0.0.1:12352".parse::<SocketAddr>().unwrap();
+    let (tx, rx) = oneshot::channel::<()>();
+    let server = Server::bind(public, App::default())
+        .await
+        .unwrap()
+        .with_listener(TcpListener::bind(admin).await.unwrap())
+        .with_graceful_shutdown(async move {
+            rx.await.ok();
+        });
+    let handle = tokio::spawn(server.serve());
+    sleep(Duration::from_millis(10)).await;
+
+    for (index, addr) in [public, admin].into_iter().enumerate() {
+        let rsp = reqwest::get(format!("http://{addr}/listener"))
+            .await
+            .unwrap();
+        assert_eq!(rsp.status(), StatusCode::OK);
+        assert_eq!(
+            rsp.text().await.unwrap(),
+            format!("listener {index}: {addr}")
+        );
+    }
+
+    tx.send(()).unwrap();
+    handle.await.unwrap().unwrap();
+    for addr in [public, admin] {
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+}
+
+#[tokio::test]
+async fn test_shutdown_handle() {
+    let addr = "127.0.0.1:12353".parse::<SocketAddr>().unwrap();
+    let server = Server::bind(addr, App::default()).await.unwrap();
+    let shutdown = server.shutdown_handle();
+    let handle = tokio::spawn(server.serve());
+    sleep(Duration::from_millis(10)).await;
+
+    // A handler waiting for the shutdown signal finishes when the server shuts down
+    let request = tokio::spawn(reqwest::get(format!("http://{addr}/wait-for-shutdown")));
+    sleep(Duration::from_millis(50)).await;
+    shutdown.shutdown();
+
+    let rsp = request.await.unwrap().unwrap();
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(rsp.text().await.unwrap(), "shutting down");
+    handle.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_drain_timeout() {
+    let addr = "127.0.0.1:12354".parse::<SocketAddr>().unwrap();
+    let server = Server::bind(addr, App::default())
+        .await
+        .unwrap()
+        .drain_timeout(Duration::from_millis(100));
+    let shutdown = server.shutdown_handle();
+    let handle = tokio::spawn(server.serve());
+    sleep(Duration::from_millis(10)).await;
+
+    let request = tokio::spawn(reqwest::get(format!("http://{addr}/hang")));
+    sleep(Duration::from_millis(50)).await;
+
+    let start = Instant::now();
+    shutdown.shutdown();
+    handle.await.unwrap().unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert!(request.await.unwrap().is_err());
+}
+
+#[tokio::test]
+async fn test_panic_response() {
+    let addr = "127.0.0.1:12355".parse::<SocketAddr>().unwrap();
+    let runner = ServerRunner::run(addr).await;
+
+    let rsp = reqwest::get(format!("http://{addr}/panic")).await.unwrap();
+    assert_eq!(rsp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(
+        rsp.text().await.unwrap(),
+        "{\"error\": \"handler panicked: oops\", \"path\": \"/panic\", \"route\": \"panic\"}"
+    );
+
+    let rsp = reqwest::get(format!("http://{addr}/panic-default"))
+        .await
+        .unwrap();
+    assert_eq!(rsp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(rsp.text().await.unwrap(), "Caught panic");
+
+    let rsp = reqwest::Client::new()
+        .get(format!("http://{addr}/panic"))
+        .header("accept", "text/plain")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rsp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(rsp.text().await.unwrap(), "handler panicked: oops");
+
+    runner.stop();
+}
+
+#[cfg(feature = "metrics")]
+#[tokio::test]
+async fn test_metrics() {
+    use mendes::hyper::Metrics;
+
+    let addr = "127.0.0.1:12356".parse::<SocketAddr>().unwrap();
+    let metrics = Arc::new(Metrics::new());
+    let server = Server::bind(addr, App::default()).await.unwrap();
+    let runner = ServerRunner::spawn(server.metrics(metrics.clone())).await;
+
+    for path in ["client-addr", "client-addr", "missing"] {
+        let rsp = reqwest::get(format!("http://{addr}/{path}")).await.unwrap();
+        rsp.text().await.unwrap();
+    }
+
+    let client = reqwest::Client::new();
+    for method in ["FOO1", "FOO2"] {
+        let method = reqwest::Method::from_bytes(method.as_bytes()).unwrap();
+        let rsp = client
+            .request(method, format!("http://{addr}/missing"))
+            .send()
+            .await
+            .unwrap();
+        rsp.text().await.unwrap();
+    }
+
+    sleep(Duration::from_millis(10)).await;
+    let rendered = metrics.render();
+    let labels = "method=\"GET\",status=\"200\",route=\"client_addr\"";
+    assert!(rendered.contains(&format!("mendes_requests_total{{{labels}}} 2\n")));
+    assert!(rendered.contains(&format!(
+        "mendes_request_duration_seconds_count{{{labels}}} 2\n"
+    )));
+    assert!(rendered.contains(&format!("mendes_response_bytes_total{{{labels}}} 44\n")));
+    assert!(
+        rendered.contains("mendes_requests_total{method=\"GET\",status=\"404\",route=\"\"} 1\n")
+    );
+    assert!(
+        rendered.contains("mendes_requests_total{method=\"OTHER\",status=\"404\",route=\"\"} 2\n")
+    );
+    assert!(!rendered.contains("FOO"));
+    assert!(rendered.contains("# TYPE mendes_request_duration_seconds histogram\n"));
+    assert!(rendered.contains("mendes_connections_opened_total "));
+
+    runner.stop();
+}
+
+#[tokio::test]
+async fn test_access_log() {
+    use mendes::http::header::{HeaderName, COOKIE, REFERER, USER_AGENT};
+    use mendes::hyper::{AccessLog, LogFormat};
+
+    let (output, _guard) = capture_logs();
+
+    let addr = "127.0.0.1:12357".parse::<SocketAddr>().unwrap();
+    let log = AccessLog::new(LogFormat::Combined)
+        .header(COOKIE)
+        .header(HeaderName::from_static("x-custom"));
+    let server = Server::bind(addr, App::default()).await.unwrap();
+    let runner = ServerRunner::spawn(server.access_log(log)).await;
+
+    let rsp = reqwest::Client::new()
+        .get(format!("http://{addr}/client-addr?foo=bar"))
+        .header(COOKIE, "session=secret")
+        .header(USER_AGENT, "test \"agent\"")
+        .header(REFERER, "http://example.com/")
+        .send()
+        .await
+        .unwrap();
+    rsp.text().await.unwrap();
+    sleep(Duration::from_millis(10)).await;
+
+    let output = output.contents();
+    let line = output
+        .lines()
+        .find(|line| line.contains("mendes::access"))
+        .unwrap();
+    assert!(line.starts_with(" INFO mendes::access: 127.0.0.1 - - ["));
+    assert!(line.ends_with(concat!(
+        "] \"GET /client-addr?foo=bar HTTP/1.1\" 200 22 \"http://example.com/\" ",
+        "\"test \\\"agent\\\"\" \"[redacted]\" \"-\""
+    )));
+    assert!(!output.contains("secret"));
+
+    runner.stop();
+}
+
+#[tokio::test]
+async fn test_request_id() {
+    let (output, _guard) = capture_logs();
+    let addr = "127.0.0.1:12358".parse::<SocketAddr>().unwrap();
+    let runner = ServerRunner::run(addr).await;
+
+    let rsp = reqwest::Client::new()
+        .get(format!("http://{addr}/request-id"))
+        .header("x-request-id", "abc-123")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rsp.headers()["x-request-id"], "abc-123");
+    assert_eq!(rsp.text().await.unwrap(), "request_id: abc-123");
+    let output = output.contents();
+    let line = output.lines().find(|line| line.contains("in handler"));
+    assert!(line
+        .unwrap()
+        .contains("request{id=abc-123 method=GET path=\"/request-id\"}"));
+
+    let rsp = reqwest::Client::new()
+        .get(format!("http://{addr}/request-id"))
+        .header("x-request-id", "not valid")
+        .send()
+        .await
+        .unwrap();
+    let id = rsp.headers()["x-request-id"].to_str().unwrap().to_owned();
+    assert_eq!(id.len(), 32);
+    assert_eq!(rsp.text().await.unwrap(), format!("request_id: {id}"));
+
+    // A request ID set by the handler is left alone
+    let rsp = reqwest::Client::new()
+        .get(format!("http://{addr}/request-id?override"))
+        .header("x-request-id", "abc-123")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rsp.headers()["x-request-id"], "from-handler");
+
+    runner.stop();
+}
+
+#[cfg(feature = "tower")]
+#[tokio::test]
+async fn test_tower_layer() {
+    use mendes::http::{HeaderValue, Request};
+    use tower::ServiceBuilder;
+
+    let addr = "127.0.0.1:12359".parse::<SocketAddr>().unwrap();
+    let layer = ServiceBuilder::new()
+        .map_request(|req: Request<Incoming>| {
+            // The server's extensions are available to the layers
+            assert!(req.extensions().get::<ClientAddr>().is_some());
+            req
+        })
+        .map_response(|mut rsp: Response<Body>| {
+            let value = HeaderValue::from_static("yes");
+            rsp.headers_mut().insert("x-layered", value);
+            rsp
+        });
+    let server = Server::bind(addr, App::default()).await.unwrap();
+    let handle = tokio::spawn(server.layer(layer).serve());
+    sleep(Duration::from_millis(10)).await;
+
+    let rsp = reqwest::get(format!("http://{addr}/client-addr"))
+        .await
+        .unwrap();
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(rsp.headers()["x-layered"], "yes");
+    assert!(rsp.headers().contains_key("x-request-id"));
+    assert_eq!(rsp.text().await.unwrap(), "client_addr: 127.0.0.1");
+
+    // Panics in the application are still turned into responses
+    let rsp = reqwest::get(format!("http://{addr}/panic")).await.unwrap();
+    assert_eq!(rsp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(rsp.headers()["x-layered"], "yes");
+
+    handle.abort();
+}
+
+/// Capture log output for the current thread
+fn capture_logs() -> (LogOutput, tracing::subscriber::DefaultGuard) {
+    let output = LogOutput::default();
+    let writer = output.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .without_time()
+        .finish();
+    (output, tracing::subscriber::set_default(subscriber))
+}
+
+#[derive(Clone, Default)]
+struct LogOutput(Arc<Mutex<Vec<u8>>>);
+
+impl LogOutput {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl io::Write for LogOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "body-util")]
+#[tokio::test]
+async fn test_expect_continue() {
+    let addr = "127.0.0.1:12361".parse::<SocketAddr>().unwrap();
+    let _server = ServerRunner::run(addr).await;
+
+    // The application rejects the request before the body is sent
+    let head = "POST /upload HTTP/1.1\r\nContent-Length: 1000000\r\nExpect: 100-continue\r\n";
+    let rsp = expect_continue(addr, head).await;
+    assert!(rsp.starts_with("HTTP/1.1 401 Unauthorized\r\n"), "{rsp}");
+
+    // The body extractor rejects the request based on the Content-Length
+    let head = format!("{head}Authorization: secret\r\n");
+    let rsp = expect_continue(addr, &head).await;
+    assert!(
+        rsp.starts_with("HTTP/1.1 413 Payload Too Large\r\n"),
+        "{rsp}"
+    );
+
+    // The body extractor rejects the request based on the content type
+    let head = "POST /upload-json HTTP/1.1\r\nContent-Type: text/plain\r\n\
+        Content-Length: 2\r\nExpect: 100-continue\r\nAuthorization: secret\r\n";
+    let rsp = expect_continue(addr, head).await;
+    assert!(
+        rsp.starts_with("HTTP/1.1 415 Unsupported Media Type\r\n"),
+        "{rsp}"
+    );
+
+    // Accepted requests get a `100 Continue` before the final response
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let head = "POST /upload HTTP/1.1\r\nContent-Length: 5\r\nExpect: 100-continue\r\n\
+        Authorization: secret\r\nConnection: close\r\n\r\n";
+    stream.write_all(head.as_bytes()).await.unwrap();
+    let mut buf = [0; 64];
+    let len = stream.read(&mut buf).await.unwrap();
+    assert_eq!(&buf[..len], b"HTTP/1.1 100 Continue\r\n\r\n");
+    stream.write_all(b"hello").await.unwrap();
+    let mut rsp = String::new();
+    stream.read_to_string(&mut rsp).await.unwrap();
+    assert!(rsp.starts_with("HTTP/1.1 200 OK\r\n"), "{rsp}");
+    assert!(rsp.ends_with("\r\n\r\nuploaded 5 bytes"), "{rsp}");
+}
+
+/// Send a request head with `Expect: 100-continue` and read the response without sending a body
+#[cfg(feature = "body-util")]
+async fn expect_continue(addr: SocketAddr, head: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(format!("{head}Host: {addr}\r\n\r\n").as_bytes())
+        .await
+        .unwrap();
+
+    let mut buf = [0; 1024];
+    let len = tokio::time::timeout(Duration::from_secs(1), stream.read(&mut buf))
+        .await
+        .expect("no response before the body was sent")
+        .unwrap();
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+async fn raw_request(addr: SocketAddr, header: &[u8], path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let mut request = header.to_vec();
+    request.extend(
+        format!("GET {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n").bytes(),
+    );
+    stream.write_all(&request).await.unwrap();
+
+    let mut rsp = Vec::new();
+    let _ = stream.read_to_end(&mut rsp).await;
+    String::from_utf8(rsp).unwrap()
+}
+
 #[derive(Default)]
 struct App {}
 
@@ -66,8 +581,58 @@ impl Application for App {
     async fn handle(mut cx: Context<Self>) -> Response<Self::ResponseBody> {
         route!(match cx.path() {
             Some("client-addr") => client_addr,
+            Some("local-addr") => local_addr,
+            Some("forwarded") => forwarded,
+            Some("listener") => listener,
+            Some("wait-for-shutdown") => wait_for_shutdown,
+            Some("hang") => hang,
+            Some("panic") => panic,
+            Some("panic-default") => panic,
+            Some("request-id") => request_id,
+            #[cfg(feature = "body-util")]
+            Some("upload") => upload,
+            #[cfg(feature = "body-util")]
+            Some("upload-json") => upload_json,
         })
     }
+
+    fn expect_continue(&self, req: &Parts) -> Result<(), Error> {
+        match req.headers.contains_key("authorization") {
+            true => Ok(()),
+            false => Err(Error::Unauthorized),
+        }
+    }
+
+    fn panic_response(&self, panic: &(dyn Any + Send), req: &Parts) -> Option<Response<Body>> {
+        if req.uri.path() == "/panic-default" {
+            return None;
+        }
+
+        let msg = panic.downcast_ref::<&str>().unwrap();
+        if req
+            .headers
+            .get(ACCEPT)
+            .is_some_and(|accept| accept == "text/plain")
+        {
+            return Some(
+                Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from(format!("handler panicked: {msg}")))
+                    .unwrap(),
+            );
+        }
+
+        Some(
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(format!(
+                    "{{\"error\": \"handler panicked: {msg}\", \"path\": \"{}\", \"route\": \"{}\"}}",
+                    req.uri.path(),
+                    req.extensions.get::<Route>().map(|route| route.0).unwrap_or_default(),
+                )))
+                .unwrap(),
+        )
+    }
 }
 
 #[handler(GET)]
@@ -81,9 +646,91 @@ async fn client_addr(_: &App, client_addr: ClientAddr) -> Result<Response<Body>,
         .unwrap())
 }
 
+#[handler(GET)]
+async fn local_addr(_: &App, local_addr: LocalAddr) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(format!("local_addr: {}", *local_addr)))
+        .unwrap())
+}
+
+#[handler(GET)]
+async fn forwarded(_: &App, forwarded: Forwarded) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(format!(
+            "{} {} {}",
+            forwarded.ip(),
+            forwarded.scheme(),
+            forwarded.host().unwrap_or_default()
+        )))
+        .unwrap())
+}
+
+#[handler(GET)]
+async fn listener(_: &App, listener: Listener) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(format!(
+            "listener {}: {}",
+            listener.index(),
+            listener.local_addr()
+        )))
+        .unwrap())
+}
+
+#[handler(GET)]
+async fn wait_for_shutdown(_: &App, mut signal: ShutdownSignal) -> Result<Response<Body>, Error> {
+    signal.wait().await;
+    assert!(signal.is_shutting_down());
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from("shutting down"))
+        .unwrap())
+}
+
+#[handler(GET)]
+async fn hang(_: &App) -> Result<Response<Body>, Error> {
+    std::future::pending().await
+}
+
+#[handler(GET)]
+async fn panic(_: &App) -> Result<Response<Body>, Error> {
+    panic!("oops")
+}
+
+#[handler(GET)]
+async fn request_id(_: &App, req: &Parts, id: RequestId) -> Result<Response<Body>, Error> {
+    tracing::info!("in handler");
+    let mut rsp = Response::builder().status(StatusCode::OK);
+    if req.uri.query() == Some("override") {
+        rsp = rsp.header("x-request-id", "from-handler");
+    }
+
+    Ok(rsp.body(Body::from(format!("request_id: {id}"))).unwrap())
+}
+
+#[cfg(feature = "body-util")]
+#[handler(POST)]
+async fn upload(_: &App, req: &Parts, body: Incoming) -> Result<Response<Body>, Error> {
+    let body = App::body_bytes_decoded(req, body, 16).await?;
+    Ok(Response::new(Body::from(format!(
+        "uploaded {} bytes",
+        body.len()
+    ))))
+}
+
+#[cfg(feature = "body-util")]
+#[handler(POST)]
+async fn upload_json(_: &App, req: &Parts, body: Incoming) -> Result<Response<Body>, Error> {
+    let value = App::from_body::<Vec<u8>>(req, body, 16).await?;
+    Ok(Response::new(Body::from(format!("{value:?}"))))
+}
+
 #[derive(Debug)]
 enum Error {
     Mendes(mendes::Error),
+    Unauthorized,
 }
 
 impl std::error::Error for Error {}
@@ -92,6 +739,7 @@ impl Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::Mendes(err) => err.fmt(formatter),
+            Error::Unauthorized => formatter.write_str("unauthorized"),
         }
     }
 }
@@ -104,17 +752,18 @@ impl From<mendes::Error> for Error {
 
 impl From<&Error> for StatusCode {
     fn from(e: &Error) -> StatusCode {
-        let Error::Mendes(e) = e;
-        StatusCode::from(e)
+        match e {
+            Error::Mendes(e) => StatusCode::from(e),
+            Error::Unauthorized => StatusCode::UNAUTHORIZED,
+        }
     }
 }
 
 impl IntoResponse<App> for Error {
     fn into_response(self, _: &App, _: &Parts) -> Response<Body> {
-        let Error::Mendes(err) = self;
         Response::builder()
-            .status(StatusCode::from(&err))
-            .body(Body::from(Bytes::from(err.to_string())))
+            .status(StatusCode::from(&self))
+            .body(Body::from(Bytes::from(self.to_string())))
             .unwrap()
     }
 }
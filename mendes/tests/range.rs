@@ -0,0 +1,129 @@
+#![cfg(all(feature = "application", feature = "body-util"))]
+
+use http::header::{
+    ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, LAST_MODIFIED,
+    RANGE,
+};
+use http_body_util::BodyExt;
+
+use mendes::body::RangeResponse;
+use mendes::http::request::Parts;
+use mendes::http::{Method, Request, Response, StatusCode};
+use mendes::Body;
+
+#[tokio::test]
+async fn test_single_range() {
+    let rsp = response().ranged(&request(Method::GET, &[(RANGE, "bytes=4-8")]));
+    assert_eq!(rsp.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(rsp.headers()[ACCEPT_RANGES], "bytes");
+    assert_eq!(rsp.headers()[CONTENT_RANGE], "bytes 4-8/26");
+    assert_eq!(rsp.headers()[CONTENT_LENGTH], "5");
+    assert_eq!(rsp.headers()[CONTENT_TYPE], "text/plain");
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "efghi");
+
+    let rsp = response().ranged(&request(Method::GET, &[(RANGE, "bytes=-3")]));
+    assert_eq!(rsp.headers()[CONTENT_RANGE], "bytes 23-25/26");
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "xyz");
+}
+
+#[tokio::test]
+async fn test_multiple_ranges() {
+    let rsp = response().ranged(&request(Method::GET, &[(RANGE, "bytes=0-1, 24-")]));
+    assert_eq!(rsp.status(), StatusCode::PARTIAL_CONTENT);
+    assert!(!rsp.headers().contains_key(CONTENT_RANGE));
+    let content_type = rsp.headers()[CONTENT_TYPE].to_str().unwrap().to_owned();
+    let boundary = content_type
+        .strip_prefix("multipart/byteranges; boundary=")
+        .unwrap();
+    let len = rsp.headers()[CONTENT_LENGTH]
+        .to_str()
+        .unwrap()
+        .parse::<usize>();
+
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(len.unwrap(), body.len());
+    assert_eq!(
+        String::from_utf8_lossy(&body),
+        format!(
+            "--{boundary}\r\n\
+             Content-Type: text/plain\r\n\
+             Content-Range: bytes 0-1/26\r\n\r\n\
+             ab\r\n\
+             --{boundary}\r\n\
+             Content-Type: text/plain\r\n\
+             Content-Range: bytes 24-25/26\r\n\r\n\
+             yz\r\n\
+             --{boundary}--\r\n"
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_unsatisfiable() {
+    let rsp = response().ranged(&request(Method::GET, &[(RANGE, "bytes=26-")]));
+    assert_eq!(rsp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(rsp.headers()[CONTENT_RANGE], "bytes */26");
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert!(body.is_empty());
+}
+
+#[tokio::test]
+async fn test_full() {
+    // Malformed ranges are ignored
+    let rsp = response().ranged(&request(Method::GET, &[(RANGE, "bytes=8-4")]));
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(rsp.headers()[ACCEPT_RANGES], "bytes");
+
+    // Only GET requests are served partially
+    let rsp = response().ranged(&request(Method::POST, &[(RANGE, "bytes=0-1")]));
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert!(!rsp.headers().contains_key(ACCEPT_RANGES));
+
+    // Streaming bodies don't support ranges
+    let rsp = Response::new(Body::lazy(async { Ok("abc".into()) }))
+        .ranged(&request(Method::GET, &[(RANGE, "bytes=0-1")]));
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert!(!rsp.headers().contains_key(ACCEPT_RANGES));
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "abc");
+}
+
+#[tokio::test]
+async fn test_if_range() {
+    for (if_range, status) in [
+        ("\"abc\"", StatusCode::PARTIAL_CONTENT),
+        ("\"def\"", StatusCode::OK),
+        ("W/\"abc\"", StatusCode::OK),
+        ("Wed, 21 Oct 2015 07:28:00 GMT", StatusCode::PARTIAL_CONTENT),
+        ("Thu, 22 Oct 2015 07:28:00 GMT", StatusCode::OK),
+    ] {
+        let mut rsp = response();
+        let headers = rsp.headers_mut();
+        headers.insert(ETAG, "\"abc\"".parse().unwrap());
+        headers.insert(
+            LAST_MODIFIED,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+
+        let req = request(Method::GET, &[(RANGE, "bytes=0-1"), (IF_RANGE, if_range)]);
+        assert_eq!(rsp.ranged(&req).status(), status, "{if_range}");
+    }
+}
+
+fn response() -> Response<Body> {
+    Response::builder()
+        .header(CONTENT_TYPE, "text/plain")
+        .body(Body::from("abcdefghijklmnopqrstuvwxyz"))
+        .unwrap()
+}
+
+fn request(method: Method, headers: &[(http::HeaderName, &str)]) -> Parts {
+    let mut builder = Request::builder().method(method).uri("/");
+    for (name, value) in headers {
+        builder = builder.header(name, *value);
+    }
+
+    builder.body(()).unwrap().into_parts().0
+}
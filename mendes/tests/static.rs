@@ -0,0 +1,476 @@
+#![cfg(all(feature = "static", feature = "body-util"))]
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{env, fs, process};
+
+use async_trait::async_trait;
+use http::header::{
+    ACCEPT, ACCEPT_RANGES, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+    IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, LOCATION, RANGE,
+};
+use http_body::Body as _;
+use http_body_util::BodyExt;
+
+use mendes::application::IntoResponse;
+use mendes::http::request::Parts;
+use mendes::http::{Method, Request, Response, StatusCode};
+use mendes::utils::{serve_file, StaticDir};
+use mendes::{route, Application, Body, Context};
+
+#[tokio::test]
+async fn test_text_file() {
+    let dir = TempDir::new("text");
+    fs::write(dir.path().join("hello.txt"), "hello world").unwrap();
+
+    let rsp = serve_file(&request(Method::GET), dir.path().join("hello.txt"))
+        .await
+        .unwrap();
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(rsp.headers()[CONTENT_TYPE], "text/plain; charset=utf-8");
+    assert_eq!(rsp.headers()[CONTENT_LENGTH], "11");
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "hello world");
+}
+
+#[tokio::test]
+async fn test_binary_file_streamed() {
+    let dir = TempDir::new("binary");
+    let data = (0..200_000).map(|i| i as u8).collect::<Vec<_>>();
+    fs::write(dir.path().join("data.png"), &data).unwrap();
+
+    let rsp = serve_file(&request(Method::GET), dir.path().join("data.png"))
+        .await
+        .unwrap();
+    assert_eq!(rsp.headers()[CONTENT_TYPE], "image/png");
+    assert_eq!(rsp.headers()[CONTENT_LENGTH], "200000");
+
+    let mut body = rsp.into_body();
+    assert_eq!(body.size_hint().exact(), Some(200_000));
+    let (mut frames, mut received) = (0, Vec::new());
+    while let Some(frame) = body.frame().await {
+        received.extend_from_slice(&frame.unwrap().into_data().unwrap());
+        frames += 1;
+    }
+
+    assert!(frames > 1);
+    assert_eq!(received, data);
+}
+
+#[tokio::test]
+async fn test_head() {
+    let dir = TempDir::new("head");
+    fs::write(dir.path().join("app.js"), "console.log(1);").unwrap();
+
+    let rsp = serve_file(&request(Method::HEAD), dir.path().join("app.js"))
+        .await
+        .unwrap();
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(rsp.headers()[CONTENT_LENGTH], "15");
+    assert_eq!(rsp.headers()[ACCEPT_RANGES], "bytes");
+    assert!(rsp.headers()[CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .ends_with("; charset=utf-8"));
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert!(body.is_empty());
+}
+
+#[tokio::test]
+async fn test_ranges() {
+    let dir = TempDir::new("ranges");
+    let data = (0..200_000).map(|i| i as u8).collect::<Vec<_>>();
+    fs::write(dir.path().join("data.bin"), &data).unwrap();
+
+    let req = request_with_range("bytes=100000-100009");
+    let rsp = serve_file(&req, dir.path().join("data.bin")).await.unwrap();
+    assert_eq!(rsp.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(rsp.headers()[CONTENT_RANGE], "bytes 100000-100009/200000");
+    assert_eq!(rsp.headers()[CONTENT_LENGTH], "10");
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, data[100_000..100_010]);
+
+    // Multiple ranges, spanning several chunks
+    let req = request_with_range("bytes=150000-,0-99999");
+    let rsp = serve_file(&req, dir.path().join("data.bin")).await.unwrap();
+    assert_eq!(rsp.status(), StatusCode::PARTIAL_CONTENT);
+    let len = rsp.headers()[CONTENT_LENGTH]
+        .to_str()
+        .unwrap()
+        .parse::<usize>();
+    let content_type = rsp.headers()[CONTENT_TYPE].to_str().unwrap();
+    let boundary = content_type.split_once("boundary=").unwrap().1;
+    let trailer = format!("\r\n--{boundary}--\r\n").len();
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body.len(), len.unwrap());
+    let first = body
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .unwrap();
+    assert_eq!(body[first + 4..first + 100_004], data[..100_000]);
+    assert_eq!(
+        body[body.len() - 50_000 - trailer..body.len() - trailer],
+        data[150_000..]
+    );
+
+    let req = request_with_range("bytes=200000-");
+    let rsp = serve_file(&req, dir.path().join("data.bin")).await.unwrap();
+    assert_eq!(rsp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(rsp.headers()[CONTENT_RANGE], "bytes */200000");
+}
+
+#[tokio::test]
+async fn test_validators() {
+    let dir = TempDir::new("validators");
+    fs::write(dir.path().join("style.css"), "body {}").unwrap();
+
+    let rsp = serve_file(&request(Method::GET), dir.path().join("style.css"))
+        .await
+        .unwrap();
+    let etag = rsp.headers()[ETAG].clone();
+    let last_modified = rsp.headers()[LAST_MODIFIED].clone();
+    assert!(etag.to_str().unwrap().starts_with('"'));
+
+    let mut req = request(Method::GET);
+    req.headers.insert(IF_NONE_MATCH, etag.clone());
+    let rsp = serve_file(&req, dir.path().join("style.css"))
+        .await
+        .unwrap();
+    assert_eq!(rsp.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(rsp.headers()[ETAG], etag);
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert!(body.is_empty());
+
+    let mut req = request(Method::GET);
+    req.headers.insert(IF_MODIFIED_SINCE, last_modified);
+    let rsp = serve_file(&req, dir.path().join("style.css"))
+        .await
+        .unwrap();
+    assert_eq!(rsp.status(), StatusCode::NOT_MODIFIED);
+
+    // Changing the file changes its entity tag
+    fs::write(dir.path().join("style.css"), "body { margin: 0 }").unwrap();
+    let mut req = request_with_range("bytes=0-3");
+    req.headers.insert(IF_NONE_MATCH, etag.clone());
+    req.headers.insert(IF_RANGE, etag.clone());
+    let rsp = serve_file(&req, dir.path().join("style.css"))
+        .await
+        .unwrap();
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_ne!(rsp.headers()[ETAG], etag);
+
+    let etag = rsp.headers()[ETAG].clone();
+    req.headers.remove(IF_NONE_MATCH);
+    req.headers.insert(IF_RANGE, etag);
+    let rsp = serve_file(&req, dir.path().join("style.css"))
+        .await
+        .unwrap();
+    assert_eq!(rsp.status(), StatusCode::PARTIAL_CONTENT);
+}
+
+#[cfg(all(feature = "brotli", feature = "gzip"))]
+#[tokio::test]
+async fn test_precompressed() {
+    use http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, VARY};
+    use mendes::body::{EncodeResponse, NoCompression};
+
+    let dir = TempDir::new("precompressed");
+    fs::write(dir.path().join("app.js"), "console.log('hello world');").unwrap();
+    fs::write(dir.path().join("app.js.br"), "brotli").unwrap();
+    fs::write(dir.path().join("app.js.gz"), "gzip").unwrap();
+    fs::write(dir.path().join("plain.js"), "console.log('hello world');").unwrap();
+
+    let get = |accept: Option<&'static str>| {
+        let mut req = request(Method::GET);
+        if let Some(accept) = accept {
+            req.headers.insert(ACCEPT_ENCODING, accept.parse().unwrap());
+        }
+
+        let path = dir.path().join("app.js");
+        async move { (serve_file(&req, path).await.unwrap(), req) }
+    };
+
+    let (rsp, req) = get(Some("gzip, br")).await;
+    assert_eq!(rsp.headers()[CONTENT_ENCODING], "br");
+    assert_eq!(rsp.headers()[VARY], "accept-encoding");
+    assert_eq!(
+        rsp.headers()[CONTENT_TYPE],
+        "text/javascript; charset=utf-8"
+    );
+    assert_eq!(rsp.headers()[CONTENT_LENGTH], "6");
+    assert!(rsp.headers()[ETAG].to_str().unwrap().ends_with("-br\""));
+    assert!(rsp.extensions().get::<NoCompression>().is_some());
+    let rsp = rsp.encoded(&req);
+    assert_eq!(rsp.headers()[CONTENT_ENCODING], "br");
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "brotli");
+
+    let (rsp, _) = get(Some("gzip, br;q=0.5")).await;
+    assert_eq!(rsp.headers()[CONTENT_ENCODING], "gzip");
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "gzip");
+
+    // The uncompressed file varies too
+    for accept in [
+        None,
+        Some("identity"),
+        Some("deflate"),
+        Some("br;q=0.5, identity"),
+    ] {
+        let (rsp, _) = get(accept).await;
+        assert!(!rsp.headers().contains_key(CONTENT_ENCODING), "{accept:?}");
+        assert_eq!(rsp.headers()[VARY], "accept-encoding", "{accept:?}");
+        let body = rsp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "console.log('hello world');", "{accept:?}");
+    }
+
+    let mut req = request(Method::GET);
+    req.headers.insert(ACCEPT_ENCODING, "br".parse().unwrap());
+    let rsp = serve_file(&req, dir.path().join("plain.js")).await.unwrap();
+    assert!(!rsp.headers().contains_key(CONTENT_ENCODING));
+    assert!(!rsp.headers().contains_key(VARY));
+}
+
+#[tokio::test]
+async fn test_directory_index() {
+    let dir = TempDir::new("index");
+    fs::write(dir.path().join("index.html"), "<h1>index</h1>").unwrap();
+
+    let rsp = serve_file(&request(Method::GET), dir.path().to_owned())
+        .await
+        .unwrap();
+    assert_eq!(rsp.headers()[CONTENT_TYPE], "text/html; charset=utf-8");
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "<h1>index</h1>");
+}
+
+#[tokio::test]
+async fn test_not_found() {
+    let dir = TempDir::new("missing");
+    let err = serve_file(&request(Method::GET), dir.path().join("missing.txt"))
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(err, mendes::Error::FileNotFound));
+    assert_eq!(StatusCode::from(&err), StatusCode::NOT_FOUND);
+
+    // A directory without an index
+    let err = serve_file(&request(Method::GET), dir.path().to_owned())
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(err, mendes::Error::FileNotFound));
+}
+
+#[tokio::test]
+async fn test_static_dir() {
+    let dir = TempDir::new("dir");
+    let root = dir.path().join("public");
+    fs::create_dir_all(root.join("assets/js")).unwrap();
+    fs::write(root.join("index.html"), "<h1>app</h1>").unwrap();
+    fs::write(root.join("assets/js/app.js"), "start();").unwrap();
+    fs::write(root.join("assets/site.css"), "body {}").unwrap();
+    fs::write(root.join("assets/a&b <c>.txt"), "escaped").unwrap();
+    fs::write(root.join(".env"), "SECRET=1").unwrap();
+    fs::write(dir.path().join("outside.txt"), "outside").unwrap();
+
+    let app = App::new(
+        StaticDir::new(&root)
+            .listing(true)
+            .fallback(true)
+            .cache_control("assets/js/**", "public, max-age=31536000, immutable")
+            .cache_control("*.html", "no-cache"),
+    );
+
+    let rsp = app.get("/static/assets/js/app.js", None).await;
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(
+        rsp.headers()[CACHE_CONTROL],
+        "public, max-age=31536000, immutable"
+    );
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "start();");
+
+    let rsp = app.get("/static/assets/site.css", None).await;
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert!(!rsp.headers().contains_key(CACHE_CONTROL));
+
+    // Directories are redirected to the path with a trailing slash
+    let rsp = app.get("/static", None).await;
+    assert_eq!(rsp.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(rsp.headers()[LOCATION], "/static/");
+    let rsp = app.get("/static/assets?sort=name", None).await;
+    assert_eq!(rsp.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(rsp.headers()[LOCATION], "/static/assets/?sort=name");
+    assert!(!rsp.headers().contains_key(CACHE_CONTROL));
+    // Leading slashes are collapsed, so the redirect doesn't point to another host
+    let rsp = app.get("//assets", None).await;
+    assert_eq!(rsp.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(rsp.headers()[LOCATION], "/assets/");
+
+    // And served through their index
+    let rsp = app.get("/static/", None).await;
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(rsp.headers()[CACHE_CONTROL], "no-cache");
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "<h1>app</h1>");
+
+    // Or a listing, if they don't have one
+    let rsp = app.get("/static/assets/", None).await;
+    assert_eq!(rsp.status(), StatusCode::OK);
+    assert_eq!(rsp.headers()[CONTENT_TYPE], "text/html; charset=utf-8");
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    let body = String::from_utf8_lossy(&body);
+    assert!(body.contains("<a href=\"/static/assets/js/\">js/</a>"));
+    assert!(body.contains("<a href=\"/static/assets/site.css\">site.css</a>"));
+    assert!(
+        body.contains("<a href=\"/static/assets/a&amp;b%20%3Cc%3E.txt\">a&amp;b &lt;c&gt;.txt</a>")
+    );
+    assert!(body.find("js/").unwrap() < body.find("site.css").unwrap());
+
+    let rsp = app.get("/static/assets/a%26b%20%3Cc%3E.txt", None).await;
+    assert_eq!(rsp.status(), StatusCode::OK);
+
+    // Traversal and hidden files
+    for path in [
+        "/static/../outside.txt",
+        "/static/%2e%2e/outside.txt",
+        "/static/assets%2f..%2f..%2foutside.txt",
+        "/static/.env",
+    ] {
+        let rsp = app.get(path, Some("text/html")).await;
+        assert_eq!(rsp.status(), StatusCode::NOT_FOUND, "{path}");
+    }
+
+    // Fallback for HTML requests
+    let rsp = app
+        .get("/static/settings/profile", Some("text/html,*/*;q=0.8"))
+        .await;
+    assert_eq!(rsp.status(), StatusCode::OK);
+    let body = rsp.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "<h1>app</h1>");
+
+    let rsp = app.get("/static/missing.js", Some("*/*")).await;
+    assert_eq!(rsp.status(), StatusCode::NOT_FOUND);
+
+    let mut req = Request::builder()
+        .method(Method::POST)
+        .uri("/static/index.html")
+        .body(Body::empty())
+        .unwrap();
+    req.headers_mut()
+        .insert(ACCEPT, "text/html".parse().unwrap());
+    let rsp = App::handle(Context::new(app.clone(), req)).await;
+    assert_eq!(rsp.status(), StatusCode::METHOD_NOT_ALLOWED);
+}
+
+#[tokio::test]
+async fn test_static_dir_defaults() {
+    let dir = TempDir::new("defaults");
+    fs::create_dir_all(dir.path().join("empty")).unwrap();
+    fs::write(dir.path().join("index.html"), "<h1>app</h1>").unwrap();
+
+    let app = App::new(StaticDir::new(dir.path()));
+    let rsp = app.get("/static/empty/", None).await;
+    assert_eq!(rsp.status(), StatusCode::NOT_FOUND);
+    let rsp = app.get("/static/missing", Some("text/html")).await;
+    assert_eq!(rsp.status(), StatusCode::NOT_FOUND);
+}
+
+struct App {
+    assets: StaticDir,
+}
+
+impl App {
+    fn new(assets: StaticDir) -> Arc<Self> {
+        Arc::new(Self { assets })
+    }
+
+    async fn get(self: &Arc<Self>, path: &str, accept: Option<&str>) -> Response<Body> {
+        let mut req = Request::builder().uri(path);
+        if let Some(accept) = accept {
+            req = req.header(ACCEPT, accept);
+        }
+
+        let req = req.body(Body::empty()).unwrap();
+        App::handle(Context::new(self.clone(), req)).await
+    }
+}
+
+#[async_trait]
+impl Application for App {
+    type RequestBody = Body;
+    type ResponseBody = Body;
+    type Error = Error;
+
+    async fn handle(mut cx: Context<Self>) -> Response<Self::ResponseBody> {
+        route!(match cx.path() {
+            Some("static") => cx.app.assets.clone(),
+            _ => cx.app.assets.clone(),
+        })
+    }
+}
+
+#[derive(Debug)]
+enum Error {
+    Mendes(mendes::Error),
+}
+
+impl From<mendes::Error> for Error {
+    fn from(e: mendes::Error) -> Self {
+        Error::Mendes(e)
+    }
+}
+
+impl From<&Error> for StatusCode {
+    fn from(e: &Error) -> StatusCode {
+        let Error::Mendes(e) = e;
+        StatusCode::from(e)
+    }
+}
+
+impl IntoResponse<App> for Error {
+    fn into_response(self, _: &App, _: &Parts) -> Response<Body> {
+        let Error::Mendes(err) = self;
+        Response::builder()
+            .status(StatusCode::from(&err))
+            .body(err.to_string().into())
+            .unwrap()
+    }
+}
+
+fn request_with_range(range: &str) -> Parts {
+    let mut req = request(Method::GET);
+    req.headers.insert(RANGE, range.parse().unwrap());
+    req
+}
+
+fn request(method: Method) -> Parts {
+    Request::builder()
+        .method(method)
+        .uri("/")
+        .body(())
+        .unwrap()
+        .into_parts()
+        .0
+}
+
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("mendes-static-{}-{name}", process::id()));
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,327 @@
+#![cfg(all(feature = "testing", feature = "body-util"))]
+
+use async_trait::async_trait;
+use mendes::application::IntoResponse;
+#[cfg(feature = "cookies")]
+use mendes::cookies::{cookie, AppWithAeadKey, AppWithCookies, Key};
+use mendes::http::header::{CONTENT_TYPE, SET_COOKIE};
+use mendes::http::request::Parts;
+use mendes::http::{Response, StatusCode};
+#[cfg(feature = "uploads")]
+use mendes::testing::Multipart;
+use mendes::testing::TestClient;
+use mendes::{handler, route, Application, Body, Context};
+use serde::{Deserialize, Serialize};
+
+#[tokio::test]
+async fn test_assertions() {
+    let mut client = TestClient::new(App::new());
+    client
+        .get("/hello")
+        .send()
+        .await
+        .assert_status(StatusCode::OK)
+        .assert_header("content-type", "text/plain")
+        .assert_text("Hello, world")
+        .assert_contains("world");
+
+    client
+        .get("/missing")
+        .send()
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_form() {
+    let mut client = TestClient::new(App::new());
+    let rsp = client
+        .post("/greet")
+        .form(&Greeting { name: "Mendes" })
+        .send()
+        .await;
+    rsp.assert_status(StatusCode::OK)
+        .assert_text("Hello, Mendes");
+}
+
+#[cfg(feature = "json")]
+#[tokio::test]
+async fn test_json() {
+    let mut client = TestClient::new(App::new());
+    let rsp = client
+        .post("/greet")
+        .json(&Greeting { name: "JSON" })
+        .send()
+        .await;
+    rsp.assert_text("Hello, JSON");
+
+    let rsp = client
+        .get("/greeting")
+        .query(&[("name", "you")])
+        .send()
+        .await;
+    rsp.assert_header("content-type", "application/json")
+        .assert_json(&serde_json::json!({ "name": "you" }));
+}
+
+#[cfg(feature = "uploads")]
+#[tokio::test]
+async fn test_multipart() {
+    let mut client = TestClient::new(App::new());
+    let form = Multipart::new().text("name", "upload").file(
+        "file",
+        "hello.txt",
+        "text/plain",
+        b"file contents",
+    );
+    let rsp = client.post("/upload").multipart(form).send().await;
+    rsp.assert_status(StatusCode::OK)
+        .assert_text("upload: hello.txt (13 bytes)");
+}
+
+#[cfg(feature = "cookies")]
+#[tokio::test]
+async fn test_cookies() {
+    let mut client = TestClient::new(App::new());
+    client
+        .get("/extract")
+        .send()
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+
+    client.get("/store").send().await.assert_text("stored");
+    assert_eq!(client.cookie::<Session>().unwrap().user, 37);
+    client.get("/extract").send().await.assert_text("user = 37");
+
+    client.get("/clear").send().await.assert_text("cleared");
+    assert!(client.cookie_value("Session").is_none());
+    client
+        .get("/extract")
+        .send()
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+
+    client.set_cookie(Session { user: 12 });
+    client.get("/extract").send().await.assert_text("user = 12");
+}
+
+#[tokio::test]
+async fn test_cookie_expiry() {
+    let mut client = TestClient::new(App::new());
+    client.get("/expiring").send().await.assert_text("set");
+    assert!(client.cookie_value("past").is_none());
+    assert_eq!(client.cookie_value("future"), Some("2"));
+    assert_eq!(client.cookie_value("invalid"), Some("3"));
+    assert_eq!(client.cookie_value("max-age"), Some("4"));
+    assert!(client.cookie_value("zero").is_none());
+    assert!(client.cookie_value("negative").is_none());
+}
+
+#[cfg(feature = "hyper")]
+#[tokio::test]
+async fn test_client_addr() {
+    let addr = "192.0.2.1:1234".parse().unwrap();
+    let mut client = TestClient::new(App::new()).client_addr(addr);
+    client
+        .get("/client_addr")
+        .send()
+        .await
+        .assert_text("client_addr: 192.0.2.1");
+}
+
+struct App {
+    #[cfg(feature = "cookies")]
+    key: Key,
+}
+
+impl App {
+    fn new() -> Self {
+        Self {
+            #[cfg(feature = "cookies")]
+            key: Key::new(&[
+                0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22,
+                23, 24, 25, 26, 27, 28, 29, 30, 31,
+            ]),
+        }
+    }
+}
+
+#[cfg(feature = "cookies")]
+impl AppWithAeadKey for App {
+    fn key(&self) -> &Key {
+        &self.key
+    }
+}
+
+#[async_trait]
+impl Application for App {
+    type RequestBody = Body;
+    type ResponseBody = Body;
+    type Error = Error;
+
+    async fn handle(mut cx: Context<Self>) -> Response<Self::ResponseBody> {
+        route!(match cx.path() {
+            Some("hello") => hello,
+            Some("greet") => greet,
+            Some("expiring") => expiring,
+            #[cfg(feature = "json")]
+            Some("greeting") => greeting,
+            #[cfg(feature = "uploads")]
+            Some("upload") => upload,
+            #[cfg(feature = "cookies")]
+            Some("store") => store,
+            #[cfg(feature = "cookies")]
+            Some("extract") => extract,
+            #[cfg(feature = "cookies")]
+            Some("clear") => clear,
+            #[cfg(feature = "hyper")]
+            Some("client_addr") => client_addr,
+        })
+    }
+}
+
+#[handler(GET)]
+async fn hello(_: &App) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, "text/plain")
+        .body("Hello, world".into())
+        .unwrap())
+}
+
+#[handler(POST)]
+async fn greet(_: &App, req: &Parts, body: Body) -> Result<Response<Body>, Error> {
+    let bytes = App::body_bytes_decoded(req, body, 1024).await?;
+    let greeting = App::from_body_bytes::<Greeting<'_>>(req, &bytes)?;
+    Ok(Response::new(format!("Hello, {}", greeting.name).into()))
+}
+
+#[handler(GET)]
+async fn expiring(_: &App) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .header(SET_COOKIE, "past=1; Expires=Sat, 01 Jan 2000 00:00:00 GMT")
+        .header(
+            SET_COOKIE,
+            "future=2; Expires=Fri, 01 Jan 2100 00:00:00 GMT",
+        )
+        .header(SET_COOKIE, "invalid=3; Expires=not a date 1970")
+        .header(
+            SET_COOKIE,
+            "max-age=4; Max-Age=60; Expires=Sat, 01 Jan 2000 00:00:00 GMT",
+        )
+        .header(SET_COOKIE, "zero=5; Max-Age=0")
+        .header(SET_COOKIE, "negative=6; Max-Age=-1")
+        .body("set".into())
+        .unwrap())
+}
+
+#[cfg(feature = "json")]
+#[handler(GET)]
+async fn greeting(_: &App, #[query] greeting: Greeting<'_>) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, mendes::types::JSON)
+        .body(serde_json::to_string(&greeting).unwrap().into())
+        .unwrap())
+}
+
+#[cfg(feature = "uploads")]
+#[handler(POST)]
+async fn upload(_: &App, req: &Parts, body: Body) -> Result<Response<Body>, Error> {
+    let bytes = App::body_bytes_decoded(req, body, 1024).await?;
+    let upload = App::from_body_bytes::<Upload<'_>>(req, &bytes)?;
+    Ok(Response::new(
+        format!(
+            "{}: {} ({} bytes)",
+            upload.name,
+            upload.file.filename.unwrap_or_default(),
+            upload.file.data.len()
+        )
+        .into(),
+    ))
+}
+
+#[cfg(feature = "cookies")]
+#[handler(GET)]
+async fn store(app: &App) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .header(
+            SET_COOKIE,
+            app.set_cookie_header(Some(Session { user: 37 })).unwrap(),
+        )
+        .body("stored".into())
+        .unwrap())
+}
+
+#[cfg(feature = "cookies")]
+#[handler(GET)]
+async fn extract(app: &App, req: &Parts) -> Result<Response<Body>, Error> {
+    match app.cookie::<Session>(&req.headers) {
+        Some(session) => Ok(Response::new(format!("user = {}", session.user).into())),
+        None => Ok(Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(Body::empty())
+            .unwrap()),
+    }
+}
+
+#[cfg(feature = "cookies")]
+#[handler(GET)]
+async fn clear(app: &App) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .header(SET_COOKIE, app.set_cookie_header::<Session>(None).unwrap())
+        .body("cleared".into())
+        .unwrap())
+}
+
+#[cfg(feature = "hyper")]
+#[handler(GET)]
+async fn client_addr(_: &App, addr: mendes::hyper::ClientAddr) -> Result<Response<Body>, Error> {
+    Ok(Response::new(format!("client_addr: {}", addr.ip()).into()))
+}
+
+#[derive(Deserialize, Serialize)]
+struct Greeting<'a> {
+    name: &'a str,
+}
+
+#[cfg(feature = "uploads")]
+#[derive(Deserialize)]
+struct Upload<'a> {
+    name: &'a str,
+    #[serde(borrow)]
+    file: mendes::forms::File<'a>,
+}
+
+#[cfg(feature = "cookies")]
+#[cookie]
+#[derive(Deserialize, Serialize)]
+struct Session {
+    user: i32,
+}
+
+#[derive(Debug)]
+enum Error {
+    Mendes(mendes::Error),
+}
+
+impl From<mendes::Error> for Error {
+    fn from(e: mendes::Error) -> Self {
+        Error::Mendes(e)
+    }
+}
+
+impl From<&Error> for StatusCode {
+    fn from(e: &Error) -> StatusCode {
+        let Error::Mendes(e) = e;
+        StatusCode::from(e)
+    }
+}
+
+impl IntoResponse<App> for Error {
+    fn into_response(self, _: &App, _: &Parts) -> Response<Body> {
+        let Error::Mendes(err) = self;
+        Response::builder()
+            .status(StatusCode::from(&err))
+            .body(err.to_string().into())
+            .unwrap()
+    }
+}